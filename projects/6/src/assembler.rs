@@ -1,50 +1,137 @@
-use crate::parser::{AddressInst, CComp, CDest, CJump, ComputationInst, Token};
-use std::collections::HashMap;
+use crate::diagnostic::Diagnostic;
+use crate::parser::{AddressInst, ComputationInst, SourceLoc, Token, MAX_CONSTANT};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 
 const VAR_START: u16 = 16;
+const SCREEN: u16 = 16384;
+
+pub(crate) const PREDEFINED: [(&str, u16); 23] = [
+    ("R0", 0),
+    ("R1", 1),
+    ("R2", 2),
+    ("R3", 3),
+    ("R4", 4),
+    ("R5", 5),
+    ("R6", 6),
+    ("R7", 7),
+    ("R8", 8),
+    ("R9", 9),
+    ("R10", 10),
+    ("R11", 11),
+    ("R12", 12),
+    ("R13", 13),
+    ("R14", 14),
+    ("R15", 15),
+    ("SCREEN", 16384),
+    ("KBD", 24576),
+    ("SP", 0),
+    ("LCL", 1),
+    ("ARG", 2),
+    ("THIS", 3),
+    ("THAT", 4),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SymbolKind {
+    Predefined,
+    Label,
+    Variable,
+}
+
+impl SymbolKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            SymbolKind::Predefined => "predefined",
+            SymbolKind::Label => "label",
+            SymbolKind::Variable => "variable",
+        }
+    }
+}
+
+/// Machine code produced by the assembler, one 16-bit word per ROM address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub instructions: Vec<u16>,
+}
+
+impl Program {
+    /// Renders the program in the `.hack` text format, one binary word per line.
+    pub fn to_hack(&self) -> String {
+        self.instructions
+            .iter()
+            .map(|inst| format!("{inst:016b}\n"))
+            .collect()
+    }
+}
 
 pub struct Assembler {
     symbols: HashMap<String, u16>,
     tokens: Vec<Token>,
+    locations: Vec<SourceLoc>,
 }
 
 impl Assembler {
     pub fn new(tokens: Vec<Token>) -> Self {
         Self {
             tokens,
-            symbols: HashMap::from([
-                ("R0".into(), 0),
-                ("R1".into(), 1),
-                ("R2".into(), 2),
-                ("R3".into(), 3),
-                ("R4".into(), 4),
-                ("R5".into(), 5),
-                ("R6".into(), 6),
-                ("R7".into(), 7),
-                ("R8".into(), 8),
-                ("R9".into(), 9),
-                ("R10".into(), 10),
-                ("R11".into(), 01),
-                ("R12".into(), 12),
-                ("R13".into(), 13),
-                ("R14".into(), 14),
-                ("R15".into(), 15),
-                ("SCREEN".into(), 16384),
-                ("KBD".into(), 24576),
-                ("SP".into(), 0),
-                ("LCL".into(), 1),
-                ("ARG".into(), 2),
-                ("THIS".into(), 3),
-                ("THAT".into(), 4),
-            ]),
+            locations: Vec::new(),
+            symbols: PREDEFINED
+                .iter()
+                .map(|&(name, addr)| (name.into(), addr))
+                .collect(),
+        }
+    }
+
+    /// Creates an assembler whose diagnostics point back at the source, as
+    /// returned by [`crate::parser::parse_located`].
+    pub fn with_locations(tokens: Vec<(Token, SourceLoc)>) -> Self {
+        let (tokens, locations) = tokens.into_iter().unzip();
+        Self {
+            locations,
+            ..Self::new(tokens)
         }
     }
 
-    pub fn resolve_symbols(&mut self) {
+    fn location(&self, token_idx: usize) -> (usize, Range<usize>) {
+        match self.locations.get(token_idx) {
+            Some(loc) => (loc.line, loc.span.clone()),
+            None => (0, 0..0),
+        }
+    }
+
+    /// Assigns addresses to labels and variables.
+    ///
+    /// Duplicate labels, labels redefining a predefined symbol, running out
+    /// of variable space and constants wider than 15 bits are reported as
+    /// errors, labels that are never referenced as warnings. The program must
+    /// not be assembled if any errors were returned.
+    pub fn resolve_symbols(&mut self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut labels: HashMap<&str, usize> = HashMap::new();
+
         let mut line_count = 0;
-        for token in &self.tokens {
+        for (i, token) in self.tokens.iter().enumerate() {
             if let Token::Label(label) = token {
-                if !self.symbols.contains_key(label) {
+                let (line, span) = self.location(i);
+                if let Some(&first) = labels.get(label.as_str()) {
+                    let mut diag =
+                        Diagnostic::error(line, span, format!("Label {label} is declared twice"));
+                    if let (first_line @ 1.., _) = self.location(first) {
+                        diag = diag.with_hint(format!("first declared on line {first_line}"));
+                    }
+                    diagnostics.push(diag);
+                } else if self.symbols.contains_key(label) {
+                    diagnostics.push(
+                        Diagnostic::error(
+                            line,
+                            span,
+                            format!("Label {label} redefines a predefined symbol"),
+                        )
+                        .with_hint("pick a name not used by R0-R15, SCREEN, KBD, SP, LCL, ARG, THIS or THAT"),
+                    );
+                } else {
+                    labels.insert(label, i);
                     self.symbols.insert(label.clone(), line_count);
                 }
             } else {
@@ -52,90 +139,105 @@ impl Assembler {
             }
         }
 
+        // the parser never produces these, but tokens can be built directly
+        for (i, token) in self.tokens.iter().enumerate() {
+            if let Token::A(AddressInst::Value(value)) = token {
+                if u32::from(*value) > MAX_CONSTANT {
+                    let (line, span) = self.location(i);
+                    diagnostics.push(
+                        Diagnostic::error(
+                            line,
+                            span,
+                            format!("Constant {value} does not fit in an A-instruction"),
+                        )
+                        .with_hint(format!(
+                            "A-instructions load values from 0 to {MAX_CONSTANT} (0x{MAX_CONSTANT:X})"
+                        )),
+                    );
+                }
+            }
+        }
+
+        let mut referenced = HashSet::new();
         let mut var_count = VAR_START;
-        for token in &self.tokens {
+        for (i, token) in self.tokens.iter().enumerate() {
             if let Token::A(AddressInst::Symbol(addr)) = token {
-                if !self.symbols.contains_key(addr) {
-                    self.symbols.insert(addr.clone(), var_count);
-                    var_count += 1;
+                referenced.insert(addr.as_str());
+                if self.symbols.contains_key(addr) {
+                    continue;
                 }
+
+                if var_count == SCREEN {
+                    let (line, span) = self.location(i);
+                    diagnostics.push(
+                        Diagnostic::error(
+                            line,
+                            span,
+                            format!("No RAM left to allocate variable {addr}"),
+                        )
+                        .with_hint(format!(
+                            "variables live in RAM[{VAR_START}..{SCREEN}], below the screen memory map"
+                        )),
+                    );
+                    break;
+                }
+
+                self.symbols.insert(addr.clone(), var_count);
+                var_count += 1;
             }
         }
+
+        let mut unused: Vec<_> = labels
+            .into_iter()
+            .filter(|(label, _)| !referenced.contains(label))
+            .map(|(_, i)| i)
+            .collect();
+        unused.sort();
+        for i in unused {
+            let Token::Label(label) = &self.tokens[i] else {
+                unreachable!()
+            };
+            let (line, span) = self.location(i);
+            diagnostics.push(Diagnostic::warning(
+                line,
+                span,
+                format!("Label {label} is never referenced"),
+            ));
+        }
+
+        diagnostics
     }
 
-    fn compile_a_instruction(&self, inst: &AddressInst) -> u16 {
+    fn compile_a_instruction(&self, inst: &AddressInst) -> Result<u16, String> {
         let addr = match inst {
             AddressInst::Value(val) => *val,
             AddressInst::Symbol(symbol) => *self.symbols.get(symbol).unwrap(),
         };
 
-        0b0111111111111111 & addr
+        if u32::from(addr) > MAX_CONSTANT {
+            return Err(format!("Constant {addr} does not fit in an A-instruction"));
+        }
+        Ok(addr)
     }
 
-    fn compile_c_instruction(&self, inst: &ComputationInst) -> u16 {
-        let dest: u16 = match inst.dest {
-            CDest::Null => 0,
-            CDest::M => 0b001,
-            CDest::D => 0b010,
-            CDest::MD => 0b011,
-            CDest::A => 0b100,
-            CDest::AM => 0b101,
-            CDest::AD => 0b110,
-            CDest::AMD => 0b111,
-        };
-
-        let jump: u16 = match inst.jump {
-            CJump::Null => 0,
-            CJump::JGT => 0b001,
-            CJump::JEQ => 0b010,
-            CJump::JGE => 0b011,
-            CJump::JLT => 0b100,
-            CJump::JNE => 0b101,
-            CJump::JLE => 0b110,
-            CJump::JMP => 0b111,
-        };
-
-        let comp: u16 = match inst.comp {
-            // a = 0
-            CComp::Zero => 0b0101010,
-            CComp::One => 0b0111111,
-            CComp::NegOne => 0b0111010,
-            CComp::D => 0b0001100,
-            CComp::A => 0b0110000,
-            CComp::NotD => 0b0001101,
-            CComp::NotA => 0b0110001,
-            CComp::NegD => 0b0001111,
-            CComp::NegA => 0b0110011,
-            CComp::DPlusOne => 0b0011111,
-            CComp::APlusOne => 0b0110111,
-            CComp::DMinusOne => 0b0001110,
-            CComp::AMinusOne => 0b0110010,
-            CComp::DPlusA => 0b0000010,
-            CComp::DMinusA => 0b0010011,
-            CComp::AMinusD => 0b0000111,
-            CComp::DAndA => 0,
-            CComp::DOrA => 0b0010101,
-            // a = 1
-            CComp::M => 0b1110000,
-            CComp::NotM => 0b1110001,
-            CComp::NegM => 0b1110011,
-            CComp::MPlusOne => 0b1110111,
-            CComp::MMinusOne => 0b1110010,
-            CComp::DPlusM => 0b1000010,
-            CComp::DMinusM => 0b1010011,
-            CComp::MMinusD => 0b1000111,
-            CComp::DAndM => 0b1000000,
-            CComp::DOrM => 0b1010101,
-        };
+    /// Panics on constants that don't fit, which `resolve_symbols` reports
+    /// as errors.
+    fn a_instruction_word(&self, inst: &AddressInst) -> u16 {
+        self.compile_a_instruction(inst)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
 
-        0b1110000000000000 | (comp << 6) | (dest << 3) | jump
+    fn compile_c_instruction(&self, inst: &ComputationInst) -> u16 {
+        inst.encode()
     }
 
-    pub fn assemble(&self) -> Vec<u16> {
+    /// Translates the tokens into machine code. `resolve_symbols` must have
+    /// been called beforehand, and returned no errors.
+    pub fn assemble(&self) -> Program {
         let mut insts = Vec::new();
         for token in &self.tokens {
             let inst = match token {
-                Token::A(a_inst) => self.compile_a_instruction(a_inst),
+                Token::A(a_inst) => self.a_instruction_word(a_inst),
                 Token::C(c_inst) => self.compile_c_instruction(c_inst),
                 _ => continue,
             };
@@ -143,13 +245,104 @@ impl Assembler {
             insts.push(inst);
         }
 
-        insts
+        Program {
+            instructions: insts,
+        }
+    }
+
+    /// Every resolved symbol with its address, labels being ROM addresses and
+    /// everything else RAM addresses. Sorted by kind, then address.
+    pub fn symbol_table(&self) -> Vec<(SymbolKind, &str, u16)> {
+        let predefined: HashSet<_> = PREDEFINED.iter().map(|&(name, _)| name).collect();
+        let labels: HashSet<_> = self
+            .tokens
+            .iter()
+            .filter_map(|token| match token {
+                Token::Label(label) => Some(label.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        let mut table: Vec<_> = self
+            .symbols
+            .iter()
+            .map(|(name, &addr)| {
+                let kind = if predefined.contains(name.as_str()) {
+                    SymbolKind::Predefined
+                } else if labels.contains(name.as_str()) {
+                    SymbolKind::Label
+                } else {
+                    SymbolKind::Variable
+                };
+                (kind, name.as_str(), addr)
+            })
+            .collect();
+
+        table.sort_by_key(|&(kind, name, addr)| (kind, addr, name));
+        table
+    }
+
+    /// The source line of every instruction, indexed by ROM address. Lines are
+    /// 0 when the tokens were created without locations.
+    pub fn instruction_lines(&self) -> Vec<usize> {
+        self.tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, token)| !matches!(token, Token::Label(_)))
+            .map(|(i, _)| self.location(i).0)
+            .collect()
+    }
+
+    /// Renders the `.sym` file: one `kind address name` entry per line.
+    pub fn symbol_map(&self) -> String {
+        let mut out = String::from("// kind address name\n");
+        for (kind, name, addr) in self.symbol_table() {
+            out += &format!("{} {addr} {name}\n", kind.name());
+        }
+        out
+    }
+
+    /// Renders the `.lst` file: every instruction's ROM address, its binary and
+    /// hexadecimal encoding and the source line it was assembled from. Label
+    /// declarations are listed without an address.
+    ///
+    /// `source` must be the text the tokens were parsed from. `resolve_symbols`
+    /// must have been called beforehand, and returned no errors.
+    pub fn listing(&self, source: &str) -> String {
+        let rows: Vec<_> = source.lines().collect();
+        let mut out = String::from("ROM    BINARY            HEX   LINE  SOURCE\n");
+
+        let mut rom_addr = 0;
+        for (i, token) in self.tokens.iter().enumerate() {
+            let (line, _) = self.location(i);
+            let text = rows.get(line.wrapping_sub(1)).map_or("", |row| row.trim());
+            let line = if line == 0 {
+                String::new()
+            } else {
+                line.to_string()
+            };
+
+            let inst = match token {
+                Token::A(a_inst) => self.a_instruction_word(a_inst),
+                Token::C(c_inst) => self.compile_c_instruction(c_inst),
+                Token::Label(_) => {
+                    out += &format!("{:30}{line:>5}  {text}\n", "");
+                    continue;
+                }
+            };
+
+            out += &format!("{rom_addr:05}  {inst:016b}  {inst:04X}  {line:>5}  {text}\n");
+            rom_addr += 1;
+        }
+
+        out
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::{CComp, CDest, CJump};
 
     #[test]
     fn c_instruction_compilation() {
@@ -167,9 +360,79 @@ mod tests {
     fn a_instruction_compilation() {
         let asm = Assembler::new(Vec::new());
         let inst1 = asm.compile_a_instruction(&AddressInst::Symbol("R1".into()));
-        assert_eq!(inst1, 0b0000000000000001);
+        assert_eq!(inst1, Ok(0b0000000000000001));
 
         let inst2 = asm.compile_a_instruction(&AddressInst::Value(0xFFFF));
-        assert_eq!(inst2, 0xFFFF >> 1);
+        assert_eq!(
+            inst2,
+            Err("Constant 65535 does not fit in an A-instruction".into())
+        );
+
+        let mut asm = Assembler::new(vec![Token::A(AddressInst::Value(0x8000))]);
+        let diagnostics = asm.resolve_symbols();
+        assert!(diagnostics[0].is_error());
+        assert_eq!(
+            diagnostics[0].message,
+            "Constant 32768 does not fit in an A-instruction"
+        );
+    }
+
+    fn resolve(asm: &str) -> (Assembler, Vec<Diagnostic>) {
+        let mut asm = Assembler::with_locations(crate::parser::parse_located(asm).unwrap());
+        let diagnostics = asm.resolve_symbols();
+        (asm, diagnostics)
+    }
+
+    #[test]
+    fn duplicate_and_predefined_labels() {
+        let (_, diagnostics) = resolve("(LOOP)\n@LOOP\n0;JMP\n(LOOP)\n(SP)\n@SP\n");
+        let errors: Vec<_> = diagnostics
+            .iter()
+            .map(|diag| (diag.line, diag.is_error()))
+            .collect();
+        assert_eq!(errors, [(4, true), (5, true)]);
+        assert_eq!(
+            diagnostics[0].hint.as_deref(),
+            Some("first declared on line 1")
+        );
+    }
+
+    #[test]
+    fn unused_labels_warn() {
+        let (asm, diagnostics) = resolve("(START)\n@END\n0;JMP\n(END)\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(!diagnostics[0].is_error());
+        assert_eq!(diagnostics[0].message, "Label START is never referenced");
+        assert_eq!(asm.symbols["END"], 2);
+    }
+
+    #[test]
+    fn variable_space_overflow() {
+        let vars: String = (VAR_START..=SCREEN).map(|i| format!("@v{i}\n")).collect();
+        let (_, diagnostics) = resolve(&vars);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, (SCREEN - VAR_START + 1) as usize);
+    }
+
+    #[test]
+    fn symbol_map_and_listing() {
+        let source = "(LOOP)\n  @i\n  M=1\n  @LOOP\n  0;JMP\n";
+        let (asm, _) = resolve(source);
+
+        let map = asm.symbol_map();
+        assert!(map.contains("\nlabel 0 LOOP\n"));
+        assert!(map.ends_with("\nvariable 16 i\n"));
+        assert!(map.contains("\npredefined 0 R0\n"));
+
+        assert_eq!(
+            asm.listing(source),
+            "ROM    BINARY            HEX   LINE  SOURCE\n\
+             \x20                                 1  (LOOP)\n\
+             00000  0000000000010000  0010      2  @i\n\
+             00001  1110111111001000  EFC8      3  M=1\n\
+             00002  0000000000000000  0000      4  @LOOP\n\
+             00003  1110101010000111  EA87      5  0;JMP\n"
+        );
+        assert_eq!(asm.instruction_lines(), [2, 3, 4, 5]);
     }
 }
@@ -0,0 +1,142 @@
+use assembler::disassembler::{self, disassemble};
+use std::env;
+use std::fs;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: disassembler [OPTIONS] <FILE>
+
+Disassembles Hack machine code to assembly, printed to stdout.
+
+Options:
+  --labels    Give the targets of jumps labels
+  --binary    Read a raw ROM image of big-endian words instead of .hack text
+  -h, --help  Print this help
+
+Exit codes:
+  0  success
+  1  the machine code contains errors or could not be read
+  2  invalid command line";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Options {
+    input: String,
+    labels: bool,
+    binary: bool,
+    help: bool,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut input = None;
+
+    for arg in args {
+        match arg.as_str() {
+            "--labels" => options.labels = true,
+            "--binary" => options.binary = true,
+            "-h" | "--help" => options.help = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {arg}")),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("Unexpected argument {arg}")),
+        }
+    }
+
+    if !options.help {
+        options.input = input.ok_or("No machine code file was provided.")?;
+    }
+    Ok(options)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{msg}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    if options.help {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    run(&options)
+}
+
+fn run(options: &Options) -> ExitCode {
+    let hack_file = &options.input;
+
+    let bytes = match fs::read(hack_file) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("Could not read {hack_file}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let words = if options.binary {
+        disassembler::read_hack_binary(&bytes).map_err(|diag| vec![diag])
+    } else {
+        disassembler::read_hack_text(&String::from_utf8_lossy(&bytes))
+    };
+
+    let words = match words {
+        Ok(words) => words,
+        Err(diagnostics) => {
+            for diag in diagnostics {
+                eprintln!("{}", diag.with_file(hack_file.as_str()));
+            }
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let disassembly = disassemble(&words, options.labels);
+    for diag in &disassembly.diagnostics {
+        eprintln!("{}", diag.clone().with_file(hack_file.as_str()));
+    }
+    print!("{}", disassembly.to_asm());
+
+    if disassembly.diagnostics.iter().any(|diag| diag.is_error()) {
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = parse(&["--binary", "Prog.hack", "--labels"]).unwrap();
+        assert_eq!(options.input, "Prog.hack");
+        assert!(options.binary && options.labels);
+
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["Prog.hack", "--label"]).is_err());
+        assert!(parse(&["Prog.hack", "Other.hack"]).is_err());
+        assert!(parse(&["--help"]).unwrap().help);
+    }
+
+    #[test]
+    fn fails_on_invalid_instructions() {
+        let path = std::env::temp_dir().join(format!("hack-disassembler-{}", std::process::id()));
+        let options = Options {
+            input: path.to_string_lossy().into_owned(),
+            ..Options::default()
+        };
+
+        fs::write(&path, "0000000000000111\n1110110000010000\n").unwrap();
+        assert_eq!(run(&options), ExitCode::SUCCESS);
+
+        // comp bits that aren't part of the instruction set
+        fs::write(&path, "1110111110000000\n").unwrap();
+        assert_eq!(run(&options), ExitCode::FAILURE);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+/// A problem found in an assembly file.
+///
+/// `line` is 1-based, `span` is the 0-based range of columns within that line
+/// the diagnostic points at. A `line` of 0 means the diagnostic is not tied to
+/// any particular line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: Option<String>,
+    pub line: usize,
+    pub span: Range<usize>,
+    pub severity: Severity,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(line: usize, span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            file: None,
+            line,
+            span,
+            severity: Severity::Error,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    pub fn warning(line: usize, span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(line, span, message)
+        }
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    pub fn with_file(mut self, file: impl Into<String>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders the diagnostic together with the offending line of `source`
+    /// and a caret underline below the span.
+    pub fn render(&self, source: &str) -> String {
+        let mut out = format!("{}: {}\n", self.severity, self.message);
+
+        let file = self.file.as_deref().unwrap_or("<input>");
+        if self.line == 0 {
+            out += &format!(" --> {file}\n");
+        } else {
+            out += &format!(" --> {file}:{}:{}\n", self.line, self.span.start + 1);
+        }
+
+        if let Some(row) = source.lines().nth(self.line.wrapping_sub(1)) {
+            let gutter = " ".repeat(self.line.to_string().len());
+            let underline = self.span.end.saturating_sub(self.span.start).max(1);
+            out += &format!("{gutter} |\n");
+            out += &format!("{} | {row}\n", self.line);
+            out += &format!(
+                "{gutter} | {}{}\n",
+                " ".repeat(self.span.start),
+                "^".repeat(underline)
+            );
+        }
+
+        if let Some(hint) = &self.hint {
+            out += &format!("  = hint: {hint}\n");
+        }
+
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let file = match &self.file {
+            Some(file) => json_string(file),
+            None => "null".into(),
+        };
+        let hint = match &self.hint {
+            Some(hint) => json_string(hint),
+            None => "null".into(),
+        };
+
+        format!(
+            "{{\"file\":{file},\"line\":{},\"column_start\":{},\"column_end\":{},\"severity\":\"{}\",\"message\":{},\"hint\":{hint}}}",
+            self.line,
+            self.span.start + 1,
+            self.span.end + 1,
+            self.severity,
+            json_string(&self.message),
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{file}:")?;
+        }
+        if self.line != 0 {
+            write!(f, "{}:{}: ", self.line, self.span.start + 1)?;
+        } else if self.file.is_some() {
+            f.write_str(" ")?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// Serializes a list of diagnostics as a JSON array.
+pub fn to_json(diagnostics: &[Diagnostic]) -> String {
+    let items: Vec<_> = diagnostics.iter().map(Diagnostic::to_json).collect();
+    format!("[{}]", items.join(","))
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\r' => out += "\\r",
+            '\t' => out += "\\t",
+            c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_underlines_span() {
+        let source = "@1\n  D=Q\n";
+        let diag = Diagnostic::error(2, 4..5, "Found invalid computation: Q")
+            .with_file("Test.asm")
+            .with_hint("valid computations are 0, 1, -1, D, A, M, ...");
+
+        assert_eq!(
+            diag.render(source),
+            "error: Found invalid computation: Q\n \
+             --> Test.asm:2:5\n  \
+              |\n\
+             2 |   D=Q\n  \
+              |     ^\n  \
+              = hint: valid computations are 0, 1, -1, D, A, M, ...\n"
+        );
+    }
+
+    #[test]
+    fn json_escapes_strings() {
+        let diag = Diagnostic::error(3, 0..4, "label \"LOOP\" is declared twice");
+        assert_eq!(
+            to_json(&[diag]),
+            "[{\"file\":null,\"line\":3,\"column_start\":1,\"column_end\":5,\"severity\":\"error\",\"message\":\"label \\\"LOOP\\\" is declared twice\",\"hint\":null}]"
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::diagnostic::Diagnostic;
+use crate::parser::{AddressInst, CJump, ComputationInst, Token};
+use std::collections::BTreeSet;
+
+/// A single ROM word turned back into assembly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decoded {
+    Token(Token),
+    /// A C-instruction whose computation bits are not part of the instruction
+    /// set, so it has no assembly form.
+    Invalid(u16),
+}
+
+pub fn decode(word: u16) -> Decoded {
+    if word & 0x8000 == 0 {
+        return Decoded::Token(Token::A(AddressInst::Value(word)));
+    }
+
+    match ComputationInst::decode(word) {
+        Some(c_inst) => Decoded::Token(Token::C(c_inst)),
+        None => Decoded::Invalid(word),
+    }
+}
+
+pub struct Disassembly {
+    /// Decoded instructions, interleaved with synthesized label declarations.
+    pub items: Vec<Decoded>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Disassembly {
+    /// Renders the disassembly as assembly source. Words that can't be
+    /// represented are kept as comments.
+    pub fn to_asm(&self) -> String {
+        let mut out = String::new();
+        for item in &self.items {
+            match item {
+                Decoded::Token(label @ Token::Label(_)) => out += &format!("{label}\n"),
+                Decoded::Token(token) => out += &format!("    {token}\n"),
+                Decoded::Invalid(word) => {
+                    out += &format!("    // invalid instruction {word:016b}\n")
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Reads the `.hack` text format: one 16-digit binary word per line.
+pub fn read_hack_text(text: &str) -> Result<Vec<u16>, Vec<Diagnostic>> {
+    let mut words = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    for (i, row) in text.lines().enumerate() {
+        let word = row.trim();
+        if word.is_empty() {
+            continue;
+        }
+
+        if word.len() != 16 || !word.chars().all(|c| c == '0' || c == '1') {
+            let col = row.len() - row.trim_start().len();
+            diagnostics.push(
+                Diagnostic::error(i + 1, col..col + word.len(), "Invalid machine code word")
+                    .with_hint("every line must be 16 binary digits"),
+            );
+            continue;
+        }
+
+        words.push(u16::from_str_radix(word, 2).unwrap());
+    }
+
+    if diagnostics.is_empty() {
+        Ok(words)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// Reads a raw ROM image of big-endian 16-bit words.
+pub fn read_hack_binary(bytes: &[u8]) -> Result<Vec<u16>, Diagnostic> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(Diagnostic::error(
+            0,
+            0..0,
+            format!("ROM image has an odd number of bytes ({})", bytes.len()),
+        ));
+    }
+
+    Ok(bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect())
+}
+
+/// Disassembles machine code. Diagnostics point at the 1-based ROM address
+/// (i.e. the line of the `.hack` file) of the offending word.
+///
+/// With `synthesize_labels`, every `@n` directly followed by a jump gets its
+/// target replaced by a label `Ln` declared at ROM address `n`, as long as `n`
+/// lies within the program.
+pub fn disassemble(words: &[u16], synthesize_labels: bool) -> Disassembly {
+    let mut decoded: Vec<_> = words.iter().map(|&word| decode(word)).collect();
+    let mut diagnostics = Vec::new();
+
+    for (addr, (&word, item)) in words.iter().zip(&decoded).enumerate() {
+        let line = addr + 1;
+        if let Decoded::Invalid(_) = item {
+            diagnostics.push(
+                Diagnostic::error(
+                    line,
+                    3..10,
+                    format!(
+                        "Computation bits {:07b} are not a valid instruction",
+                        (word >> 6) & 0b1111111
+                    ),
+                )
+                .with_hint("the word is kept as a comment in the output"),
+            );
+        } else if word & 0x8000 != 0 && word & 0x6000 != 0x6000 {
+            diagnostics.push(Diagnostic::warning(
+                line,
+                1..3,
+                "C-instruction has its unused bits cleared, reassembling will set them",
+            ));
+        }
+    }
+
+    let mut targets = BTreeSet::new();
+    if synthesize_labels {
+        for addr in 1..decoded.len() {
+            let Decoded::Token(Token::C(c_inst)) = &decoded[addr] else {
+                continue;
+            };
+            if c_inst.jump == CJump::Null {
+                continue;
+            }
+
+            if let Decoded::Token(Token::A(a_inst)) = &mut decoded[addr - 1] {
+                if let AddressInst::Value(target) = *a_inst {
+                    if (target as usize) <= words.len() {
+                        targets.insert(target as usize);
+                        *a_inst = AddressInst::Symbol(format!("L{target}"));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut items = Vec::with_capacity(decoded.len() + targets.len());
+    for (addr, item) in decoded.into_iter().enumerate() {
+        if targets.contains(&addr) {
+            items.push(Decoded::Token(Token::Label(format!("L{addr}"))));
+        }
+        items.push(item);
+    }
+    if targets.contains(&words.len()) {
+        items.push(Decoded::Token(Token::Label(format!("L{}", words.len()))));
+    }
+
+    Disassembly { items, diagnostics }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble_source;
+
+    #[test]
+    fn round_trips_with_assembler() {
+        for asm in [
+            include_str!("../max/Max.asm"),
+            include_str!("../rect/Rect.asm"),
+        ] {
+            let program = assemble_source(asm).unwrap();
+            for synthesize_labels in [false, true] {
+                let disassembly = disassemble(&program.instructions, synthesize_labels);
+                assert!(disassembly.diagnostics.is_empty());
+                let reassembled = assemble_source(&disassembly.to_asm()).unwrap();
+                assert_eq!(reassembled, program);
+            }
+        }
+    }
+
+    #[test]
+    fn synthesizes_jump_labels() {
+        let words =
+            read_hack_text("0000000000000011\n1110001100000001\n0000000000000000\n").unwrap();
+        assert_eq!(
+            disassemble(&words, true).to_asm(),
+            "    @L3\n    D;JGT\n    @0\n(L3)\n"
+        );
+    }
+
+    #[test]
+    fn flags_invalid_words() {
+        let disassembly = disassemble(&[0b1110111110000000, 0b1000110000010000], false);
+        assert_eq!(disassembly.items[0], Decoded::Invalid(0b1110111110000000));
+        assert!(disassembly.diagnostics[0].is_error());
+        assert!(!disassembly.diagnostics[1].is_error());
+        assert_eq!(
+            disassembly.to_asm(),
+            "    // invalid instruction 1110111110000000\n    D=A\n"
+        );
+    }
+
+    #[test]
+    fn reads_binary_images() {
+        assert_eq!(
+            read_hack_binary(&[0x80, 0x01, 0x00, 0x02]),
+            Ok(vec![0x8001, 2])
+        );
+        assert!(read_hack_binary(&[0x80]).is_err());
+    }
+}
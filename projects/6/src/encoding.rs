@@ -0,0 +1,178 @@
+use crate::parser::{CComp, CDest, CJump, ComputationInst};
+
+impl CDest {
+    /// Every destination, ordered by encoding.
+    pub const ALL: [CDest; 8] = [
+        CDest::Null,
+        CDest::M,
+        CDest::D,
+        CDest::MD,
+        CDest::A,
+        CDest::AM,
+        CDest::AD,
+        CDest::AMD,
+    ];
+
+    pub fn bits(self) -> u16 {
+        match self {
+            CDest::Null => 0,
+            CDest::M => 0b001,
+            CDest::D => 0b010,
+            CDest::MD => 0b011,
+            CDest::A => 0b100,
+            CDest::AM => 0b101,
+            CDest::AD => 0b110,
+            CDest::AMD => 0b111,
+        }
+    }
+
+    pub fn from_bits(bits: u16) -> Self {
+        Self::ALL[(bits & 0b111) as usize]
+    }
+}
+
+impl CJump {
+    /// Every jump condition, ordered by encoding.
+    pub const ALL: [CJump; 8] = [
+        CJump::Null,
+        CJump::JGT,
+        CJump::JEQ,
+        CJump::JGE,
+        CJump::JLT,
+        CJump::JNE,
+        CJump::JLE,
+        CJump::JMP,
+    ];
+
+    pub fn bits(self) -> u16 {
+        match self {
+            CJump::Null => 0,
+            CJump::JGT => 0b001,
+            CJump::JEQ => 0b010,
+            CJump::JGE => 0b011,
+            CJump::JLT => 0b100,
+            CJump::JNE => 0b101,
+            CJump::JLE => 0b110,
+            CJump::JMP => 0b111,
+        }
+    }
+
+    pub fn from_bits(bits: u16) -> Self {
+        Self::ALL[(bits & 0b111) as usize]
+    }
+}
+
+impl CComp {
+    pub const ALL: [CComp; 28] = [
+        CComp::Zero,
+        CComp::One,
+        CComp::NegOne,
+        CComp::D,
+        CComp::A,
+        CComp::NotD,
+        CComp::NotA,
+        CComp::NegD,
+        CComp::NegA,
+        CComp::DPlusOne,
+        CComp::APlusOne,
+        CComp::DMinusOne,
+        CComp::AMinusOne,
+        CComp::DPlusA,
+        CComp::DMinusA,
+        CComp::AMinusD,
+        CComp::DAndA,
+        CComp::DOrA,
+        CComp::M,
+        CComp::NotM,
+        CComp::NegM,
+        CComp::MPlusOne,
+        CComp::MMinusOne,
+        CComp::DPlusM,
+        CComp::DMinusM,
+        CComp::MMinusD,
+        CComp::DAndM,
+        CComp::DOrM,
+    ];
+
+    /// The 7 `a c1..c6` bits of the computation.
+    pub fn bits(self) -> u16 {
+        match self {
+            // a = 0
+            CComp::Zero => 0b0101010,
+            CComp::One => 0b0111111,
+            CComp::NegOne => 0b0111010,
+            CComp::D => 0b0001100,
+            CComp::A => 0b0110000,
+            CComp::NotD => 0b0001101,
+            CComp::NotA => 0b0110001,
+            CComp::NegD => 0b0001111,
+            CComp::NegA => 0b0110011,
+            CComp::DPlusOne => 0b0011111,
+            CComp::APlusOne => 0b0110111,
+            CComp::DMinusOne => 0b0001110,
+            CComp::AMinusOne => 0b0110010,
+            CComp::DPlusA => 0b0000010,
+            CComp::DMinusA => 0b0010011,
+            CComp::AMinusD => 0b0000111,
+            CComp::DAndA => 0,
+            CComp::DOrA => 0b0010101,
+            // a = 1
+            CComp::M => 0b1110000,
+            CComp::NotM => 0b1110001,
+            CComp::NegM => 0b1110011,
+            CComp::MPlusOne => 0b1110111,
+            CComp::MMinusOne => 0b1110010,
+            CComp::DPlusM => 0b1000010,
+            CComp::DMinusM => 0b1010011,
+            CComp::MMinusD => 0b1000111,
+            CComp::DAndM => 0b1000000,
+            CComp::DOrM => 0b1010101,
+        }
+    }
+
+    /// Returns `None` for ALU control bits that don't correspond to any
+    /// computation of the Hack instruction set.
+    pub fn from_bits(bits: u16) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|comp| comp.bits() == bits & 0b1111111)
+    }
+}
+
+impl ComputationInst {
+    pub fn encode(&self) -> u16 {
+        0b1110000000000000 | (self.comp.bits() << 6) | (self.dest.bits() << 3) | self.jump.bits()
+    }
+
+    /// Decodes a C-instruction word, ignoring the two unused bits after the
+    /// opcode. Fails if the computation bits are not part of the instruction set.
+    pub fn decode(word: u16) -> Option<Self> {
+        Some(Self {
+            dest: CDest::from_bits(word >> 3),
+            comp: CComp::from_bits(word >> 6)?,
+            jump: CJump::from_bits(word),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        for comp in CComp::ALL {
+            for dest in CDest::ALL {
+                for jump in CJump::ALL {
+                    let inst = ComputationInst { dest, comp, jump };
+                    assert_eq!(ComputationInst::decode(inst.encode()), Some(inst));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_computation() {
+        assert_eq!(ComputationInst::decode(0b1110111110000000), None);
+    }
+}
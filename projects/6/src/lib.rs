@@ -0,0 +1,48 @@
+pub mod assembler;
+pub mod diagnostic;
+pub mod disassembler;
+pub mod encoding;
+pub mod output;
+pub mod parser;
+pub mod preprocessor;
+
+pub use assembler::{Assembler, Program, SymbolKind};
+pub use diagnostic::{Diagnostic, Severity};
+pub use output::OutputFormat;
+pub use parser::{parse_str, SourceLoc, Token};
+
+/// Preprocesses, parses and assembles Hack assembly source in one go.
+/// Warnings are only returned if there also were errors.
+pub fn assemble_source(asm: &str) -> Result<Program, Vec<Diagnostic>> {
+    let expansion = preprocessor::preprocess(asm, None)?;
+    let tokens =
+        parser::parse_located(&expansion.source).map_err(|diags| expansion.remap_all(diags))?;
+    let mut assembler = Assembler::with_locations(tokens);
+    let diagnostics = expansion.remap_all(assembler.resolve_symbols());
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        return Err(diagnostics);
+    }
+
+    Ok(assembler.assemble())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembles_source() {
+        let program = assemble_source("(LOOP)\n@LOOP\n0;JMP\n@i\nM=1\n").unwrap();
+        assert_eq!(
+            program.instructions,
+            [0, 0b1110101010000111, 16, 0b1110111111001000]
+        );
+    }
+
+    #[test]
+    fn reports_diagnostics() {
+        let diagnostics = assemble_source("@1\nD=Q\n").unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 2);
+    }
+}
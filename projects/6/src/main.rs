@@ -1,33 +1,333 @@
-mod assembler;
-mod parser;
-
-use assembler::Assembler;
+use assembler::preprocessor;
+use assembler::{diagnostic, parser, Assembler, Diagnostic, OutputFormat, Severity};
 use std::env;
-use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 
+const USAGE: &str = "\
+Usage: assembler [OPTIONS] <FILE>...
+
+Assembles Hack assembly files. A FILE of - reads from stdin and writes to
+stdout unless -o is given.
+
+Options:
+  -o <PATH>          Output file, - for stdout. With several inputs, a directory
+  --format <FORMAT>  Output format: hack, bin-be, bin-le, ihex or readmemb
+  --listing          Also write a .lst listing next to the output
+  --symbols          Also write a .sym symbol map next to the output
+  --strict           Only accept the canonical spelling of C-instructions
+  --json             Print diagnostics as JSON
+  --werror           Treat warnings as errors
+  --quiet            Don't print warnings
+  -h, --help         Print this help
+
+Exit codes:
+  0  success
+  1  the assembly contains errors
+  2  invalid command line
+  3  a file could not be read or written";
+
+/// Why a run failed, ordered by precedence when several files fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Failure {
+    Assembly = 1,
+    Usage = 2,
+    Io = 3,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Options {
+    inputs: Vec<String>,
+    output: Option<String>,
+    format: Option<OutputFormat>,
+    listing: bool,
+    symbols: bool,
+    strict: bool,
+    json: bool,
+    werror: bool,
+    quiet: bool,
+    help: bool,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or(format!("{name} expects a value"))
+        };
+
+        match flag.as_str() {
+            "-o" | "--output" => options.output = Some(value(&flag)?),
+            "--format" => {
+                let name = value(&flag)?;
+                options.format = Some(OutputFormat::from_name(&name).ok_or(format!(
+                    "Unknown output format {name}, expected one of: {}",
+                    OutputFormat::NAMES.join(", ")
+                ))?);
+            }
+            "--listing" => options.listing = true,
+            "--symbols" => options.symbols = true,
+            "--strict" => options.strict = true,
+            "--json" => options.json = true,
+            "--werror" => options.werror = true,
+            "--quiet" => options.quiet = true,
+            "-h" | "--help" => options.help = true,
+            "-" => options.inputs.push(arg),
+            _ if flag.starts_with('-') => return Err(format!("Unknown option {flag}")),
+            _ => options.inputs.push(arg),
+        }
+    }
+
+    if options.help {
+        return Ok(options);
+    }
+    if options.inputs.is_empty() {
+        return Err("No assembly file was provided.".into());
+    }
+    if options.inputs.len() > 1 && options.output.as_deref() == Some("-") {
+        return Err("Only a single input can be written to stdout".into());
+    }
+    let to_stdout = match options.output.as_deref() {
+        Some(output) => output == "-",
+        None => options.inputs.iter().any(|input| input == "-"),
+    };
+    if to_stdout && (options.listing || options.symbols) {
+        return Err("Can't write the .lst or .sym file when writing to stdout, use -o".into());
+    }
+
+    Ok(options)
+}
+
+struct Run<'a> {
+    options: &'a Options,
+    format: OutputFormat,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Run<'_> {
+    fn report(&mut self, diagnostics: Vec<Diagnostic>, sources: &dyn Fn(&Diagnostic) -> String) {
+        for mut diag in diagnostics {
+            if diag.severity == Severity::Warning {
+                if self.options.werror {
+                    diag.severity = Severity::Error;
+                } else if self.options.quiet {
+                    continue;
+                }
+            }
+
+            if !self.options.json {
+                eprintln!("{}", diag.render(&sources(&diag)));
+            }
+            self.diagnostics.push(diag);
+        }
+    }
+
+    /// Where the machine code for `input` goes, `None` meaning stdout.
+    fn output_path(&self, input: &str) -> Option<PathBuf> {
+        let path = Path::new(input).with_extension(self.format.extension());
+
+        match self.options.output.as_deref() {
+            Some("-") => None,
+            Some(dir) if self.options.inputs.len() > 1 => {
+                Some(Path::new(dir).join(path.file_name().unwrap()))
+            }
+            Some(path) => Some(PathBuf::from(path)),
+            None if input == "-" => None,
+            None => Some(path),
+        }
+    }
+
+    fn assemble_file(&mut self, input: &str) -> Result<(), Failure> {
+        let asm = if input == "-" {
+            let mut asm = String::new();
+            io::stdin().read_to_string(&mut asm).map(|_| asm)
+        } else {
+            fs::read_to_string(input)
+        };
+        let asm = asm.map_err(|err| {
+            eprintln!("Could not read {input}: {err}");
+            Failure::Io
+        })?;
+
+        let file = (input != "-").then(|| Path::new(input));
+        let expansion = match preprocessor::preprocess(&asm, file) {
+            Ok(expansion) => expansion,
+            Err(diagnostics) => {
+                // directive errors can come from included files, which aren't kept around
+                let sources = |diag: &Diagnostic| match &diag.file {
+                    Some(file) => fs::read_to_string(file).unwrap_or_default(),
+                    None => asm.clone(),
+                };
+                self.report(diagnostics, &sources);
+                return Err(Failure::Assembly);
+            }
+        };
+        let sources = |diag: &Diagnostic| expansion.source_of(diag).to_string();
+
+        let tokens = match parser::parse_located_with(&expansion.source, self.options.strict) {
+            Ok(tokens) => tokens,
+            Err(diagnostics) => {
+                self.report(expansion.remap_all(diagnostics), &sources);
+                return Err(Failure::Assembly);
+            }
+        };
+
+        let mut assembler = Assembler::with_locations(tokens);
+        let reported = self.diagnostics.len();
+        self.report(expansion.remap_all(assembler.resolve_symbols()), &sources);
+        if self.diagnostics[reported..]
+            .iter()
+            .any(Diagnostic::is_error)
+        {
+            return Err(Failure::Assembly);
+        }
+
+        let program = assembler.assemble();
+        let machine_code = program.to_format(self.format);
+        let output = self.output_path(input);
+
+        let written = match &output {
+            Some(path) => fs::write(path, machine_code),
+            None => io::stdout().write_all(&machine_code),
+        };
+        written.map_err(|err| {
+            let path = output.as_deref().unwrap_or(Path::new("stdout"));
+            eprintln!("Could not write {}: {err}", path.display());
+            Failure::Io
+        })?;
+
+        let mut extra = Vec::new();
+        if self.options.listing {
+            extra.push(("lst", assembler.listing(&expansion.source)));
+        }
+        if self.options.symbols {
+            extra.push(("sym", assembler.symbol_map()));
+        }
+        // parse_args rejects listings and symbol maps for stdout
+        for (extension, contents) in extra {
+            let path = output.as_ref().unwrap().with_extension(extension);
+            fs::write(&path, contents).map_err(|err| {
+                eprintln!("Could not write {}: {err}", path.display());
+                Failure::Io
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
 fn main() -> ExitCode {
-    let Some(asm_file) = env::args().nth(1) else {
-        eprintln!("No assembly file was provided.");
-        return ExitCode::FAILURE;
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{msg}\n\n{USAGE}");
+            return ExitCode::from(Failure::Usage as u8);
+        }
+    };
+
+    if options.help {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    let mut run = Run {
+        options: &options,
+        format: options.format.unwrap_or(OutputFormat::Hack),
+        diagnostics: Vec::new(),
     };
 
-    let tokens = parser::parse_assembly(&asm_file);
-    let mut assembler = Assembler::new(tokens);
-    assembler.resolve_symbols();
-    let machine_code = assembler.assemble();
+    let mut failure = None;
+    for input in &options.inputs {
+        if let Err(err) = run.assemble_file(input) {
+            failure = failure.max(Some(err));
+        }
+    }
+
+    if options.json {
+        let json = diagnostic::to_json(&run.diagnostics);
+        let stdout_taken = options
+            .inputs
+            .iter()
+            .any(|input| run.output_path(input).is_none());
+        if stdout_taken {
+            eprintln!("{json}");
+        } else {
+            println!("{json}");
+        }
+    }
+
+    match failure {
+        Some(failure) => ExitCode::from(failure as u8),
+        None => ExitCode::SUCCESS,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
 
-    let mut hack_path = PathBuf::from(asm_file);
-    hack_path.set_extension("hack");
+    #[test]
+    fn parses_options() {
+        let options = parse(&["a.asm", "-o", "out", "--format=ihex", "--werror", "b.asm"]).unwrap();
+        assert_eq!(options.inputs, ["a.asm", "b.asm"]);
+        assert_eq!(options.output.as_deref(), Some("out"));
+        assert_eq!(options.format, Some(OutputFormat::IntelHex));
+        assert!(options.werror && !options.quiet);
 
-    let mut hack_file = File::create(hack_path).unwrap();
-    for inst in machine_code {
-        hack_file
-            .write_fmt(format_args!("{:016.b}\n", inst))
-            .unwrap();
+        let options = parse(&["--format", "bin-le", "-"]).unwrap();
+        assert_eq!(options.inputs, ["-"]);
+        assert_eq!(options.format, Some(OutputFormat::BinaryLe));
     }
 
-    ExitCode::SUCCESS
+    #[test]
+    fn rejects_invalid_command_lines() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["a.asm", "--format", "elf"]).is_err());
+        assert!(parse(&["a.asm", "-o"]).is_err());
+        assert!(parse(&["a.asm", "--bogus"]).is_err());
+        assert!(parse(&["a.asm", "b.asm", "-o", "-"]).is_err());
+        assert!(parse(&["a.asm", "-o", "-", "--listing"]).is_err());
+        assert!(parse(&["a.asm", "-", "--symbols"]).is_err());
+        assert!(parse(&["-", "-o", "out.hack", "--symbols"]).is_ok());
+        assert!(parse(&["--help"]).unwrap().help);
+    }
+
+    #[test]
+    fn output_paths() {
+        let options = parse(&["dir/a.asm", "b.asm", "-o", "out", "--format", "ihex"]).unwrap();
+        let run = Run {
+            options: &options,
+            format: OutputFormat::IntelHex,
+            diagnostics: Vec::new(),
+        };
+        assert_eq!(
+            run.output_path("dir/a.asm"),
+            Some(PathBuf::from("out/a.hex"))
+        );
+
+        let options = parse(&["-"]).unwrap();
+        let run = Run {
+            options: &options,
+            format: OutputFormat::Hack,
+            diagnostics: Vec::new(),
+        };
+        assert_eq!(run.output_path("-"), None);
+    }
 }
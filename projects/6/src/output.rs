@@ -0,0 +1,142 @@
+use crate::assembler::Program;
+
+/// Bytes of data per Intel HEX record.
+const HEX_RECORD_LEN: usize = 16;
+
+/// File formats machine code can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The course's `.hack` text format, one binary word per line.
+    Hack,
+    /// Raw ROM image, big-endian words.
+    BinaryBe,
+    /// Raw ROM image, little-endian words.
+    BinaryLe,
+    /// Intel HEX with byte addresses, big-endian words.
+    IntelHex,
+    /// Memory file for Verilog's `$readmemb` (also loadable by Logisim).
+    Readmemb,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 5] = ["hack", "bin-be", "bin-le", "ihex", "readmemb"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "hack" => OutputFormat::Hack,
+            "bin-be" => OutputFormat::BinaryBe,
+            "bin-le" => OutputFormat::BinaryLe,
+            "ihex" => OutputFormat::IntelHex,
+            "readmemb" => OutputFormat::Readmemb,
+            _ => return None,
+        })
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Hack => "hack",
+            OutputFormat::BinaryBe | OutputFormat::BinaryLe => "bin",
+            OutputFormat::IntelHex => "hex",
+            OutputFormat::Readmemb => "mem",
+        }
+    }
+}
+
+impl Program {
+    pub fn to_format(&self, format: OutputFormat) -> Vec<u8> {
+        match format {
+            OutputFormat::Hack => self.to_hack().into_bytes(),
+            OutputFormat::BinaryBe => self
+                .instructions
+                .iter()
+                .flat_map(|inst| inst.to_be_bytes())
+                .collect(),
+            OutputFormat::BinaryLe => self
+                .instructions
+                .iter()
+                .flat_map(|inst| inst.to_le_bytes())
+                .collect(),
+            OutputFormat::IntelHex => self.to_intel_hex().into_bytes(),
+            OutputFormat::Readmemb => {
+                let mut out = format!("// Hack ROM, {} words\n@0\n", self.instructions.len());
+                out += &self.to_hack();
+                out.into_bytes()
+            }
+        }
+    }
+
+    fn to_intel_hex(&self) -> String {
+        let bytes = self.to_format(OutputFormat::BinaryBe);
+        let mut out = String::new();
+
+        for (i, chunk) in bytes.chunks(HEX_RECORD_LEN).enumerate() {
+            let addr = (i * HEX_RECORD_LEN) as u16;
+            let mut record = vec![chunk.len() as u8];
+            record.extend(addr.to_be_bytes());
+            record.push(0x00); // data record
+            record.extend(chunk);
+            out += &hex_record(&record);
+        }
+
+        out += &hex_record(&[0x00, 0x00, 0x00, 0x01]); // end of file
+        out
+    }
+}
+
+fn hex_record(record: &[u8]) -> String {
+    let checksum = record
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg();
+
+    let mut out = String::from(":");
+    for byte in record.iter().chain([&checksum]) {
+        out += &format!("{byte:02X}");
+    }
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program() -> Program {
+        Program {
+            instructions: vec![0x0002, 0xEC10],
+        }
+    }
+
+    #[test]
+    fn binary_images() {
+        assert_eq!(
+            program().to_format(OutputFormat::BinaryBe),
+            [0x00, 0x02, 0xEC, 0x10]
+        );
+        assert_eq!(
+            program().to_format(OutputFormat::BinaryLe),
+            [0x02, 0x00, 0x10, 0xEC]
+        );
+    }
+
+    #[test]
+    fn intel_hex() {
+        let program = Program {
+            instructions: (0..9).collect(),
+        };
+        assert_eq!(
+            String::from_utf8(program.to_format(OutputFormat::IntelHex)).unwrap(),
+            ":1000000000000001000200030004000500060007D4\n\
+             :020010000008E6\n\
+             :00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn readmemb() {
+        assert_eq!(
+            String::from_utf8(program().to_format(OutputFormat::Readmemb)).unwrap(),
+            "// Hack ROM, 2 words\n@0\n0000000000000010\n1110110000010000\n"
+        );
+    }
+}
@@ -1,10 +1,16 @@
-use std::fs;
+use crate::diagnostic::Diagnostic;
+use crate::preprocessor::preprocess;
+use std::fmt;
+use std::ops::Range;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AddressInst {
     Value(u16),
     Symbol(String),
 }
 
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CDest {
     Null,
     M,
@@ -16,6 +22,8 @@ pub enum CDest {
     AMD,
 }
 
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CJump {
     Null,
     JGT,
@@ -27,6 +35,7 @@ pub enum CJump {
     JMP,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CComp {
     Zero,
     One,
@@ -58,40 +67,273 @@ pub enum CComp {
     DOrM,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ComputationInst {
     pub dest: CDest,
     pub comp: CComp,
     pub jump: CJump,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Token {
     A(AddressInst),
     C(ComputationInst),
     Label(String),
 }
 
-fn parse_label(row: &str, line: i32) -> Result<Token, String> {
-    let Some(mut row) = row.strip_prefix('(') else {
-        return Err(format!("Invalid label syntax on line {}: {}", line, row));
+impl CDest {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            CDest::Null => "",
+            CDest::M => "M",
+            CDest::D => "D",
+            CDest::MD => "MD",
+            CDest::A => "A",
+            CDest::AM => "AM",
+            CDest::AD => "AD",
+            CDest::AMD => "AMD",
+        }
+    }
+}
+
+impl CJump {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            CJump::Null => "",
+            CJump::JGT => "JGT",
+            CJump::JEQ => "JEQ",
+            CJump::JGE => "JGE",
+            CJump::JLT => "JLT",
+            CJump::JNE => "JNE",
+            CJump::JLE => "JLE",
+            CJump::JMP => "JMP",
+        }
+    }
+}
+
+impl CComp {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            CComp::Zero => "0",
+            CComp::One => "1",
+            CComp::NegOne => "-1",
+            CComp::D => "D",
+            CComp::A => "A",
+            CComp::NotD => "!D",
+            CComp::NotA => "!A",
+            CComp::NegD => "-D",
+            CComp::NegA => "-A",
+            CComp::DPlusOne => "D+1",
+            CComp::APlusOne => "A+1",
+            CComp::DMinusOne => "D-1",
+            CComp::AMinusOne => "A-1",
+            CComp::DPlusA => "D+A",
+            CComp::DMinusA => "D-A",
+            CComp::AMinusD => "A-D",
+            CComp::DAndA => "D&A",
+            CComp::DOrA => "D|A",
+            CComp::M => "M",
+            CComp::NotM => "!M",
+            CComp::NegM => "-M",
+            CComp::MPlusOne => "M+1",
+            CComp::MMinusOne => "M-1",
+            CComp::DPlusM => "D+M",
+            CComp::DMinusM => "D-M",
+            CComp::MMinusD => "M-D",
+            CComp::DAndM => "D&M",
+            CComp::DOrM => "D|M",
+        }
+    }
+}
+
+impl fmt::Display for ComputationInst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.dest != CDest::Null {
+            write!(f, "{}=", self.dest.mnemonic())?;
+        }
+        f.write_str(self.comp.mnemonic())?;
+        if self.jump != CJump::Null {
+            write!(f, ";{}", self.jump.mnemonic())?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::A(AddressInst::Value(val)) => write!(f, "@{val}"),
+            Token::A(AddressInst::Symbol(symbol)) => write!(f, "@{symbol}"),
+            Token::C(c_inst) => write!(f, "{c_inst}"),
+            Token::Label(label) => write!(f, "({label})"),
+        }
+    }
+}
+
+fn parse_label(row: &str, line: usize, col: usize) -> Result<Token, Diagnostic> {
+    let invalid = || {
+        Diagnostic::error(
+            line,
+            col..col + row.len(),
+            format!("Invalid label syntax: {row}"),
+        )
+        .with_hint("labels are declared as (NAME)")
     };
 
-    row = match row.strip_suffix(')') {
-        Some(row) => row,
-        None => return Err(format!("Invalid label syntax on line {}: {}", line, row)),
+    let label = row
+        .strip_prefix('(')
+        .and_then(|row| row.strip_suffix(')'))
+        .ok_or_else(invalid)?;
+
+    Ok(Token::Label(label.into()))
+}
+
+/// Largest value an A-instruction can load, since its top bit is the opcode.
+pub(crate) const MAX_CONSTANT: u32 = 0x7FFF;
+
+/// Parses a decimal, `0x` hexadecimal or `0b` binary literal.
+pub(crate) fn parse_number(literal: &str) -> Option<u32> {
+    let (digits, radix) = if let Some(hex) = literal
+        .strip_prefix("0x")
+        .or_else(|| literal.strip_prefix("0X"))
+    {
+        (hex, 16)
+    } else if let Some(bin) = literal
+        .strip_prefix("0b")
+        .or_else(|| literal.strip_prefix("0B"))
+    {
+        (bin, 2)
+    } else {
+        (literal, 10)
     };
 
-    Ok(Token::Label(row.into()))
+    // from_str_radix would otherwise accept a leading '+'
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+
+    // saturate so that absurdly long literals are reported as out of range
+    Some(u32::from_str_radix(digits, radix).unwrap_or(u32::MAX))
 }
 
-fn parse_address_inst(row: &str, _line: i32) -> Result<Token, String> {
+fn parse_address_inst(row: &str, line: usize, col: usize) -> Result<Token, Diagnostic> {
     let addr = &row[1..];
-    Ok(match addr.parse::<u16>() {
-        Ok(num) => Token::A(AddressInst::Value(num)),
-        Err(_) => Token::A(AddressInst::Symbol(addr.into())),
-    })
+    let span = col + 1..col + row.len();
+
+    if addr.is_empty() {
+        return Err(Diagnostic::error(
+            line,
+            col..col + 1,
+            "Missing address after @",
+        ));
+    }
+
+    if let Some(negated) = addr.strip_prefix('-') {
+        if parse_number(negated).is_none() {
+            return Err(Diagnostic::error(
+                line,
+                span,
+                format!("Invalid constant: {addr}"),
+            ));
+        }
+
+        return Err(Diagnostic::error(
+            line,
+            span,
+            format!("Negative constant {addr} can't be loaded by an A-instruction"),
+        )
+        .with_hint(format!(
+            "load {negated} and negate it, e.g. @{negated} then D=-A"
+        )));
+    }
+
+    if !addr.starts_with(|c: char| c.is_ascii_digit()) {
+        return Ok(Token::A(AddressInst::Symbol(addr.into())));
+    }
+
+    match parse_number(addr) {
+        Some(num) if num <= MAX_CONSTANT => Ok(Token::A(AddressInst::Value(num as u16))),
+        Some(_) => Err(Diagnostic::error(
+            line,
+            span,
+            format!("Constant {addr} does not fit in an A-instruction"),
+        )
+        .with_hint(format!(
+            "A-instructions load values from 0 to {MAX_CONSTANT} (0x{MAX_CONSTANT:X})"
+        ))),
+        None => Err(
+            Diagnostic::error(line, span, format!("Invalid constant: {addr}"))
+                .with_hint("symbols can't start with a digit"),
+        ),
+    }
+}
+
+/// Returns the columns `part` spans within the line, given that `part` is a
+/// subslice of `row` and `row` starts at column `col`.
+fn span_of(row: &str, part: &str, col: usize) -> Range<usize> {
+    let start = col + (part.as_ptr() as usize - row.as_ptr() as usize);
+    let trimmed = part.trim();
+    let start = start + (part.len() - part.trim_start().len());
+    start..start + trimmed.len()
+}
+
+/// Reads a destination with its registers in non-canonical order, e.g. `DM`.
+fn reordered_dest(dest: &str) -> Option<CDest> {
+    let mut bits = 0;
+    for register in dest.chars() {
+        let bit = match register {
+            'A' => 0b100,
+            'D' => 0b010,
+            'M' => 0b001,
+            _ => return None,
+        };
+        if bits & bit != 0 {
+            return None;
+        }
+        bits |= bit;
+    }
+
+    (bits != 0).then(|| CDest::from_bits(bits))
+}
+
+/// Reads a commutative computation with its operands swapped, e.g. `A+D`.
+fn commuted_comp(comp: &str) -> Option<CComp> {
+    let op_idx = comp
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| "+&|".contains(*c))?
+        .0;
+    let (lhs, op, rhs) = (&comp[..op_idx], &comp[op_idx..=op_idx], &comp[op_idx + 1..]);
+    let swapped = format!("{rhs}{op}{lhs}");
+
+    CComp::ALL
+        .into_iter()
+        .find(|candidate| candidate.mnemonic() == swapped)
 }
 
-fn parse_computation_inst(row: &str, line: i32) -> Result<Token, String> {
+/// Error for an alternative spelling that strict mode rejects.
+fn non_canonical(
+    line: usize,
+    span: Range<usize>,
+    kind: &str,
+    found: &str,
+    canonical: &str,
+) -> Diagnostic {
+    Diagnostic::error(
+        line,
+        span,
+        format!("Non-canonical {kind} in strict mode: {found}"),
+    )
+    .with_hint(format!("write it as {canonical}"))
+}
+
+fn parse_computation_inst(
+    row: &str,
+    line: usize,
+    col: usize,
+    strict: bool,
+) -> Result<Token, Diagnostic> {
+    let full_row = row;
     let mut row = row;
     let mut c_inst = ComputationInst {
         dest: CDest::Null,
@@ -109,12 +351,26 @@ fn parse_computation_inst(row: &str, line: i32) -> Result<Token, String> {
             "AM" => CDest::AM,
             "AD" => CDest::AD,
             "AMD" => CDest::AMD,
-            _ => {
-                return Err(format!(
-                    "Found invalid destination on line {}: {}",
-                    line, dest
-                ))
-            }
+            trimmed => match reordered_dest(trimmed) {
+                Some(dest) if !strict => dest,
+                Some(canonical) => {
+                    return Err(non_canonical(
+                        line,
+                        span_of(full_row, dest, col),
+                        "destination",
+                        trimmed,
+                        canonical.mnemonic(),
+                    ))
+                }
+                None => {
+                    return Err(Diagnostic::error(
+                        line,
+                        span_of(full_row, dest, col),
+                        format!("Found invalid destination: {trimmed}"),
+                    )
+                    .with_hint("valid destinations are M, D, MD, A, AM, AD and AMD"))
+                }
+            },
         }
     } else {
         CDest::Null
@@ -131,10 +387,12 @@ fn parse_computation_inst(row: &str, line: i32) -> Result<Token, String> {
             "JLE" => CJump::JLE,
             "JMP" => CJump::JMP,
             _ => {
-                return Err(format!(
-                    "Found invalid jump condition on line {}: {}",
-                    line, jump
-                ))
+                return Err(Diagnostic::error(
+                    line,
+                    span_of(full_row, jump, col),
+                    format!("Found invalid jump condition: {}", jump.trim()),
+                )
+                .with_hint("valid jumps are JGT, JEQ, JGE, JLT, JNE, JLE and JMP"))
             }
         }
     } else {
@@ -172,45 +430,194 @@ fn parse_computation_inst(row: &str, line: i32) -> Result<Token, String> {
         "M-D" => CComp::MMinusD,
         "D&M" => CComp::DAndM,
         "D|M" => CComp::DOrM,
-        _ => {
-            return Err(format!(
-                "Found invalid computation on line {}: {}",
-                line, comp
-            ))
-        }
+        _ => match commuted_comp(&comp) {
+            Some(comp) if !strict => comp,
+            Some(canonical) => {
+                return Err(non_canonical(
+                    line,
+                    span_of(full_row, row, col),
+                    "computation",
+                    &comp,
+                    canonical.mnemonic(),
+                ))
+            }
+            None => {
+                return Err(Diagnostic::error(
+                    line,
+                    span_of(full_row, row, col),
+                    format!("Found invalid computation: {comp}"),
+                ))
+            }
+        },
     };
 
     Ok(Token::C(c_inst))
 }
 
-pub fn parse_assembly(asm_file: &str) -> Vec<Token> {
-    let asm = fs::read_to_string(asm_file).unwrap();
+/// Where a token came from in the source: its 1-based line and the 0-based
+/// columns it spans on that line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLoc {
+    pub line: usize,
+    pub span: Range<usize>,
+}
+
+/// Parses Hack assembly source, collecting every error found instead of
+/// stopping at the first one. Macros and directives are expanded first, with
+/// `.include` paths relative to the current directory.
+pub fn parse_str(asm: &str) -> Result<Vec<Token>, Vec<Diagnostic>> {
+    let expansion = preprocess(asm, None)?;
+    let tokens = parse_located(&expansion.source).map_err(|diags| expansion.remap_all(diags))?;
+    Ok(tokens.into_iter().map(|(token, _)| token).collect())
+}
+
+/// Parses plain assembly, without expanding directives, pairing every token
+/// with its location in the source.
+pub fn parse_located(asm: &str) -> Result<Vec<(Token, SourceLoc)>, Vec<Diagnostic>> {
+    parse_located_with(asm, false)
+}
+
+/// Same as [`parse_located`]. Unless `strict`, computations with commuted
+/// operands (`A+D`) and destinations in any order (`DM`) are accepted and
+/// normalized to their canonical form.
+pub fn parse_located_with(
+    asm: &str,
+    strict: bool,
+) -> Result<Vec<(Token, SourceLoc)>, Vec<Diagnostic>> {
     let mut tokens = Vec::new();
+    let mut diagnostics = Vec::new();
 
-    let mut line = 0;
-    for row in asm.lines() {
-        line += 1;
-        let row = row.trim();
+    for (i, raw_row) in asm.lines().enumerate() {
+        let line = i + 1;
+        let row = raw_row.trim();
         if row.is_empty() || row.starts_with("//") {
             continue;
         }
+        let col = raw_row.len() - raw_row.trim_start().len();
+        let loc = SourceLoc {
+            line,
+            span: col..col + row.len(),
+        };
 
         let row = if row.starts_with('@') {
-            parse_address_inst(row, line)
+            parse_address_inst(row, line, col)
         } else if row.starts_with('(') {
-            parse_label(row, line)
+            parse_label(row, line, col)
         } else {
-            parse_computation_inst(row, line)
+            parse_computation_inst(row, line, col, strict)
         };
 
-        tokens.push(match row {
-            Ok(token) => token,
-            Err(msg) => {
-                eprintln!("{msg}");
-                std::process::exit(1);
-            }
-        })
+        match row {
+            Ok(token) => tokens.push((token, loc)),
+            Err(diag) => diagnostics.push(diag),
+        }
+    }
+
+    if diagnostics.is_empty() {
+        Ok(tokens)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// Same as [`parse_located`], but labels the diagnostics with the file they
+/// came from.
+pub fn parse_assembly(asm: &str, file: &str) -> Result<Vec<(Token, SourceLoc)>, Vec<Diagnostic>> {
+    parse_located(asm).map_err(|diagnostics| {
+        diagnostics
+            .into_iter()
+            .map(|diag| diag.with_file(file))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternative_spellings() {
+        let lenient = |row| parse_computation_inst(row, 1, 0, false);
+        let strict = |row| parse_computation_inst(row, 1, 0, true);
+        let c_inst = |dest, comp, jump| Ok(Token::C(ComputationInst { dest, comp, jump }));
+
+        assert_eq!(
+            lenient("DM=A+D"),
+            c_inst(CDest::MD, CComp::DPlusA, CJump::Null)
+        );
+        assert_eq!(
+            lenient("MA=M&D"),
+            c_inst(CDest::AM, CComp::DAndM, CJump::Null)
+        );
+        assert_eq!(
+            lenient("DAM=A|D"),
+            c_inst(CDest::AMD, CComp::DOrA, CJump::Null)
+        );
+        assert_eq!(
+            lenient("1+M;JGT"),
+            c_inst(CDest::Null, CComp::MPlusOne, CJump::JGT)
+        );
+        assert!(lenient("DD=A").is_err());
+        assert!(lenient("D=A-M").is_err());
+        assert!(lenient("D=1-D").is_err());
+
+        assert_eq!(
+            strict("MD=D+A"),
+            c_inst(CDest::MD, CComp::DPlusA, CJump::Null)
+        );
+        let diag = strict("D=A+D").unwrap_err();
+        assert_eq!(
+            diag.message,
+            "Non-canonical computation in strict mode: A+D"
+        );
+        assert_eq!(diag.hint.as_deref(), Some("write it as D+A"));
+        assert_eq!(diag.span, 2..5);
+        assert!(strict("DM=D").is_err());
     }
 
-    tokens
+    #[test]
+    fn numeric_literals() {
+        let value = |row| match parse_address_inst(row, 1, 0) {
+            Ok(Token::A(AddressInst::Value(num))) => Ok(num),
+            Ok(_) => panic!("{row} was parsed as a symbol"),
+            Err(diag) => Err(diag.message),
+        };
+
+        assert_eq!(value("@32767"), Ok(32767));
+        assert_eq!(value("@0x4000"), Ok(16384));
+        assert_eq!(value("@0b1010"), Ok(10));
+        assert_eq!(
+            value("@40000"),
+            Err("Constant 40000 does not fit in an A-instruction".into())
+        );
+        assert_eq!(
+            value("@0xFFFF"),
+            Err("Constant 0xFFFF does not fit in an A-instruction".into())
+        );
+        assert_eq!(
+            value("@-1"),
+            Err("Negative constant -1 can't be loaded by an A-instruction".into())
+        );
+        assert_eq!(value("@0x"), Err("Invalid constant: 0x".into()));
+        assert_eq!(value("@12ab"), Err("Invalid constant: 12ab".into()));
+        assert_eq!(
+            parse_address_inst("@ITSR0", 1, 0),
+            Ok(Token::A(AddressInst::Symbol("ITSR0".into())))
+        );
+    }
+
+    #[test]
+    fn collects_every_error() {
+        let asm = "@1\nX=D\n  D=Q;JMP\n(LOOP\n0;JXX\n";
+        let diagnostics = parse_assembly(asm, "Test.asm").err().unwrap();
+
+        let lines: Vec<_> = diagnostics.iter().map(|diag| diag.line).collect();
+        assert_eq!(lines, [2, 3, 4, 5]);
+        assert_eq!(diagnostics[0].span, 0..1);
+        assert_eq!(diagnostics[1].span, 4..5);
+        assert_eq!(diagnostics[3].span, 2..5);
+        assert!(diagnostics
+            .iter()
+            .all(|diag| diag.file.as_deref() == Some("Test.asm")));
+    }
 }
@@ -0,0 +1,584 @@
+//! Expands macros, `.include` and `.equ` directives into plain Hack assembly.
+//!
+//! ```text
+//! .equ STACK 256
+//! .include "runtime.asm"
+//!
+//! .macro PUSH_CONST value
+//!     @%value
+//!     D=A
+//!     @SP
+//!     AM=M+1
+//!     A=A-1
+//!     M=D
+//! .endm
+//!
+//! PUSH_CONST STACK
+//! ```
+//!
+//! Macro parameters are referenced as `%name` and invocations pass arguments
+//! separated by commas. Labels declared inside a macro body are local to each
+//! expansion, so a macro may be invoked several times without its labels
+//! clashing. Only `(LABEL)` declarations and `@LABEL` references on lines
+//! holding nothing else but a comment are renamed: a local label passed as an
+//! argument to another macro keeps its unrenamed name.
+//!
+//! Constants can't share their name with a predefined symbol or a label.
+
+use crate::assembler::PREDEFINED;
+use crate::diagnostic::Diagnostic;
+use crate::parser::{parse_number, MAX_CONSTANT};
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+const MAX_EXPANSION_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Origin {
+    file: Option<String>,
+    line: usize,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<(String, Origin)>,
+    labels: Vec<String>,
+}
+
+/// Plain assembly produced by [`preprocess`], along with where each of its
+/// lines came from.
+pub struct Expansion {
+    pub source: String,
+    origins: Vec<Origin>,
+    files: Vec<(Option<String>, String)>,
+}
+
+impl Expansion {
+    /// Maps a diagnostic about [`Expansion::source`] back to the file and line
+    /// the offending line was written on.
+    pub fn remap(&self, mut diag: Diagnostic) -> Diagnostic {
+        if let Some(origin) = self.origins.get(diag.line.wrapping_sub(1)) {
+            diag.line = origin.line;
+            diag.file = origin.file.clone();
+        }
+        diag
+    }
+
+    pub fn remap_all(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .map(|diag| self.remap(diag))
+            .collect()
+    }
+
+    /// The file and line a line of [`Expansion::source`] was written on.
+    pub fn origin(&self, line: usize) -> Option<(Option<&str>, usize)> {
+        self.origins
+            .get(line.wrapping_sub(1))
+            .map(|origin| (origin.file.as_deref(), origin.line))
+    }
+
+    /// The text of the file a remapped diagnostic points at, for rendering it.
+    pub fn source_of(&self, diag: &Diagnostic) -> &str {
+        self.files
+            .iter()
+            .find(|(file, _)| *file == diag.file)
+            .map_or("", |(_, source)| source)
+    }
+}
+
+struct Preprocessor {
+    macros: HashMap<String, Macro>,
+    /// Each constant's value and where it was defined.
+    equs: HashMap<String, (String, Origin)>,
+    lines: Vec<String>,
+    origins: Vec<Origin>,
+    files: Vec<(Option<String>, String)>,
+    include_stack: Vec<PathBuf>,
+    expansions: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+fn error(origin: &Origin, span: Range<usize>, message: String) -> Diagnostic {
+    let diag = Diagnostic::error(origin.line, span, message);
+    match &origin.file {
+        Some(file) => diag.with_file(file.as_str()),
+        None => diag,
+    }
+}
+
+/// The code of a row, without its comment.
+fn code_of(row: &str) -> &str {
+    row.split_once("//").map_or(row, |(code, _)| code).trim()
+}
+
+fn is_symbol(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.$:".contains(c))
+}
+
+impl Preprocessor {
+    fn process(&mut self, source: &str, file: Option<String>, dir: &Path) {
+        self.files.push((file.clone(), source.to_string()));
+        let mut definition: Option<(String, Macro, Origin)> = None;
+
+        for (i, raw_row) in source.lines().enumerate() {
+            let row = raw_row.trim();
+            let col = raw_row.len() - raw_row.trim_start().len();
+            let span = col..col + row.len();
+            let origin = Origin {
+                file: file.clone(),
+                line: i + 1,
+            };
+
+            if row.is_empty() || row.starts_with("//") {
+                continue;
+            }
+
+            let (directive, rest) = row.split_once(char::is_whitespace).unwrap_or((row, ""));
+            let rest = rest.trim();
+
+            if let Some((_, mac, _)) = &mut definition {
+                match directive {
+                    ".endm" => {
+                        let (name, mac, _) = definition.take().unwrap();
+                        self.macros.insert(name, mac);
+                    }
+                    ".macro" => self.diagnostics.push(
+                        error(
+                            &origin,
+                            span,
+                            "Macros can't be defined inside a macro".into(),
+                        )
+                        .with_hint("close the enclosing macro with .endm first"),
+                    ),
+                    _ => {
+                        if let Some(label) = code_of(row)
+                            .strip_prefix('(')
+                            .and_then(|r| r.strip_suffix(')'))
+                        {
+                            mac.labels.push(label.to_string());
+                        }
+                        mac.body.push((raw_row.to_string(), origin));
+                    }
+                }
+                continue;
+            }
+
+            match directive {
+                ".macro" => {
+                    let (name, params) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                    let params: Vec<_> = params
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|param| !param.is_empty())
+                        .map(String::from)
+                        .collect();
+
+                    if !is_symbol(name) {
+                        self.diagnostics.push(error(
+                            &origin,
+                            span,
+                            format!("Invalid macro name: {name}"),
+                        ));
+                    } else if self.macros.contains_key(name) {
+                        self.diagnostics.push(error(
+                            &origin,
+                            span,
+                            format!("Macro {name} is defined twice"),
+                        ));
+                    }
+
+                    let mac = Macro {
+                        params,
+                        body: Vec::new(),
+                        labels: Vec::new(),
+                    };
+                    definition = Some((name.to_string(), mac, origin));
+                }
+                ".endm" => self.diagnostics.push(error(
+                    &origin,
+                    span,
+                    ".endm without a matching .macro".into(),
+                )),
+                ".equ" => self.define_equ(rest, &origin, span),
+                ".include" => self.include(rest, &origin, span, dir),
+                _ if directive.starts_with('.') => self.diagnostics.push(
+                    error(&origin, span, format!("Unknown directive: {directive}"))
+                        .with_hint("supported directives are .macro, .endm, .equ and .include"),
+                ),
+                _ => self.emit(raw_row.to_string(), origin, 0),
+            }
+        }
+
+        if let Some((name, _, origin)) = definition {
+            self.diagnostics.push(
+                error(&origin, 0..0, format!("Macro {name} is never closed"))
+                    .with_hint("end the macro body with .endm"),
+            );
+        }
+    }
+
+    fn define_equ(&mut self, rest: &str, origin: &Origin, span: Range<usize>) {
+        let Some((name, value)) = rest.split_once(char::is_whitespace) else {
+            self.diagnostics.push(
+                error(origin, span, "Missing value for .equ".into())
+                    .with_hint("constants are defined as .equ NAME value"),
+            );
+            return;
+        };
+        let value = value.trim();
+
+        let resolved = match self.equs.get(value) {
+            Some((value, _)) => Some(value.clone()),
+            None => parse_number(value)
+                .filter(|&num| num <= MAX_CONSTANT)
+                .map(|num| num.to_string()),
+        };
+
+        if !is_symbol(name) {
+            self.diagnostics.push(error(
+                origin,
+                span,
+                format!("Invalid constant name: {name}"),
+            ));
+        } else if self.equs.contains_key(name) {
+            self.diagnostics.push(error(
+                origin,
+                span,
+                format!("Constant {name} is defined twice"),
+            ));
+        } else if PREDEFINED.iter().any(|&(predefined, _)| predefined == name) {
+            self.diagnostics.push(
+                error(
+                    origin,
+                    span,
+                    format!("Constant {name} redefines a predefined symbol"),
+                )
+                .with_hint(
+                    "pick a name not used by R0-R15, SCREEN, KBD, SP, LCL, ARG, THIS or THAT",
+                ),
+            );
+        } else if let Some(value) = resolved {
+            self.equs.insert(name.to_string(), (value, origin.clone()));
+        } else {
+            self.diagnostics.push(
+                error(
+                    origin,
+                    span,
+                    format!("Invalid value for constant {name}: {value}"),
+                )
+                .with_hint(format!(
+                    "values must be constants from 0 to {MAX_CONSTANT} or previously defined names"
+                )),
+            );
+        }
+    }
+
+    fn include(&mut self, rest: &str, origin: &Origin, span: Range<usize>, dir: &Path) {
+        let Some(path) = rest
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+        else {
+            self.diagnostics.push(
+                error(origin, span, format!("Invalid include: {rest}"))
+                    .with_hint("the path must be quoted, e.g. .include \"lib.asm\""),
+            );
+            return;
+        };
+
+        let path = dir.join(path);
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if self.include_stack.contains(&canonical) {
+            self.diagnostics.push(error(
+                origin,
+                span,
+                format!("{} includes itself", path.display()),
+            ));
+            return;
+        }
+
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                self.diagnostics.push(error(
+                    origin,
+                    span,
+                    format!("Could not read {}: {err}", path.display()),
+                ));
+                return;
+            }
+        };
+
+        self.include_stack.push(canonical);
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        self.process(&source, Some(path.display().to_string()), &dir);
+        self.include_stack.pop();
+    }
+
+    fn emit(&mut self, raw_row: String, origin: Origin, depth: usize) {
+        let row = raw_row.trim();
+        let (name, args) = row.split_once(char::is_whitespace).unwrap_or((row, ""));
+        let col = raw_row.len() - raw_row.trim_start().len();
+        let span = col..col + row.len();
+
+        if let Some(mac) = self.macros.get(name) {
+            let args: Vec<_> = args
+                .split(',')
+                .map(str::trim)
+                .filter(|arg| !arg.is_empty())
+                .collect();
+
+            if depth == MAX_EXPANSION_DEPTH {
+                self.diagnostics.push(
+                    error(
+                        &origin,
+                        span,
+                        format!("Macro {name} is expanded too deeply"),
+                    )
+                    .with_hint("macros can't invoke themselves"),
+                );
+                return;
+            }
+            if args.len() != mac.params.len() {
+                self.diagnostics.push(error(
+                    &origin,
+                    span,
+                    format!(
+                        "Macro {name} takes {} arguments but {} were given",
+                        mac.params.len(),
+                        args.len()
+                    ),
+                ));
+                return;
+            }
+
+            self.expansions += 1;
+            let expansion = self.expansions;
+
+            // substitute longer parameter names first so %ab isn't taken for %a
+            let mut params: Vec<_> = mac.params.iter().zip(&args).collect();
+            params.sort_by_key(|(param, _)| std::cmp::Reverse(param.len()));
+
+            let body: Vec<_> = mac
+                .body
+                .iter()
+                .map(|(line, body_origin)| {
+                    let mut line = line.clone();
+                    for (param, arg) in &params {
+                        line = line.replace(&format!("%{param}"), arg);
+                    }
+                    for label in &mac.labels {
+                        let local = format!("{name}.{expansion}.{label}");
+                        let code = code_of(&line).to_string();
+                        if code == format!("({label})") {
+                            line = line.replacen(&code, &format!("({local})"), 1);
+                        } else if code == format!("@{label}") {
+                            line = line.replacen(&code, &format!("@{local}"), 1);
+                        }
+                    }
+                    (line, body_origin.clone())
+                })
+                .collect();
+
+            for (line, body_origin) in body {
+                self.emit(line, body_origin, depth + 1);
+            }
+            return;
+        }
+
+        let row = match row
+            .strip_prefix('@')
+            .and_then(|symbol| self.equs.get(symbol))
+            .map(|(value, _)| value)
+        {
+            Some(value) => raw_row.replace(row, &format!("@{value}")),
+            None => raw_row,
+        };
+        self.lines.push(row);
+        self.origins.push(origin);
+    }
+
+    /// Reports constants named like a label, which would silently replace
+    /// the references to the label.
+    fn check_labels(&mut self) {
+        for (raw_row, label_origin) in self.lines.iter().zip(&self.origins) {
+            let Some(label) = code_of(raw_row)
+                .strip_prefix('(')
+                .and_then(|r| r.strip_suffix(')'))
+            else {
+                continue;
+            };
+            let Some((_, origin)) = self.equs.get(label) else {
+                continue;
+            };
+
+            let declared = match &label_origin.file {
+                Some(file) => format!("{file}:{}", label_origin.line),
+                None => format!("line {}", label_origin.line),
+            };
+            self.diagnostics.push(
+                error(
+                    origin,
+                    0..0,
+                    format!("Constant {label} has the same name as a label"),
+                )
+                .with_hint(format!("the label is declared at {declared}")),
+            );
+        }
+    }
+}
+
+/// Expands the directives and macros in `source`. `file` names the source in
+/// diagnostics, and `.include` paths are relative to its directory.
+pub fn preprocess(source: &str, file: Option<&Path>) -> Result<Expansion, Vec<Diagnostic>> {
+    let mut preprocessor = Preprocessor {
+        macros: HashMap::new(),
+        equs: HashMap::new(),
+        lines: Vec::new(),
+        origins: Vec::new(),
+        files: Vec::new(),
+        include_stack: Vec::new(),
+        expansions: 0,
+        diagnostics: Vec::new(),
+    };
+
+    let dir = file
+        .and_then(Path::parent)
+        .unwrap_or(Path::new(""))
+        .to_path_buf();
+    if let Some(file) = file {
+        let canonical = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+        preprocessor.include_stack.push(canonical);
+    }
+    preprocessor.process(source, file.map(|file| file.display().to_string()), &dir);
+    preprocessor.check_labels();
+
+    if !preprocessor.diagnostics.is_empty() {
+        return Err(preprocessor.diagnostics);
+    }
+
+    let mut source = preprocessor.lines.join("\n");
+    source.push('\n');
+    Ok(Expansion {
+        source,
+        origins: preprocessor.origins,
+        files: preprocessor.files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_macros_and_constants() {
+        let source = "\
+.equ BASE 0x10
+.macro LOAD value, dest
+  @%value
+  %dest=A
+.endm
+LOAD BASE, D
+LOAD 7, M
+";
+        let expansion = preprocess(source, None).unwrap();
+        assert_eq!(expansion.source, "  @16\n  D=A\n  @7\n  M=A\n");
+    }
+
+    #[test]
+    fn macro_labels_are_local() {
+        let source = "\
+.macro SPIN
+(LOOP) // spin here
+@LOOP
+0;JMP
+.endm
+SPIN
+SPIN
+";
+        let expansion = preprocess(source, None).unwrap();
+        assert_eq!(
+            expansion.source,
+            "(SPIN.1.LOOP) // spin here\n@SPIN.1.LOOP\n0;JMP\n\
+             (SPIN.2.LOOP) // spin here\n@SPIN.2.LOOP\n0;JMP\n"
+        );
+    }
+
+    #[test]
+    fn remaps_to_macro_body() {
+        let source = "@1\n.macro BAD\nD=Q\n.endm\nBAD\n";
+        let expansion = preprocess(source, Some(Path::new("Test.asm"))).unwrap();
+        let diag = expansion.remap(Diagnostic::error(2, 0..3, "Found invalid computation: Q"));
+        assert_eq!(diag.line, 3);
+        assert_eq!(diag.file.as_deref(), Some("Test.asm"));
+        assert_eq!(expansion.source_of(&diag), source);
+        assert_eq!(expansion.origin(2), Some((Some("Test.asm"), 3)));
+    }
+
+    #[test]
+    fn includes_relative_to_file() {
+        let dir = std::env::temp_dir().join(format!("hack-include-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("lib/push.asm"),
+            ".include \"consts.asm\"\n@VALUE\n",
+        )
+        .unwrap();
+        fs::write(dir.join("lib/consts.asm"), ".equ VALUE 3\n").unwrap();
+        fs::write(dir.join("lib/loop.asm"), ".include \"loop.asm\"\n").unwrap();
+
+        let main = dir.join("Main.asm");
+        let expansion = preprocess(".include \"lib/push.asm\"\nD=A\n", Some(&main)).unwrap();
+        assert_eq!(expansion.source, "@3\nD=A\n");
+        let diag = expansion.remap(Diagnostic::error(1, 0..2, ""));
+        assert_eq!(
+            diag.file,
+            Some(dir.join("lib/push.asm").display().to_string())
+        );
+        assert_eq!(diag.line, 2);
+
+        let diagnostics = preprocess(".include \"lib/loop.asm\"\n", Some(&main))
+            .err()
+            .unwrap();
+        assert!(diagnostics[0].message.ends_with("includes itself"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_directive_errors() {
+        let source =
+            ".endm\n.equ X 40000\n.macro M a\n.endm\nM\n.include lib.asm\n.bogus\n.macro OPEN\n";
+        let lines: Vec<_> = preprocess(source, None)
+            .err()
+            .unwrap()
+            .iter()
+            .map(|diag| diag.line)
+            .collect();
+        assert_eq!(lines, [1, 2, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn reports_shadowed_symbols() {
+        let source = ".equ SP 5\n.equ LOOP 3\n.equ N 2\n(LOOP)\n@LOOP\n0;JMP\n";
+        let messages: Vec<_> = preprocess(source, None)
+            .err()
+            .unwrap()
+            .iter()
+            .map(|diag| (diag.line, diag.message.clone()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (1, "Constant SP redefines a predefined symbol".into()),
+                (2, "Constant LOOP has the same name as a label".into()),
+            ]
+        );
+    }
+}
@@ -1,10 +1,10 @@
 // -- call {0} {1}
 /// 0 = function name
 /// 1 = arg number
-/// 2 = return number
+/// 2 = return label
 
 /// push retAddr
-@{0}$ret.{2}
+@{2}
 D=A
 @SP
 A=M
@@ -68,4 +68,4 @@ M=D
 @{0}
 0;JMP
 
-({0})
+({2})
@@ -22,6 +22,6 @@ M=M+1
 M=M-1
 D=M
 @{0}$__loop_start
-D;JLE
+D;JGT
 
 ({0}$__loop_end)
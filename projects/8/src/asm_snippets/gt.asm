@@ -1,18 +1,44 @@
 // -- gt
+/// x - y overflows when x and y have different signs, in which case the
+/// sign of x alone decides
 @SP
 M=M-1
-
 A=M
 D=M
+@__y
+M=D
 
 @SP
 M=M-1
 A=M
-M=M-D
 D=M
+@__x
+M=D
 
+@x_negative_{0}
+D;JLT
+@__y
+D=M
+@is_greater_{0}
+D;JLT
+@compare_{0}
+0;JMP
+
+(x_negative_{0})
+@__y
+D=M
+@not_greater_{0}
+D;JGE
+
+(compare_{0})
+@__x
+D=M
+@__y
+D=D-M
 @is_greater_{0}
 D;JGT
+
+(not_greater_{0})
 @SP
 A=M
 M=0
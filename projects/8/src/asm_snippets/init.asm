@@ -1,5 +1,2 @@
 // -- runtime initialization
-@256
-D=A
-@SP
-M=D
+{0}{1}
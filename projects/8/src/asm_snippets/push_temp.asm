@@ -2,7 +2,7 @@
 @5
 D=A
 @{0}
-D=D+M
+D=D+A
 
 A=D
 D=M
@@ -70,3 +70,8 @@ A=D
 D=M
 @LCL
 M=D
+
+/// goto RET
+@__ret
+A=M
+0;JMP
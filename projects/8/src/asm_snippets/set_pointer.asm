@@ -0,0 +1,5 @@
+// -- set {0}
+@{1}
+D=A
+@{0}
+M=D
@@ -0,0 +1,1236 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt, fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentAddr {
+    Constant(u16),
+    Static(u16),
+    Temp(u16),
+    Pointer(u16),
+    This(u16),
+    That(u16),
+    Local(u16),
+    Arg(u16),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inst {
+    Push(SegmentAddr),
+    Pop(SegmentAddr),
+    Add,
+    Sub,
+    Neg,
+    Eq,
+    Gt,
+    Lt,
+    And,
+    Or,
+    Not,
+    Label(String),
+    Goto(String),
+    IfGoto(String),
+    Function(String, u16),
+    Call(String, u16),
+    Return,
+}
+
+impl fmt::Display for SegmentAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (segment, index) = match self {
+            SegmentAddr::Constant(i) => ("constant", i),
+            SegmentAddr::Static(i) => ("static", i),
+            SegmentAddr::Temp(i) => ("temp", i),
+            SegmentAddr::Pointer(i) => ("pointer", i),
+            SegmentAddr::This(i) => ("this", i),
+            SegmentAddr::That(i) => ("that", i),
+            SegmentAddr::Local(i) => ("local", i),
+            SegmentAddr::Arg(i) => ("argument", i),
+        };
+        write!(f, "{segment} {index}")
+    }
+}
+
+/// Writes the command the way it is written in a `.vm` file.
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Inst::Push(segment) => write!(f, "push {segment}"),
+            Inst::Pop(segment) => write!(f, "pop {segment}"),
+            Inst::Add => write!(f, "add"),
+            Inst::Sub => write!(f, "sub"),
+            Inst::Neg => write!(f, "neg"),
+            Inst::Eq => write!(f, "eq"),
+            Inst::Gt => write!(f, "gt"),
+            Inst::Lt => write!(f, "lt"),
+            Inst::And => write!(f, "and"),
+            Inst::Or => write!(f, "or"),
+            Inst::Not => write!(f, "not"),
+            Inst::Label(label) => write!(f, "label {label}"),
+            Inst::Goto(label) => write!(f, "goto {label}"),
+            Inst::IfGoto(label) => write!(f, "if-goto {label}"),
+            Inst::Function(name, locals) => write!(f, "function {name} {locals}"),
+            Inst::Call(name, args) => write!(f, "call {name} {args}"),
+            Inst::Return => write!(f, "return"),
+        }
+    }
+}
+
+/// A command of a `.vm` file that couldn't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column of the word at fault.
+    pub column: usize,
+    pub message: String,
+}
+
+/// Writes `line:column: message`, to be prefixed with the file name.
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// A word of a command and its 1-based column.
+struct Word<'a> {
+    text: &'a str,
+    column: usize,
+}
+
+/// Splits a line into words, up to the `//` starting a comment.
+fn words(line: &str) -> Vec<Word<'_>> {
+    let code = line.find("//").map_or(line, |comment| &line[..comment]);
+    let mut words = Vec::new();
+    let mut start = None;
+    for (column, c) in code.chars().chain([' ']).enumerate() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(column),
+            (true, Some(first)) => {
+                let (from, to) = (char_offset(code, first), char_offset(code, column));
+                words.push(Word {
+                    text: &code[from..to],
+                    column: first + 1,
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+/// The byte offset of the `index`th character.
+fn char_offset(text: &str, index: usize) -> usize {
+    text.char_indices()
+        .nth(index)
+        .map_or(text.len(), |(at, _)| at)
+}
+
+pub struct Parser<'a> {
+    pub file: &'a Path,
+    pub tokens: Vec<Inst>,
+    /// The line and column of each command.
+    pub positions: Vec<(usize, usize)>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(vm_file: &'a Path) -> Self {
+        Self {
+            file: vm_file,
+            tokens: Vec::new(),
+            positions: Vec::new(),
+        }
+    }
+
+    /// Reads, parses and validates `file`, reporting every error found, one
+    /// per line and each prefixed with the file name.
+    pub fn parse(&mut self) -> Result<(), String> {
+        let vm_code = fs::read_to_string(self.file)
+            .map_err(|err| format!("could not read {}: {err}", self.file.display()))?;
+        self.parse_source(&vm_code)
+            .and_then(|()| self.validate())
+            .map_err(|errors| {
+                errors
+                    .iter()
+                    .map(|err| format!("{}:{err}", self.file.display()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+    }
+
+    /// Parses VM code that was not read from `file`, which still names the
+    /// statics. Every line is parsed, so that all of its errors are found.
+    pub fn parse_source(&mut self, vm_code: &str) -> Result<(), Vec<ParseError>> {
+        let mut errors = Vec::new();
+        for (row, line) in vm_code.lines().enumerate() {
+            let words = words(line);
+            if words.is_empty() {
+                continue;
+            }
+            match parse_command(&words) {
+                Ok(inst) => {
+                    self.tokens.push(inst);
+                    self.positions.push((row + 1, words[0].column));
+                }
+                Err((column, message)) => errors.push(ParseError {
+                    line: row + 1,
+                    column,
+                    message,
+                }),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Checks that every command parsed can be translated.
+    pub fn validate(&self) -> Result<(), Vec<ParseError>> {
+        let errors: Vec<_> = self
+            .tokens
+            .iter()
+            .zip(&self.positions)
+            .filter_map(|(inst, &(line, column))| {
+                let message = validate_inst(inst).err()?;
+                Some(ParseError {
+                    line,
+                    column,
+                    message,
+                })
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// The largest value `push constant` can push: A-instructions load 15 bits.
+pub const MAX_CONSTANT: u16 = 0x7FFF;
+/// Entries of the `temp` segment, at RAM[5..=12].
+pub const TEMP_SIZE: u16 = 8;
+/// The RAM addresses the assembler gives variables, statics included.
+pub const VARIABLES: RangeInclusive<u16> = 16..=255;
+/// Every snippet of translated code, for finding the variables they use.
+const SNIPPETS: [&str; 26] = [
+    include_str!("asm_snippets/add.asm"),
+    include_str!("asm_snippets/and.asm"),
+    include_str!("asm_snippets/call.asm"),
+    include_str!("asm_snippets/eq.asm"),
+    include_str!("asm_snippets/function.asm"),
+    include_str!("asm_snippets/goto.asm"),
+    include_str!("asm_snippets/gt.asm"),
+    include_str!("asm_snippets/if_goto.asm"),
+    include_str!("asm_snippets/init.asm"),
+    include_str!("asm_snippets/label.asm"),
+    include_str!("asm_snippets/lt.asm"),
+    include_str!("asm_snippets/neg.asm"),
+    include_str!("asm_snippets/not.asm"),
+    include_str!("asm_snippets/or.asm"),
+    include_str!("asm_snippets/pop_local_arg_this_that.asm"),
+    include_str!("asm_snippets/pop_pointer.asm"),
+    include_str!("asm_snippets/pop_static.asm"),
+    include_str!("asm_snippets/pop_temp.asm"),
+    include_str!("asm_snippets/push_constant.asm"),
+    include_str!("asm_snippets/push_local_arg_this_that.asm"),
+    include_str!("asm_snippets/push_pointer.asm"),
+    include_str!("asm_snippets/push_static.asm"),
+    include_str!("asm_snippets/push_temp.asm"),
+    include_str!("asm_snippets/return.asm"),
+    include_str!("asm_snippets/set_pointer.asm"),
+    include_str!("asm_snippets/sub.asm"),
+];
+
+/// The symbols the assembler predefines, besides `R0` to `R15`.
+const PREDEFINED_SYMBOLS: [&str; 7] = ["SP", "LCL", "ARG", "THIS", "THAT", "SCREEN", "KBD"];
+
+/// The variables of some assembly: the symbols it loads that are neither
+/// numbers, predefined symbols, nor labels it defines.
+fn variables(asm: &str) -> BTreeSet<&str> {
+    let labels: HashSet<_> = asm
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix('(')?.strip_suffix(')'))
+        .collect();
+    let is_register = |symbol: &str| {
+        symbol
+            .strip_prefix('R')
+            .and_then(|number| number.parse::<u8>().ok())
+            .is_some_and(|number| number < 16)
+    };
+    asm.lines()
+        .filter_map(|line| line.trim().strip_prefix('@'))
+        .filter(|symbol| {
+            !symbol.starts_with(|c: char| c.is_ascii_digit())
+                && !PREDEFINED_SYMBOLS.contains(symbol)
+                && !is_register(symbol)
+                && !labels.contains(symbol)
+        })
+        .collect()
+}
+
+/// Variables the translated code keeps in RAM besides the statics, such as
+/// `__frame`: the variables of the snippets, placeholders left out.
+pub fn translator_variables() -> BTreeSet<&'static str> {
+    SNIPPETS
+        .iter()
+        .flat_map(|snippet| variables(snippet))
+        .filter(|symbol| !symbol.contains('{'))
+        .collect()
+}
+
+/// Checks that a command can be translated: its segment has the entry it
+/// names, and constants fit in an A-instruction.
+pub fn validate_inst(inst: &Inst) -> Result<(), String> {
+    let (Inst::Push(segment) | Inst::Pop(segment)) = inst else {
+        return Ok(());
+    };
+    match *segment {
+        SegmentAddr::Constant(_) if matches!(inst, Inst::Pop(_)) => {
+            Err("can't pop to the constant segment".into())
+        }
+        SegmentAddr::Constant(value) if value > MAX_CONSTANT => Err(format!(
+            "constant {value} doesn't fit in 15 bits, the largest is {MAX_CONSTANT}"
+        )),
+        SegmentAddr::Pointer(index) if index > 1 => Err(format!(
+            "pointer {index} is out of range, pointer only has entries 0 and 1"
+        )),
+        SegmentAddr::Temp(index) if index >= TEMP_SIZE => Err(format!(
+            "temp {index} is out of range, temp only has entries 0 to {}",
+            TEMP_SIZE - 1
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the static variables of every file, which the assembler
+/// allocates along with the translator's own variables, fit in RAM[16..=255].
+pub fn validate_statics(parsers: &[Parser]) -> Result<(), String> {
+    let mut statics = HashSet::new();
+    for parser in parsers {
+        for inst in &parser.tokens {
+            if let Inst::Push(SegmentAddr::Static(index)) | Inst::Pop(SegmentAddr::Static(index)) =
+                inst
+            {
+                statics.insert((parser.file, *index));
+            }
+        }
+    }
+
+    let room = VARIABLES.len() - translator_variables().len();
+    if statics.len() > room {
+        return Err(format!(
+            "the program uses {} static variables, but only {room} fit in RAM[{}..={}]",
+            statics.len(),
+            VARIABLES.start(),
+            VARIABLES.end()
+        ));
+    }
+    Ok(())
+}
+
+/// Parses the words of a command, or returns the column of the word at fault
+/// and what is wrong with it.
+fn parse_command(words: &[Word]) -> Result<Inst, (usize, String)> {
+    let command = &words[0];
+    let args = &words[1..];
+    let arity = |count: usize| {
+        if args.len() == count {
+            return Ok(());
+        }
+        let column = args.get(count).map_or(command.column, |extra| extra.column);
+        Err((
+            column,
+            format!(
+                "`{}` expects {count} argument{}, found {}",
+                command.text,
+                if count == 1 { "" } else { "s" },
+                args.len()
+            ),
+        ))
+    };
+    let number = |word: &Word, what: &str| {
+        word.text.parse::<u16>().map_err(|_| {
+            (
+                word.column,
+                format!("expected {what}, found `{}`", word.text),
+            )
+        })
+    };
+    let segment = || {
+        arity(2)?;
+        let index = number(&args[1], "an index")?;
+        Ok(match args[0].text {
+            "constant" => SegmentAddr::Constant(index),
+            "static" => SegmentAddr::Static(index),
+            "temp" => SegmentAddr::Temp(index),
+            "pointer" => SegmentAddr::Pointer(index),
+            "this" => SegmentAddr::This(index),
+            "that" => SegmentAddr::That(index),
+            "local" => SegmentAddr::Local(index),
+            "argument" => SegmentAddr::Arg(index),
+            invalid => return Err((args[0].column, format!("`{invalid}` is not a segment"))),
+        })
+    };
+    let symbol = || {
+        arity(1)?;
+        Ok(args[0].text.to_string())
+    };
+    let nullary = |inst: Inst| arity(0).map(|()| inst);
+
+    match command.text {
+        "push" => Ok(Inst::Push(segment()?)),
+        "pop" => Ok(Inst::Pop(segment()?)),
+
+        "add" => nullary(Inst::Add),
+        "sub" => nullary(Inst::Sub),
+        "neg" => nullary(Inst::Neg),
+        "eq" => nullary(Inst::Eq),
+        "or" => nullary(Inst::Or),
+        "and" => nullary(Inst::And),
+        "not" => nullary(Inst::Not),
+        "gt" => nullary(Inst::Gt),
+        "lt" => nullary(Inst::Lt),
+
+        "label" => Ok(Inst::Label(symbol()?)),
+        "goto" => Ok(Inst::Goto(symbol()?)),
+        "if-goto" => Ok(Inst::IfGoto(symbol()?)),
+
+        "function" => {
+            arity(2)?;
+            let locals = number(&args[1], "a number of local variables")?;
+            Ok(Inst::Function(args[0].text.into(), locals))
+        }
+        "call" => {
+            arity(2)?;
+            let arguments = number(&args[1], "a number of arguments")?;
+            Ok(Inst::Call(args[0].text.into(), arguments))
+        }
+        "return" => nullary(Inst::Return),
+
+        invalid => Err((command.column, format!("`{invalid}` is not a command"))),
+    }
+}
+
+/// The label the bootstrap code's call to `Sys.init` returns to.
+const BOOTSTRAP_RETURN: &str = "__bootstrap$ret";
+
+/// Hands out the assembly labels of a program's translation. VM labels are
+/// scoped to their function as `Function$label`, and the labels the
+/// translator makes up are unique program-wide. Every label defined is
+/// recorded, so that a collision, such as a VM label named like one the
+/// translator made up, is reported instead of assembled.
+#[derive(Debug)]
+pub struct Labels {
+    /// The function, or outside of functions the file, labels belong to.
+    scope: String,
+    /// Calls translated so far in `scope`.
+    calls: usize,
+    /// Comparisons translated so far in the program.
+    comparisons: usize,
+    defined: HashSet<String>,
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Self {
+            scope: String::new(),
+            calls: 0,
+            comparisons: 0,
+            defined: HashSet::from([BOOTSTRAP_RETURN.to_string()]),
+        }
+    }
+}
+
+impl Labels {
+    /// Starts translating a file: up to its first `function`, its labels are
+    /// scoped to the file.
+    pub fn enter_file(&mut self, filename: &str) {
+        self.scope = filename.to_string();
+        self.calls = 0;
+    }
+
+    fn enter_function(&mut self, name: &str) {
+        self.scope = name.to_string();
+        self.calls = 0;
+    }
+
+    /// The assembly label of a VM label of the current scope.
+    pub fn scoped(&self, label: &str) -> String {
+        format!("{}${label}", self.scope)
+    }
+
+    /// The label a call returns to, `Caller$ret.i` for the `i`th call of the
+    /// calling function.
+    fn return_label(&mut self) -> String {
+        self.calls += 1;
+        format!("{}$ret.{}", self.scope, self.calls - 1)
+    }
+
+    fn comparison(&mut self) -> usize {
+        self.comparisons += 1;
+        self.comparisons - 1
+    }
+
+    /// Records the labels `asm` defines, failing if one was defined before.
+    fn define(&mut self, asm: &str) -> Result<(), String> {
+        let labels = asm
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix('(')?.strip_suffix(')'));
+        for label in labels {
+            if !self.defined.insert(label.to_string()) {
+                return Err(format!("the assembly label {label} is defined twice"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The pointers a bootstrap can set, in the order they are in RAM.
+pub const POINTERS: [&str; 5] = ["SP", "LCL", "ARG", "THIS", "THAT"];
+
+/// Where the stack starts.
+pub const STACK_BASE: u16 = 256;
+
+/// The code run before the program's first command: it sets some of the
+/// pointers, then calls the entry function. The default is the course's
+/// bootstrap, setting SP to 256 and calling `Sys.init`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bootstrap {
+    pub entry: String,
+    /// The initial value of each of [`POINTERS`], if it's set.
+    pub pointers: [Option<u16>; 5],
+}
+
+impl Default for Bootstrap {
+    fn default() -> Self {
+        Self::calling("Sys.init")
+    }
+}
+
+impl Bootstrap {
+    /// Sets the stack pointer to 256 and calls `entry`.
+    pub fn calling(entry: &str) -> Self {
+        Self {
+            entry: entry.to_string(),
+            pointers: [Some(STACK_BASE), None, None, None, None],
+        }
+    }
+
+    pub fn code(&self) -> String {
+        let set_pointers: String = POINTERS
+            .iter()
+            .zip(self.pointers)
+            .filter_map(|(pointer, value)| {
+                Some(format!(
+                    include_str!("asm_snippets/set_pointer.asm"),
+                    pointer, value?
+                ))
+            })
+            .collect();
+        let call_entry = format!(
+            include_str!("asm_snippets/call.asm"),
+            self.entry, 0, BOOTSTRAP_RETURN
+        );
+        format!(
+            include_str!("asm_snippets/init.asm"),
+            set_pointers, call_entry
+        )
+    }
+}
+
+/// The code setting the stack pointer to 256 and calling `Sys.init`.
+pub fn generate_bootstrap_code() -> String {
+    Bootstrap::default().code()
+}
+
+/// Translates a single command of `filename`. Fails if the command is
+/// invalid, see [`validate_inst`], or a label it defines collides with one
+/// translated before.
+pub fn generate_inst_code(
+    inst: &Inst,
+    filename: &str,
+    labels: &mut Labels,
+) -> Result<String, String> {
+    validate_inst(inst)?;
+
+    let asm = match inst {
+        Inst::Push(push) => match push {
+            SegmentAddr::Constant(arg) => {
+                format!(include_str!("asm_snippets/push_constant.asm"), arg)
+            }
+
+            SegmentAddr::Static(arg) => {
+                format!(include_str!("asm_snippets/push_static.asm"), filename, arg)
+            }
+
+            SegmentAddr::Temp(arg) => {
+                format!(include_str!("asm_snippets/push_temp.asm"), arg)
+            }
+
+            SegmentAddr::Pointer(arg) => {
+                let addr = match arg {
+                    0 => "THIS",
+                    1 => "THAT",
+                    _ => unreachable!("rejected by validate_inst"),
+                };
+
+                format!(include_str!("asm_snippets/push_pointer.asm"), addr)
+            }
+
+            ref segment @ (SegmentAddr::Local(arg)
+            | SegmentAddr::Arg(arg)
+            | SegmentAddr::This(arg)
+            | SegmentAddr::That(arg)) => {
+                let base_addr = match segment {
+                    SegmentAddr::Local(_) => "LCL",
+                    SegmentAddr::Arg(_) => "ARG",
+                    SegmentAddr::This(_) => "THIS",
+                    SegmentAddr::That(_) => "THAT",
+                    _ => unreachable!(),
+                };
+
+                format!(
+                    include_str!("asm_snippets/push_local_arg_this_that.asm"),
+                    base_addr, arg
+                )
+            }
+        },
+
+        Inst::Pop(pop) => match pop {
+            SegmentAddr::Constant(_) => unreachable!("rejected by validate_inst"),
+
+            SegmentAddr::Static(arg) => {
+                format!(include_str!("asm_snippets/pop_static.asm"), filename, arg)
+            }
+
+            SegmentAddr::Temp(arg) => {
+                format!(include_str!("asm_snippets/pop_temp.asm"), arg)
+            }
+
+            SegmentAddr::Pointer(arg) => {
+                let addr = match arg {
+                    0 => "THIS",
+                    1 => "THAT",
+                    _ => unreachable!("rejected by validate_inst"),
+                };
+
+                format!(include_str!("asm_snippets/pop_pointer.asm"), addr)
+            }
+
+            ref segment @ (SegmentAddr::Local(arg)
+            | SegmentAddr::Arg(arg)
+            | SegmentAddr::This(arg)
+            | SegmentAddr::That(arg)) => {
+                let base_addr = match segment {
+                    SegmentAddr::Local(_) => "LCL",
+                    SegmentAddr::Arg(_) => "ARG",
+                    SegmentAddr::This(_) => "THIS",
+                    SegmentAddr::That(_) => "THAT",
+                    _ => unreachable!(),
+                };
+
+                format!(
+                    include_str!("asm_snippets/pop_local_arg_this_that.asm"),
+                    base_addr, arg
+                )
+            }
+        },
+
+        Inst::Add => include_str!("asm_snippets/add.asm").to_string(),
+        Inst::Sub => include_str!("asm_snippets/sub.asm").to_string(),
+        Inst::Neg => include_str!("asm_snippets/neg.asm").to_string(),
+        Inst::Eq => format!(include_str!("asm_snippets/eq.asm"), labels.comparison()),
+        Inst::Gt => format!(include_str!("asm_snippets/gt.asm"), labels.comparison()),
+        Inst::Lt => format!(include_str!("asm_snippets/lt.asm"), labels.comparison()),
+        Inst::And => include_str!("asm_snippets/and.asm").to_string(),
+        Inst::Or => include_str!("asm_snippets/or.asm").to_string(),
+        Inst::Not => include_str!("asm_snippets/not.asm").to_string(),
+
+        Inst::Goto(label) => format!(include_str!("asm_snippets/goto.asm"), labels.scoped(label)),
+        Inst::IfGoto(label) => format!(
+            include_str!("asm_snippets/if_goto.asm"),
+            labels.scoped(label)
+        ),
+        Inst::Label(name) => format!(include_str!("asm_snippets/label.asm"), labels.scoped(name)),
+
+        Inst::Function(name, vars_no) => {
+            labels.enter_function(name);
+            format!(include_str!("asm_snippets/function.asm"), name, vars_no)
+        }
+        Inst::Return => include_str!("asm_snippets/return.asm").to_string(),
+        Inst::Call(name, args_no) => format!(
+            include_str!("asm_snippets/call.asm",),
+            name,
+            args_no,
+            labels.return_label()
+        ),
+    };
+
+    labels.define(&asm)?;
+    Ok(asm)
+}
+
+/// Translates the commands of a single file, reporting the first label
+/// collision with the line and column of the command at fault.
+pub fn generate_vm_code(parser: &Parser, labels: &mut Labels) -> Result<String, String> {
+    let filename = file_name(parser.file);
+    labels.enter_file(filename);
+
+    let mut asm = String::new();
+    for (i, inst) in parser.tokens.iter().enumerate() {
+        let code = generate_inst_code(inst, filename, labels).map_err(|err| {
+            let (line, column) = parser.positions.get(i).copied().unwrap_or_default();
+            format!("{}:{line}:{column}: {err}", parser.file.display())
+        })?;
+        asm.push_str(&code);
+    }
+
+    Ok(asm)
+}
+
+/// Translates a whole program, file by file, after its bootstrap code if it
+/// has one. The files must be [`link`]ed.
+pub fn generate_program_code(
+    parsers: &[Parser],
+    bootstrap: Option<&Bootstrap>,
+) -> Result<String, String> {
+    let mut asm = bootstrap.map_or(String::new(), Bootstrap::code);
+    let mut labels = Labels::default();
+    for parser in parsers {
+        asm.push_str(&generate_vm_code(parser, &mut labels)?);
+    }
+    Ok(asm)
+}
+
+/// The class a file's statics and functions belong to: its name without the
+/// `.vm` extension.
+fn file_name(file: &Path) -> &str {
+    file.file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default()
+}
+
+/// The `.vm` files of a directory, sorted by name so that the translation
+/// doesn't depend on the order the file system lists them in.
+pub fn vm_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|err| format!("could not read {}: {err}", dir.display()))?;
+    let mut files: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "vm"))
+        .collect();
+    files.sort();
+
+    if files.is_empty() {
+        return Err(format!("{} has no .vm files", dir.display()));
+    }
+    Ok(files)
+}
+
+/// Where the translation of a `.vm` file or a directory of them goes:
+/// `Foo.vm` becomes `Foo.asm`, and `Foo/` becomes `Foo/Foo.asm`.
+pub fn output_path(input: &Path) -> Result<PathBuf, String> {
+    if !input.is_dir() {
+        return Ok(input.with_extension("asm"));
+    }
+    // `.` and `..` have no name of their own
+    let dir = input
+        .canonicalize()
+        .map_err(|err| format!("could not read {}: {err}", input.display()))?;
+    let name = dir
+        .file_name()
+        .ok_or(format!("{} has no name to give the output", dir.display()))?;
+    Ok(input.join(name).with_extension("asm"))
+}
+
+/// Checks the files of a program together: functions are defined once, jumps
+/// go to labels of their function, every `call`, the bootstrap's included,
+/// calls a function one of them defines, and the statics fit in RAM. A
+/// program with a bootstrap must define its entry function, `Sys.init` by
+/// default. Every error found is reported, one per line.
+pub fn link(parsers: &[Parser], bootstrap: Option<&Bootstrap>) -> Result<(), String> {
+    let at = |parser: &Parser, i: usize| {
+        let (line, column) = parser.positions.get(i).copied().unwrap_or_default();
+        format!("{}:{line}:{column}", parser.file.display())
+    };
+
+    let mut errors = Vec::new();
+    let mut functions = HashMap::new();
+    for parser in parsers {
+        for (i, inst) in parser.tokens.iter().enumerate() {
+            let Inst::Function(name, _) = inst else {
+                continue;
+            };
+            let here = at(parser, i);
+            if let Some(first) = functions.insert(name.as_str(), here.clone()) {
+                errors.push(format!(
+                    "{here}: function {name} is already defined at {first}"
+                ));
+            }
+        }
+    }
+
+    // VM labels belong to the function they're in, or outside of functions
+    // to the file
+    for parser in parsers {
+        let mut scopes = Vec::with_capacity(parser.tokens.len());
+        let mut scope = file_name(parser.file);
+        for inst in &parser.tokens {
+            if let Inst::Function(name, _) = inst {
+                scope = name;
+            }
+            scopes.push(scope);
+        }
+        let defined: HashSet<_> = parser
+            .tokens
+            .iter()
+            .zip(&scopes)
+            .filter_map(|(inst, scope)| match inst {
+                Inst::Label(label) => Some((*scope, label)),
+                _ => None,
+            })
+            .collect();
+
+        for (i, (inst, scope)) in parser.tokens.iter().zip(&scopes).enumerate() {
+            if let Inst::Goto(label) | Inst::IfGoto(label) = inst {
+                if !defined.contains(&(*scope, label)) {
+                    errors.push(format!(
+                        "{}: label {label} is not defined in {scope}",
+                        at(parser, i)
+                    ));
+                }
+            }
+        }
+    }
+
+    for parser in parsers {
+        for (i, inst) in parser.tokens.iter().enumerate() {
+            if let Inst::Call(name, _) = inst {
+                if !functions.contains_key(name.as_str()) {
+                    errors.push(format!(
+                        "{}: call to {name}, which no file defines",
+                        at(parser, i)
+                    ));
+                }
+            }
+        }
+    }
+
+    if let Some(Bootstrap { entry, .. }) = bootstrap {
+        if !functions.contains_key(entry.as_str()) {
+            errors.push(format!(
+                "the bootstrap calls {entry}, which no file defines"
+            ));
+        }
+    }
+    if let Err(err) = validate_statics(parsers) {
+        errors.push(err);
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu_emulator::machine::{Machine, Stop};
+
+    fn parse(vm_code: &str) -> Result<Vec<Inst>, Vec<ParseError>> {
+        let mut parser = Parser::new(Path::new("Main.vm"));
+        parser.parse_source(vm_code).map(|()| parser.tokens)
+    }
+
+    #[test]
+    fn parses_commands_and_comments() {
+        let insts = parse(
+            "// a comment\n\
+             function Main.main 2 // two locals\n\
+             \tpush  constant 7//no space\n\
+             pop local 1\n\
+             label LOOP\n\
+             call Math.max 2\n\
+             return\n",
+        )
+        .unwrap();
+        assert_eq!(
+            insts,
+            [
+                Inst::Function("Main.main".into(), 2),
+                Inst::Push(SegmentAddr::Constant(7)),
+                Inst::Pop(SegmentAddr::Local(1)),
+                Inst::Label("LOOP".into()),
+                Inst::Call("Math.max".into(), 2),
+                Inst::Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let errors = parse(
+            "push constant\n\
+             push local x\n\
+             add 1\n\
+             \n  pop heap 3\n\
+             push\tthat 0 0\n\
+             goto\n\
+             function Main.main -1\n\
+             jump END\n\
+             push constant 70000\n",
+        )
+        .unwrap_err();
+        let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                "1:1: `push` expects 2 arguments, found 1",
+                "2:12: expected an index, found `x`",
+                "3:5: `add` expects 0 arguments, found 1",
+                "5:7: `heap` is not a segment",
+                "6:13: `push` expects 2 arguments, found 3",
+                "7:1: `goto` expects 1 argument, found 0",
+                "8:20: expected a number of local variables, found `-1`",
+                "9:1: `jump` is not a command",
+                "10:15: expected an index, found `70000`",
+            ]
+        );
+    }
+
+    #[test]
+    fn validates_segments() {
+        let mut parser = Parser::new(Path::new("Main.vm"));
+        parser
+            .parse_source(
+                "push pointer 1\npush pointer 2\n  pop constant 0\npush temp 7\n\
+                 pop temp 8\npush constant 32767\npush constant 32768\n",
+            )
+            .unwrap();
+        let errors: Vec<_> = parser
+            .validate()
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "2:1: pointer 2 is out of range, pointer only has entries 0 and 1",
+                "3:3: can't pop to the constant segment",
+                "5:1: temp 8 is out of range, temp only has entries 0 to 7",
+                "7:1: constant 32768 doesn't fit in 15 bits, the largest is 32767",
+            ]
+        );
+    }
+
+    fn parser<'a>(file: &'a str, vm_code: &str) -> Parser<'a> {
+        let mut parser = Parser::new(Path::new(file));
+        parser.parse_source(vm_code).unwrap();
+        parser
+    }
+
+    #[test]
+    fn links_the_files_of_a_program() {
+        let program = [
+            parser("Main.vm", "function Main.main 0\ncall Sys.halt 0\nreturn\n"),
+            parser(
+                "Sys.vm",
+                "function Sys.init 0\ncall Main.main 0\nfunction Sys.halt 0\n",
+            ),
+        ];
+        assert_eq!(link(&program, Some(&Bootstrap::default())), Ok(()));
+
+        let asm = generate_program_code(&program, Some(&Bootstrap::default())).unwrap();
+        assert_eq!(asm.matches("// -- runtime initialization").count(), 1);
+        assert!(asm.find("(Main.main)") < asm.find("(Sys.init)"));
+
+        let broken = [
+            parser("Main.vm", "function Main.main 0\n  call Math.max 2\n"),
+            parser("Sys.vm", "function Sys.init 0\nfunction Main.main 1\n"),
+        ];
+        assert_eq!(
+            link(&broken, Some(&Bootstrap::default())),
+            Err(
+                "Sys.vm:2:1: function Main.main is already defined at Main.vm:1:1\n\
+                 Main.vm:2:3: call to Math.max, which no file defines"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn scopes_labels_to_their_function() {
+        let program = [
+            parser(
+                "Main.vm",
+                "function Main.main 0\nlabel LOOP\npush constant 1\npush constant 2\neq\n\
+                 if-goto LOOP\ncall Main.main 0\ncall Main.main 0\n",
+            ),
+            parser(
+                "Sys.vm",
+                "function Sys.init 0\nlabel LOOP\npush constant 1\npush constant 2\neq\n\
+                 goto LOOP\ncall Main.main 0\n",
+            ),
+        ];
+        assert_eq!(link(&program, Some(&Bootstrap::default())), Ok(()));
+
+        let asm = generate_program_code(&program, Some(&Bootstrap::default())).unwrap();
+        let labels: Vec<_> = asm
+            .lines()
+            .filter(|line| line.starts_with('(') && !line.contains("loop"))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                "(__bootstrap$ret)",
+                "(Main.main)",
+                "(Main.main$LOOP)",
+                "(is_equal_0)",
+                "(end_block_0)",
+                "(Main.main$ret.0)",
+                "(Main.main$ret.1)",
+                "(Sys.init)",
+                "(Sys.init$LOOP)",
+                "(is_equal_1)",
+                "(end_block_1)",
+                "(Sys.init$ret.0)",
+            ]
+        );
+        assert!(asm.contains("@Main.main$LOOP\nD;JNE"));
+        assert!(asm.contains("@Sys.init$LOOP\n0;JMP"));
+
+        let undefined = [parser(
+            "Main.vm",
+            "label END\nfunction Main.main 0\ngoto END\n",
+        )];
+        assert_eq!(
+            link(&undefined, None),
+            Err("Main.vm:3:1: label END is not defined in Main.main".into())
+        );
+    }
+
+    #[test]
+    fn reports_label_collisions() {
+        // the label of the second call collides with a VM label
+        let program = [parser(
+            "Main.vm",
+            "function Main.main 0\nlabel ret.1\ncall Main.main 0\ncall Main.main 0\n",
+        )];
+        assert_eq!(link(&program, None), Ok(()));
+        assert_eq!(
+            generate_program_code(&program, None),
+            Err("Main.vm:4:1: the assembly label Main.main$ret.1 is defined twice".into())
+        );
+    }
+
+    #[test]
+    fn bootstraps_programs_with_sys_init() {
+        let simple = [parser("Simple.vm", "push constant 1\n")];
+        assert_eq!(
+            generate_program_code(&simple, None),
+            Ok(include_str!("asm_snippets/push_constant.asm").replace("{0}", "1"))
+        );
+
+        // without Sys.init, the stack pointer would never be set
+        let program = [
+            parser("Main.vm", "function Main.main 0\ncall Main.helper 0\n"),
+            parser(
+                "Helper.vm",
+                "function Main.helper 0\npush constant 1\nreturn\n",
+            ),
+        ];
+        assert_eq!(link(&program, None), Ok(()));
+        assert_eq!(
+            link(&program, Some(&Bootstrap::default())),
+            Err("the bootstrap calls Sys.init, which no file defines".into())
+        );
+
+        let custom = Bootstrap {
+            entry: "Main.main".into(),
+            pointers: [Some(300), None, None, Some(3000), None],
+        };
+        let code = custom.code();
+        assert!(code.starts_with(
+            "// -- runtime initialization\n\
+             // -- set SP\n@300\nD=A\n@SP\nM=D\n\
+             // -- set THIS\n@3000\nD=A\n@THIS\nM=D\n\
+             // -- call Main.main 0\n"
+        ));
+        assert_eq!(link(&program, Some(&custom)), Ok(()));
+        assert_eq!(
+            link(&simple, Some(&custom)),
+            Err("the bootstrap calls Main.main, which no file defines".into())
+        );
+    }
+
+    #[test]
+    fn names_the_output_after_the_input() {
+        assert_eq!(
+            output_path(Path::new("ProgramFlow/BasicLoop/BasicLoop.vm")),
+            Ok(PathBuf::from("ProgramFlow/BasicLoop/BasicLoop.asm"))
+        );
+        assert_eq!(
+            output_path(Path::new("FunctionCalls/StaticsTest")),
+            Ok(PathBuf::from("FunctionCalls/StaticsTest/StaticsTest.asm"))
+        );
+        assert_eq!(
+            vm_files(Path::new("FunctionCalls/StaticsTest")).unwrap(),
+            ["Class1.vm", "Class2.vm", "Sys.vm"]
+                .map(|file| Path::new("FunctionCalls/StaticsTest").join(file))
+        );
+    }
+
+    #[test]
+    fn refuses_to_translate_invalid_commands() {
+        // commands the parser wouldn't produce, built by a library caller
+        let mut parser = Parser::new(Path::new("Main.vm"));
+        for inst in [
+            Inst::Pop(SegmentAddr::Constant(0)),
+            Inst::Push(SegmentAddr::Pointer(2)),
+            Inst::Pop(SegmentAddr::Pointer(2)),
+        ] {
+            parser.tokens = vec![inst];
+            assert!(generate_vm_code(&parser, &mut Labels::default()).is_err());
+        }
+
+        parser.tokens = vec![Inst::Pop(SegmentAddr::Constant(0))];
+        let err = generate_vm_code(&parser, &mut Labels::default()).unwrap_err();
+        assert!(err.ends_with("can't pop to the constant segment"), "{err}");
+    }
+
+    #[test]
+    fn validates_statics_of_every_file() {
+        let parse = |file, statics: std::ops::Range<u16>| {
+            let mut parser = Parser::new(Path::new(file));
+            let vm_code: String = statics.map(|i| format!("push static {i}\n")).collect();
+            parser.parse_source(&vm_code).unwrap();
+            parser
+        };
+        // a static used twice takes a single address
+        let full = [
+            parse("A.vm", 0..200),
+            parse("B.vm", 0..34),
+            parse("B.vm", 0..34),
+        ];
+        assert_eq!(validate_statics(&full), Ok(()));
+
+        let overflow = [parse("A.vm", 0..200), parse("B.vm", 0..35)];
+        assert_eq!(
+            validate_statics(&overflow),
+            Err("the program uses 235 static variables, but only 234 fit in RAM[16..=255]".into())
+        );
+    }
+
+    #[test]
+    fn counts_the_variables_of_the_snippets() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/asm_snippets");
+        assert_eq!(fs::read_dir(dir).unwrap().count(), SNIPPETS.len());
+        assert_eq!(
+            translator_variables(),
+            BTreeSet::from(["__frame", "__ret", "__x", "__y", "addr", "count"])
+        );
+
+        // a program with as many statics as fit, using every command that
+        // needs a variable, uses every variable address there is
+        let mut main = Parser::new(Path::new("Main.vm"));
+        main.parse_source(
+            "function Main.f 2\npush constant 1\npush constant 2\nlt\npush constant 3\ngt\n\
+             pop local 1\ncall Main.f 0\nreturn\n",
+        )
+        .unwrap();
+        let mut statics = Parser::new(Path::new("Statics.vm"));
+        let room = VARIABLES.len() - translator_variables().len();
+        let vm_code: String = (0..room).map(|i| format!("push static {i}\n")).collect();
+        statics.parse_source(&vm_code).unwrap();
+        let program = [main, statics];
+        assert_eq!(validate_statics(&program), Ok(()));
+
+        let asm = generate_program_code(&program, None).unwrap();
+        assert_eq!(variables(&asm).len(), VARIABLES.len());
+    }
+
+    /// Translates `vm_code` as Main.vm, without the bootstrap, and runs it
+    /// with SP at 256 until it halts: at a `goto` to the label right before
+    /// it, or at the end of its code.
+    fn run(vm_code: &str) -> Machine {
+        let mut parser = Parser::new(Path::new("Main.vm"));
+        parser.parse_source(vm_code).unwrap();
+
+        let code = generate_vm_code(&parser, &mut Labels::default()).unwrap();
+        let asm = format!("@256\nD=A\n@SP\nM=D\n{code}(__END)\n@__END\n0;JMP\n");
+
+        let program = assembler::assemble_source(&asm).unwrap();
+        let mut machine = Machine::new();
+        machine.load(&program.instructions);
+        assert_eq!(machine.run(10_000), Ok(Stop::Halted));
+        machine
+    }
+
+    /// The stack from RAM[256] up to SP.
+    fn stack(machine: &Machine) -> Vec<i16> {
+        let sp = machine.ram()[0] as usize;
+        machine.ram()[256..sp]
+            .iter()
+            .map(|&word| word as i16)
+            .collect()
+    }
+
+    #[test]
+    fn compares_operands_whose_difference_overflows() {
+        // -32768 compared with 1: x - y overflows to 32767
+        let machine = run(
+            "push constant 32767\nneg\npush constant 1\nsub\npop static 0\n\
+             push static 0\npush constant 1\ngt\n\
+             push static 0\npush constant 1\nlt\n\
+             push constant 1\npush static 0\ngt\n",
+        );
+        assert_eq!(stack(&machine), [0, -1, -1]);
+    }
+
+    #[test]
+    fn pushes_temp() {
+        // with THIS set, an address computed from RAM[3] rather than 3 is off
+        let machine =
+            run("push constant 3000\npop pointer 0\npush constant 7\npop temp 3\npush temp 3\n");
+        assert_eq!(machine.ram()[8], 7);
+        assert_eq!(stack(&machine), [7]);
+    }
+
+    #[test]
+    fn pushes_every_local() {
+        let machine = run("function Main.f 3\npush constant 1\n");
+        assert_eq!(stack(&machine), [0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn returns_to_the_caller() {
+        // the callee is followed by another function, which return must not
+        // fall through into
+        let machine = run("push constant 5\ncall Main.inc 1\nlabel END\ngoto END\n\
+             function Main.inc 0\npush argument 0\npush constant 1\nadd\nreturn\n\
+             function Main.other 0\npush constant 9\nreturn\n");
+        assert_eq!(stack(&machine), [6]);
+    }
+
+    #[test]
+    fn returns_to_each_call_site() {
+        let machine = run(
+            "call Main.one 0\ncall Main.one 0\nadd\nlabel END\ngoto END\n\
+             function Main.one 0\npush constant 1\nreturn\n",
+        );
+        assert_eq!(stack(&machine), [2]);
+    }
+}
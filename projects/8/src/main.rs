@@ -1,307 +1,192 @@
-use std::{
-    env, fs,
-    path::{Path, PathBuf},
-    process::ExitCode,
+use std::{env, fs, path::PathBuf, process::ExitCode};
+use vm_translator::{
+    generate_program_code, link, output_path, vm_files, Bootstrap, Parser, MAX_CONSTANT, POINTERS,
 };
 
-#[derive(Debug)]
-enum SegmentAddr {
-    Constant(u16),
-    Static(u16),
-    Temp(u16),
-    Pointer(u16),
-    This(u16),
-    That(u16),
-    Local(u16),
-    Arg(u16),
+const USAGE: &str = "\
+Usage: vm_translator [OPTIONS] <FILE.vm|DIR>
+
+Translates a VM file, or every .vm file of a directory, to Hack assembly:
+Foo.vm becomes Foo.asm and Foo/ becomes Foo/Foo.asm. The program starts
+with bootstrap code setting SP to 256 and calling Sys.init, which one of its
+files must define.
+
+Options:
+  --no-bootstrap  Leave the bootstrap out: the program starts with the first
+                  command of the first file. The options below set up the
+                  bootstrap, so they can't be combined with it.
+  --entry <NAME>  Have the bootstrap call NAME instead of Sys.init
+  --sp <N>        Have the bootstrap set SP to N instead of 256, as well as
+  --lcl <N>       LCL, ARG, THIS and THAT, which it leaves alone otherwise
+  --arg <N>
+  --this <N>
+  --that <N>
+  -h, --help      Print this help";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Options {
+    input: PathBuf,
+    no_bootstrap: bool,
+    entry: Option<String>,
+    pointers: [Option<u16>; 5],
+    help: bool,
 }
 
-#[derive(Debug)]
-enum Inst {
-    Push(SegmentAddr),
-    Pop(SegmentAddr),
-    Add,
-    Sub,
-    Neg,
-    Eq,
-    Gt,
-    Lt,
-    And,
-    Or,
-    Not,
-    Label(String),
-    Goto(String),
-    IfGoto(String),
-    Function(String, u16),
-    Call(String, u16),
-    Return,
+impl Options {
+    /// The bootstrap the options ask for, if any.
+    fn bootstrap(&self) -> Option<Bootstrap> {
+        if self.no_bootstrap {
+            return None;
+        }
+        let mut bootstrap = match &self.entry {
+            Some(entry) => Bootstrap::calling(entry),
+            None => Bootstrap::default(),
+        };
+        for (pointer, value) in bootstrap.pointers.iter_mut().zip(self.pointers) {
+            *pointer = value.or(*pointer);
+        }
+        Some(bootstrap)
+    }
 }
 
-struct Parser<'a> {
-    file: &'a Path,
-    tokens: Vec<Inst>,
-}
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut input = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{arg} expects a value"));
+        let pointer = POINTERS
+            .iter()
+            .position(|pointer| arg.strip_prefix("--") == Some(&pointer.to_lowercase()));
+
+        match arg.as_str() {
+            "--no-bootstrap" => options.no_bootstrap = true,
+            "--entry" => options.entry = Some(value()?),
+            "-h" | "--help" => options.help = true,
+            _ if pointer.is_some() => {
+                let address = value()?
+                    .parse()
+                    .ok()
+                    .filter(|address| *address <= MAX_CONSTANT)
+                    .ok_or(format!("{arg} expects an address from 0 to {MAX_CONSTANT}"))?;
+                options.pointers[pointer.unwrap()] = Some(address);
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {arg}")),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument {arg}")),
+        }
+    }
 
-impl<'a> Parser<'a> {
-    fn new(vm_file: &'a Path) -> Self {
-        Self {
-            file: vm_file,
-            tokens: Vec::new(),
+    if options.help {
+        return Ok(options);
+    }
+    if options.no_bootstrap {
+        if options.entry.is_some() {
+            return Err("--entry names the function the bootstrap calls: it needs one".into());
+        }
+        if let Some(index) = options.pointers.iter().position(Option::is_some) {
+            return Err(format!(
+                "--{} sets a pointer in the bootstrap: it needs one",
+                POINTERS[index].to_lowercase()
+            ));
         }
     }
+    options.input = input.ok_or("A VM file or directory was not provided.")?;
+    Ok(options)
+}
 
-    fn parse(&mut self) -> Result<(), String> {
-        let vm_code = fs::read_to_string(self.file).unwrap();
+fn main() -> ExitCode {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{msg}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if options.help {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
 
-        for (linenum, line) in vm_code.lines().enumerate() {
-            let line = line.trim();
-            if line.starts_with("//") || line.is_empty() {
-                continue;
+    let input = &options.input;
+    let files = if input.is_dir() {
+        match vm_files(input) {
+            Ok(files) => files,
+            Err(err) => {
+                eprintln!("{err}");
+                return ExitCode::FAILURE;
             }
-
-            let line_parts: Vec<_> = line.split_whitespace().map(|part| part.trim()).collect();
-
-            self.tokens.push(match line_parts[0] {
-                "push" => {
-                    let arg = line_parts[2].parse::<_>().unwrap();
-                    Inst::Push(match line_parts[1] {
-                        "constant" => SegmentAddr::Constant(arg),
-                        "static" => SegmentAddr::Static(arg),
-                        "temp" => SegmentAddr::Temp(arg),
-                        "pointer" => SegmentAddr::Pointer(arg),
-                        "this" => SegmentAddr::This(arg),
-                        "that" => SegmentAddr::That(arg),
-                        "local" => SegmentAddr::Local(arg),
-                        "argument" => SegmentAddr::Arg(arg),
-                        invalid => {
-                            return Err(format!(
-                                "push on line {linenum} has invalid segment \"{invalid}\"",
-                            ));
-                        }
-                    })
-                }
-
-                "pop" => {
-                    let arg = line_parts[2].parse::<u16>().unwrap();
-                    Inst::Pop(match line_parts[1] {
-                        "static" => SegmentAddr::Static(arg),
-                        "temp" => SegmentAddr::Temp(arg),
-                        "pointer" => SegmentAddr::Pointer(arg),
-                        "this" => SegmentAddr::This(arg),
-                        "that" => SegmentAddr::That(arg),
-                        "local" => SegmentAddr::Local(arg),
-                        "argument" => SegmentAddr::Arg(arg),
-                        invalid => {
-                            return Err(format!(
-                                "pop on line {linenum} has invalid segment \"{invalid}\"",
-                            ));
-                        }
-                    })
-                }
-
-                "add" => Inst::Add,
-                "sub" => Inst::Sub,
-                "neg" => Inst::Neg,
-                "eq" => Inst::Eq,
-                "or" => Inst::Or,
-                "and" => Inst::And,
-                "not" => Inst::Not,
-                "gt" => Inst::Gt,
-                "lt" => Inst::Lt,
-
-                "label" => Inst::Label(line_parts[1].into()),
-                "goto" => Inst::Goto(line_parts[1].into()),
-                "if-goto" => Inst::IfGoto(line_parts[1].into()),
-
-                "function" => {
-                    Inst::Function(line_parts[1].into(), line_parts[2].parse::<_>().unwrap())
-                }
-                "call" => Inst::Call(line_parts[1].into(), line_parts[2].parse::<_>().unwrap()),
-                "return" => Inst::Return,
-
-                invalid => {
-                    return Err(format!(
-                        "found invalid instruction \"{invalid}\" on line {linenum}"
-                    ));
-                }
-            })
         }
+    } else {
+        vec![input.clone()]
+    };
 
-        Ok(())
+    // every file is checked before anything is translated
+    let mut parsers = Vec::new();
+    let mut failed = false;
+    for file in &files {
+        let mut parser = Parser::new(file);
+        if let Err(err) = parser.parse() {
+            eprintln!("{err}");
+            failed = true;
+        }
+        parsers.push(parser);
+    }
+    if failed {
+        return ExitCode::FAILURE;
+    }
+    let bootstrap = options.bootstrap();
+    if let Err(err) = link(&parsers, bootstrap.as_ref()) {
+        eprintln!("{err}");
+        return ExitCode::FAILURE;
     }
-}
-
-fn generate_vm_code(parser: Parser) -> String {
-    let file = Path::new(parser.file);
-    let filename = file.file_stem().unwrap().to_str().unwrap();
-    let mut ret_no = 0;
-
-    let mut asm = String::from({
-        let call_sys_init = format!(
-            include_str!("asm_snippets/call.asm",),
-            "Sys.init", 0, ret_no
-        );
 
-        format!(include_str!("asm_snippets/init.asm"), call_sys_init)
+    let written = generate_program_code(&parsers, bootstrap.as_ref()).and_then(|asm| {
+        let output = output_path(input)?;
+        fs::write(&output, asm)
+            .map_err(|err| format!("could not write {}: {err}", output.display()))
     });
-
-    for (i, inst) in parser.tokens.iter().enumerate() {
-        asm.push_str(
-            match inst {
-                Inst::Push(push) => match push {
-                    SegmentAddr::Constant(arg) => {
-                        format!(include_str!("asm_snippets/push_constant.asm"), arg)
-                    }
-
-                    SegmentAddr::Static(arg) => {
-                        format!(include_str!("asm_snippets/push_static.asm"), filename, arg)
-                    }
-
-                    SegmentAddr::Temp(arg) => {
-                        format!(include_str!("asm_snippets/push_temp.asm"), arg)
-                    }
-
-                    SegmentAddr::Pointer(arg) => {
-                        let addr = match arg {
-                            0 => "THIS",
-                            1 => "THAT",
-                            _ => unreachable!(),
-                        };
-
-                        format!(include_str!("asm_snippets/push_pointer.asm"), addr)
-                    }
-
-                    ref segment @ (SegmentAddr::Local(arg)
-                    | SegmentAddr::Arg(arg)
-                    | SegmentAddr::This(arg)
-                    | SegmentAddr::That(arg)) => {
-                        let base_addr = match segment {
-                            SegmentAddr::Local(_) => "LCL",
-                            SegmentAddr::Arg(_) => "ARG",
-                            SegmentAddr::This(_) => "THIS",
-                            SegmentAddr::That(_) => "THAT",
-                            _ => unreachable!(),
-                        };
-
-                        format!(
-                            include_str!("asm_snippets/push_local_arg_this_that.asm"),
-                            base_addr, arg
-                        )
-                    }
-                },
-
-                Inst::Pop(pop) => match pop {
-                    SegmentAddr::Constant(_) => unreachable!(),
-
-                    SegmentAddr::Static(arg) => {
-                        format!(include_str!("asm_snippets/pop_static.asm"), filename, arg)
-                    }
-
-                    SegmentAddr::Temp(arg) => {
-                        format!(include_str!("asm_snippets/pop_temp.asm"), arg)
-                    }
-
-                    SegmentAddr::Pointer(arg) => {
-                        let addr = match arg {
-                            0 => "THIS",
-                            1 => "THAT",
-                            _ => unreachable!(),
-                        };
-
-                        format!(include_str!("asm_snippets/pop_pointer.asm"), addr)
-                    }
-
-                    ref segment @ (SegmentAddr::Local(arg)
-                    | SegmentAddr::Arg(arg)
-                    | SegmentAddr::This(arg)
-                    | SegmentAddr::That(arg)) => {
-                        let base_addr = match segment {
-                            SegmentAddr::Local(_) => "LCL",
-                            SegmentAddr::Arg(_) => "ARG",
-                            SegmentAddr::This(_) => "THIS",
-                            SegmentAddr::That(_) => "THAT",
-                            _ => unreachable!(),
-                        };
-
-                        format!(
-                            include_str!("asm_snippets/pop_local_arg_this_that.asm"),
-                            base_addr, arg
-                        )
-                    }
-                },
-
-                Inst::Add => format!(include_str!("asm_snippets/add.asm")),
-                Inst::Sub => format!(include_str!("asm_snippets/sub.asm")),
-                Inst::Neg => format!(include_str!("asm_snippets/neg.asm")),
-                Inst::Eq => format!(include_str!("asm_snippets/eq.asm"), i),
-                Inst::Gt => format!(include_str!("asm_snippets/gt.asm"), i),
-                Inst::Lt => format!(include_str!("asm_snippets/lt.asm"), i),
-                Inst::And => format!(include_str!("asm_snippets/and.asm")),
-                Inst::Or => format!(include_str!("asm_snippets/or.asm")),
-                Inst::Not => format!(include_str!("asm_snippets/not.asm")),
-
-                Inst::Goto(label) => format!(include_str!("asm_snippets/goto.asm"), label),
-                Inst::IfGoto(label) => format!(include_str!("asm_snippets/if_goto.asm"), label),
-                Inst::Label(name) => format!(include_str!("asm_snippets/label.asm"), name),
-
-                Inst::Function(name, vars_no) => {
-                    format!(include_str!("asm_snippets/function.asm"), name, vars_no)
-                }
-                Inst::Return => format!(include_str!("asm_snippets/return.asm")),
-                Inst::Call(name, args_no) => {
-                    ret_no += 1;
-                    let call = format!(
-                        include_str!("asm_snippets/call.asm",),
-                        name, args_no, ret_no
-                    );
-                    call
-                }
-            }
-            .as_str(),
-        )
+    if let Err(err) = written {
+        eprintln!("{err}");
+        return ExitCode::FAILURE;
     }
 
-    asm
+    ExitCode::SUCCESS
 }
 
-fn main() -> ExitCode {
-    let mut args = env::args();
-    if args.len() < 2 {
-        eprintln!("A VM file or directory was not provided.");
-        return ExitCode::FAILURE;
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let input = PathBuf::from(args.nth(1).unwrap());
-    let mut output_file = String::new();
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
 
-    let compile_vm_file = |vm_file: PathBuf| -> Result<String, String> {
-        let mut parser = Parser::new(vm_file.as_path());
-        if let Err(err_msg) = parser.parse() {
-            return Err(err_msg);
-        }
+    #[test]
+    fn parses_bootstrap_options() {
+        let options = parse(&["--entry", "Main.main", "Prog", "--lcl", "300"]).unwrap();
+        assert_eq!(options.input, PathBuf::from("Prog"));
+        assert_eq!(
+            options.bootstrap(),
+            Some(Bootstrap {
+                entry: "Main.main".into(),
+                pointers: [Some(256), Some(300), None, None, None],
+            })
+        );
 
-        Ok(generate_vm_code(parser))
-    };
+        let options = parse(&["Prog"]).unwrap();
+        assert_eq!(options.bootstrap(), Some(Bootstrap::default()));
+        let options = parse(&["--no-bootstrap", "Basic.vm"]).unwrap();
+        assert_eq!(options.bootstrap(), None);
 
-    if input.is_dir() {
-        for file in fs::read_dir(input).unwrap() {
-            let file = file.unwrap().path();
-            let ext = file.extension().unwrap().to_str().unwrap();
-            if ext == "vm" {
-                output_file += compile_vm_file(file).unwrap().as_str();
-            }
-        }
-    } else {
-        output_file += compile_vm_file(input).unwrap().as_str();
+        assert!(parse(&["--no-bootstrap", "--entry", "Main.main", "Prog"]).is_err());
+        assert_eq!(
+            parse(&["--no-bootstrap", "--sp", "317", "Basic.vm"]),
+            Err("--sp sets a pointer in the bootstrap: it needs one".into())
+        );
+        assert!(parse(&["--that", "40000", "Prog"]).is_err());
+        assert!(parse(&["--sp"]).is_err());
+        assert!(parse(&[]).is_err());
     }
-
-    let filename = {
-        let curr_dir = env::current_dir().unwrap();
-        let mut mut_curr_dir = curr_dir.clone();
-        mut_curr_dir.push(curr_dir.file_name().unwrap().to_str().unwrap());
-        mut_curr_dir
-    };
-    fs::write(filename.with_extension("asm"), output_file).unwrap();
-
-    ExitCode::SUCCESS
 }
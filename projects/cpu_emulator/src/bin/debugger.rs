@@ -0,0 +1,44 @@
+use cpu_emulator::debugger::{self, Debugger};
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let Some(file) = env::args().nth(1) else {
+        eprintln!("Usage: debugger <FILE.asm|FILE.hack>");
+        return ExitCode::FAILURE;
+    };
+
+    let (program, info) = match debugger::load(Path::new(&file)) {
+        Ok(loaded) => loaded,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut debugger = Debugger::new(&program, info);
+    println!(
+        "Loaded {} instructions from {file}. Type `help` for commands.",
+        program.len()
+    );
+    print!("=> {}\n(hack) ", debugger.describe(0));
+
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let Ok(line) = line else { break };
+        if matches!(line.trim(), "q" | "quit") {
+            break;
+        }
+
+        match debugger.execute(&line) {
+            Ok(out) => print!("{out}"),
+            Err(msg) => println!("error: {msg}"),
+        }
+        print!("(hack) ");
+        let _ = io::stdout().flush();
+    }
+
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,742 @@
+//! A command driven debugger for Hack machine code.
+//!
+//! Programs are loaded from `.asm` files, which gives the debugger labels and
+//! the source line of every instruction, or from `.hack` files, optionally
+//! with the `.sym` file the assembler writes next to them.
+
+use crate::machine::{Fault, Machine, ROM_SIZE};
+use assembler::disassembler::{self, Decoded};
+use assembler::{parser, preprocessor, Assembler, SymbolKind};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Cycles `continue` and `next` run for before giving up on reaching a stop.
+const MAX_RUN_CYCLES: u64 = 50_000_000;
+
+const STACK_BASE: u16 = 256;
+
+const HELP: &str = "\
+Commands:
+  s, step [N]           Execute N instructions (default 1)
+  n, next               Step over a VM call sequence, otherwise step
+  c, continue           Run until a breakpoint, watchpoint or the end
+  b, break <ADDR|LABEL> Stop when the PC reaches a ROM address
+  w, watch <ADDR|NAME>  Stop when a RAM cell changes, e.g. SP, LCL, RAM[300]
+  d, delete [TARGET]    Remove a breakpoint or watchpoint, or all of them
+  i, info               List breakpoints and watchpoints
+  r, regs               Show the registers and VM pointers
+  stack [N]             Show the top N stack entries (default 8)
+  x, mem <ADDR> [N]     Show N RAM cells (default 1)
+  l, list [ADDR|LABEL]  Show the code around the PC or an address
+  reset                 Set the PC to 0
+  h, help               Show this help
+  q, quit               Exit
+An empty line repeats the previous command.";
+
+/// Where an instruction came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub file: Option<String>,
+    pub line: usize,
+    pub text: String,
+}
+
+/// Symbols and source locations of a loaded program.
+#[derive(Debug, Clone, Default)]
+pub struct DebugInfo {
+    labels: HashMap<String, u16>,
+    variables: HashMap<String, u16>,
+    labels_at: BTreeMap<u16, Vec<String>>,
+    /// Indexed by ROM address, empty when the source isn't known.
+    lines: Vec<SourceLine>,
+}
+
+impl DebugInfo {
+    fn add(&mut self, kind: SymbolKind, name: &str, addr: u16) {
+        if kind == SymbolKind::Label {
+            self.labels.insert(name.to_string(), addr);
+            self.labels_at
+                .entry(addr)
+                .or_default()
+                .push(name.to_string());
+        } else {
+            self.variables.insert(name.to_string(), addr);
+        }
+    }
+
+    /// Only the predefined symbols, for programs loaded without a `.sym` file.
+    pub fn predefined() -> Self {
+        let mut info = DebugInfo::default();
+        for (kind, name, addr) in Assembler::new(Vec::new()).symbol_table() {
+            info.add(kind, name, addr);
+        }
+        info
+    }
+
+    /// Reads the `.sym` file written by the assembler.
+    pub fn from_symbol_map(text: &str) -> Result<Self, String> {
+        let mut info = DebugInfo::predefined();
+        for (row, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let (kind, addr, name) = match fields[..] {
+                [kind, addr, name] => (kind, addr.parse().ok(), name),
+                _ => ("", None, ""),
+            };
+            let kind = match kind {
+                "label" => SymbolKind::Label,
+                "variable" => SymbolKind::Variable,
+                "predefined" => SymbolKind::Predefined,
+                _ => return Err(format!("line {}: invalid symbol entry `{line}`", row + 1)),
+            };
+            let Some(addr) = addr else {
+                return Err(format!("line {}: invalid address in `{line}`", row + 1));
+            };
+            info.add(kind, name, addr);
+        }
+        Ok(info)
+    }
+
+    pub fn label(&self, name: &str) -> Option<u16> {
+        self.labels.get(name).copied()
+    }
+
+    /// Every label and its ROM address, sorted by address.
+    pub fn labels(&self) -> impl Iterator<Item = (&str, u16)> {
+        self.labels_at
+            .iter()
+            .flat_map(|(&addr, names)| names.iter().map(move |name| (name.as_str(), addr)))
+    }
+
+    /// A ROM address given as a number or a label.
+    pub fn rom_address(&self, target: &str) -> Option<u16> {
+        target
+            .parse()
+            .ok()
+            .or_else(|| self.label(target))
+            .filter(|&addr| (addr as usize) < ROM_SIZE)
+    }
+
+    /// A RAM address given as a number, `RAM[n]`, or a predefined symbol or
+    /// variable.
+    pub fn ram_address(&self, target: &str) -> Option<u16> {
+        let number = target
+            .strip_prefix("RAM[")
+            .and_then(|rest| rest.strip_suffix(']'))
+            .unwrap_or(target);
+        number
+            .parse()
+            .ok()
+            .or_else(|| self.variables.get(target).copied())
+    }
+
+    /// The closest label at or before `addr`, and its address.
+    pub fn enclosing_label(&self, addr: u16) -> Option<(&str, u16)> {
+        self.labels_at
+            .range(..=addr)
+            .next_back()
+            .map(|(&label_addr, names)| (names[0].as_str(), label_addr))
+    }
+
+    pub fn source_line(&self, addr: u16) -> Option<&SourceLine> {
+        self.lines.get(addr as usize)
+    }
+
+    /// The addresses of the return labels the VM translator ends its call
+    /// sequences with: `Caller$ret.N`, and `__bootstrap$ret`.
+    fn return_labels(&self) -> impl DoubleEndedIterator<Item = (&u16, &Vec<String>)> {
+        self.labels_at.iter().filter(|(_, names)| {
+            names
+                .iter()
+                .any(|name| name.contains("$ret.") || name.ends_with("$ret"))
+        })
+    }
+
+    /// The return address of the VM call sequence the PC is in, if any. A call
+    /// sequence starts by pushing the address of the return label ending it,
+    /// so the PC is in one when the next return label's address is loaded
+    /// after the previous return label.
+    fn return_address(&self, rom: &[u16], pc: u16) -> Option<u16> {
+        let (&ret, _) = self.return_labels().find(|(&addr, _)| addr > pc)?;
+        let start = self
+            .return_labels()
+            .rev()
+            .find(|(&addr, _)| addr <= pc)
+            .map_or(0, |(&addr, _)| addr);
+        rom.get(start as usize..=pc as usize)?
+            .contains(&ret)
+            .then_some(ret)
+    }
+}
+
+/// Loads a program for debugging: `.asm` files are assembled, `.hack` files
+/// are read together with their `.sym` file if there is one.
+pub fn load(path: &Path) -> Result<(Vec<u16>, DebugInfo), String> {
+    let source = fs::read_to_string(path)
+        .map_err(|err| format!("Could not read {}: {err}", path.display()))?;
+    let render = |diagnostics: Vec<assembler::Diagnostic>| {
+        diagnostics
+            .into_iter()
+            .map(|diag| diag.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    if path.extension().is_some_and(|ext| ext == "asm") {
+        let expansion = preprocessor::preprocess(&source, Some(path)).map_err(render)?;
+        let tokens = parser::parse_located(&expansion.source)
+            .map_err(|diagnostics| render(expansion.remap_all(diagnostics)))?;
+        let mut assembler = Assembler::with_locations(tokens);
+        let diagnostics = expansion.remap_all(assembler.resolve_symbols());
+        if diagnostics.iter().any(assembler::Diagnostic::is_error) {
+            return Err(render(diagnostics));
+        }
+
+        let mut info = DebugInfo::default();
+        for (kind, name, addr) in assembler.symbol_table() {
+            info.add(kind, name, addr);
+        }
+        let rows: Vec<_> = expansion.source.lines().collect();
+        info.lines = assembler
+            .instruction_lines()
+            .into_iter()
+            .map(|line| {
+                let (file, origin) = expansion.origin(line).unwrap_or((None, line));
+                SourceLine {
+                    file: file.map(str::to_string),
+                    line: origin,
+                    text: rows
+                        .get(line.wrapping_sub(1))
+                        .map_or("", |row| row.trim())
+                        .into(),
+                }
+            })
+            .collect();
+        return Ok((assembler.assemble().instructions, info));
+    }
+
+    let program = disassembler::read_hack_text(&source).map_err(render)?;
+    let symbols = path.with_extension("sym");
+    let info = match fs::read_to_string(&symbols) {
+        Ok(text) => DebugInfo::from_symbol_map(&text)
+            .map_err(|err| format!("{}: {err}", symbols.display()))?,
+        Err(_) => DebugInfo::predefined(),
+    };
+    Ok((program, info))
+}
+
+/// Why execution stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pause {
+    /// The requested steps were executed.
+    Done,
+    Breakpoint(u16),
+    Watchpoint {
+        name: String,
+        old: u16,
+        new: u16,
+    },
+    Halted,
+    /// The PC ran past the last instruction of the program.
+    EndOfProgram,
+    Fault(Fault),
+    CycleLimit,
+}
+
+impl fmt::Display for Pause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pause::Done => Ok(()),
+            Pause::Breakpoint(addr) => write!(f, "breakpoint at ROM[{addr}]"),
+            Pause::Watchpoint { name, old, new } => {
+                write!(f, "watchpoint {name}: {} -> {}", *old as i16, *new as i16)
+            }
+            Pause::Halted => f.write_str("program halted"),
+            Pause::EndOfProgram => f.write_str("ran past the end of the program"),
+            Pause::Fault(fault) => write!(f, "{fault}"),
+            Pause::CycleLimit => write!(f, "gave up after {MAX_RUN_CYCLES} cycles"),
+        }
+    }
+}
+
+struct Watchpoint {
+    name: String,
+    addr: u16,
+    value: u16,
+}
+
+pub struct Debugger {
+    pub machine: Machine,
+    pub info: DebugInfo,
+    program_len: usize,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    last_command: String,
+}
+
+impl Debugger {
+    pub fn new(program: &[u16], info: DebugInfo) -> Self {
+        let mut machine = Machine::new();
+        machine.load(program);
+        Self {
+            machine,
+            info,
+            program_len: program.len(),
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            last_command: String::new(),
+        }
+    }
+
+    /// Executes one instruction, reporting a fault or a changed watchpoint.
+    pub fn step(&mut self) -> Option<Pause> {
+        if let Err(fault) = self.machine.step() {
+            return Some(Pause::Fault(fault));
+        }
+
+        let mut pause = None;
+        for watch in &mut self.watchpoints {
+            let value = self.machine.read(watch.addr);
+            if value != watch.value && pause.is_none() {
+                pause = Some(Pause::Watchpoint {
+                    name: watch.name.clone(),
+                    old: watch.value,
+                    new: value,
+                });
+            }
+            watch.value = value;
+        }
+        pause
+    }
+
+    /// Steps until `done` holds or something else stops execution. A
+    /// breakpoint at the starting PC is ignored, so execution can resume from
+    /// it.
+    fn run_until(&mut self, done: impl Fn(&Machine) -> bool) -> Pause {
+        for cycle in 0..MAX_RUN_CYCLES {
+            if cycle > 0 && self.breakpoints.contains(&self.machine.pc) {
+                return Pause::Breakpoint(self.machine.pc);
+            }
+            if self.machine.is_halted() {
+                return Pause::Halted;
+            }
+            if self.machine.pc as usize >= self.program_len {
+                return Pause::EndOfProgram;
+            }
+            if let Some(pause) = self.step() {
+                return pause;
+            }
+            if done(&self.machine) {
+                return Pause::Done;
+            }
+        }
+        Pause::CycleLimit
+    }
+
+    pub fn cont(&mut self) -> Pause {
+        self.run_until(|_| false)
+    }
+
+    /// Steps over a whole VM call when the PC is inside a call sequence,
+    /// stopping once the callee returned to it. Otherwise steps once.
+    pub fn step_over(&mut self) -> Pause {
+        let Some(ret) = self
+            .info
+            .return_address(self.machine.rom(), self.machine.pc)
+        else {
+            return self.step().unwrap_or(Pause::Done);
+        };
+        // returning pops the arguments and pushes the return value, so the
+        // stack is at most one entry higher than before the call
+        let sp = self.machine.read(0);
+        self.run_until(|machine| machine.pc == ret && machine.read(0) <= sp.wrapping_add(1))
+    }
+
+    pub fn add_breakpoint(&mut self, target: &str) -> Result<u16, String> {
+        let addr = self
+            .info
+            .rom_address(target)
+            .ok_or(format!("no ROM address or label `{target}`"))?;
+        self.breakpoints.insert(addr);
+        Ok(addr)
+    }
+
+    pub fn add_watchpoint(&mut self, target: &str) -> Result<u16, String> {
+        let addr = self
+            .info
+            .ram_address(target)
+            .ok_or(format!("no RAM address or symbol `{target}`"))?;
+        self.watchpoints.retain(|watch| watch.addr != addr);
+        self.watchpoints.push(Watchpoint {
+            name: target.to_string(),
+            addr,
+            value: self.machine.read(addr),
+        });
+        Ok(addr)
+    }
+
+    /// Describes the instruction at `addr`: its address, the label it follows
+    /// and the source it was assembled from.
+    pub fn describe(&self, addr: u16) -> String {
+        let mut out = format!("{addr:05}");
+        if let Some((label, label_addr)) = self.info.enclosing_label(addr) {
+            match addr - label_addr {
+                0 => out += &format!("  <{label}>"),
+                offset => out += &format!("  <{label}+{offset}>"),
+            }
+        }
+
+        match self.info.source_line(addr) {
+            Some(SourceLine {
+                file: Some(file),
+                line,
+                text,
+            }) => out += &format!("  {file}:{line}  {text}"),
+            Some(SourceLine { line, text, .. }) => out += &format!("  line {line}  {text}"),
+            None => match disassembler::decode(self.machine.rom()[addr as usize]) {
+                Decoded::Token(token) => out += &format!("  {token}"),
+                Decoded::Invalid(word) => out += &format!("  <invalid {word:016b}>"),
+            },
+        }
+        out
+    }
+
+    fn list(&self, center: u16) -> String {
+        let start = center.saturating_sub(5);
+        let end = (center as usize + 6).min(ROM_SIZE) as u16;
+        (start..end)
+            .map(|addr| {
+                let marker = if addr == self.machine.pc { "=>" } else { "  " };
+                let breakpoint = if self.breakpoints.contains(&addr) {
+                    "*"
+                } else {
+                    " "
+                };
+                format!("{marker}{breakpoint}{}\n", self.describe(addr))
+            })
+            .collect()
+    }
+
+    fn registers(&self) -> String {
+        let m = &self.machine;
+        let mut out = format!(
+            "A={} D={} PC={}  cycles={}\n",
+            m.a as i16,
+            m.d as i16,
+            m.pc,
+            m.cycles()
+        );
+        for (i, name) in ["SP", "LCL", "ARG", "THIS", "THAT"].iter().enumerate() {
+            out += &format!("{name}={} ", m.read(i as u16));
+        }
+        out.pop();
+        out.push('\n');
+        out
+    }
+
+    fn stack(&self, count: u16) -> String {
+        let sp = self.machine.read(0);
+        if sp <= STACK_BASE {
+            return "stack is empty\n".into();
+        }
+
+        let mut out = String::new();
+        for addr in (sp.saturating_sub(count).max(STACK_BASE)..sp).rev() {
+            out += &format!("RAM[{addr}] = {}", self.machine.read(addr) as i16);
+            for (pointer, name) in [(1, "LCL"), (2, "ARG")] {
+                if self.machine.read(pointer) == addr {
+                    out += &format!("  <- {name}");
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn memory(&self, addr: u16, count: u16) -> String {
+        (addr..addr.saturating_add(count))
+            .map(|addr| format!("RAM[{addr}] = {}\n", self.machine.read(addr) as i16))
+            .collect()
+    }
+
+    fn info(&self) -> String {
+        let mut out = String::new();
+        for &addr in &self.breakpoints {
+            out += &format!("breakpoint {}\n", self.describe(addr));
+        }
+        for watch in &self.watchpoints {
+            out += &format!(
+                "watchpoint {} (RAM[{}]) = {}\n",
+                watch.name, watch.addr, watch.value as i16
+            );
+        }
+        if out.is_empty() {
+            out = "no breakpoints or watchpoints\n".into();
+        }
+        out
+    }
+
+    fn stopped(&self, pause: Pause) -> String {
+        let location = format!("=> {}\n", self.describe(self.machine.pc));
+        match pause {
+            Pause::Done => location,
+            pause => format!("{pause}\n{location}"),
+        }
+    }
+
+    /// Executes a debugger command, returning what to print. An empty command
+    /// repeats the previous one.
+    pub fn execute(&mut self, command: &str) -> Result<String, String> {
+        let command = match command.trim() {
+            "" => self.last_command.clone(),
+            command => command.to_string(),
+        };
+        self.last_command = command.clone();
+
+        let words: Vec<_> = command.split_whitespace().collect();
+        let count = |index: usize, default: u16| match words.get(index) {
+            Some(word) => word.parse().map_err(|_| format!("invalid count `{word}`")),
+            None => Ok(default),
+        };
+        let target = |index: usize| {
+            words
+                .get(index)
+                .copied()
+                .ok_or(format!("`{}` expects an argument", words[0]))
+        };
+
+        let Some(&name) = words.first() else {
+            return Ok(String::new());
+        };
+        Ok(match name {
+            "s" | "step" => {
+                let mut pause = Pause::Done;
+                for _ in 0..count(1, 1)? {
+                    if let Some(stop) = self.step() {
+                        pause = stop;
+                        break;
+                    }
+                }
+                self.stopped(pause)
+            }
+            "n" | "next" => {
+                let pause = self.step_over();
+                self.stopped(pause)
+            }
+            "c" | "continue" => {
+                let pause = self.cont();
+                self.stopped(pause)
+            }
+            "b" | "break" => {
+                let addr = self.add_breakpoint(target(1)?)?;
+                format!("breakpoint {}\n", self.describe(addr))
+            }
+            "w" | "watch" => {
+                let addr = self.add_watchpoint(target(1)?)?;
+                format!("watching RAM[{addr}]\n")
+            }
+            "d" | "delete" => match words.get(1) {
+                None => {
+                    self.breakpoints.clear();
+                    self.watchpoints.clear();
+                    "deleted all breakpoints and watchpoints\n".into()
+                }
+                Some(&target) => {
+                    let watches = self.watchpoints.len();
+                    self.watchpoints.retain(|watch| watch.name != target);
+                    let removed_breakpoint = self
+                        .info
+                        .rom_address(target)
+                        .is_some_and(|addr| self.breakpoints.remove(&addr));
+                    if !removed_breakpoint && watches == self.watchpoints.len() {
+                        return Err(format!("no breakpoint or watchpoint `{target}`"));
+                    }
+                    format!("deleted {target}\n")
+                }
+            },
+            "i" | "info" => self.info(),
+            "r" | "regs" => self.registers(),
+            "stack" => self.stack(count(1, 8)?),
+            "x" | "mem" => {
+                let addr = self
+                    .info
+                    .ram_address(target(1)?)
+                    .ok_or(format!("no RAM address or symbol `{}`", words[1]))?;
+                self.memory(addr, count(2, 1)?)
+            }
+            "l" | "list" => {
+                let center = match words.get(1) {
+                    Some(target) => self
+                        .info
+                        .rom_address(target)
+                        .ok_or(format!("no ROM address or label `{target}`"))?,
+                    None => self.machine.pc,
+                };
+                self.list(center)
+            }
+            "reset" => {
+                self.machine.reset();
+                self.stopped(Pause::Done)
+            }
+            "h" | "help" => format!("{HELP}\n"),
+            _ => return Err(format!("unknown command `{name}`, try `help`")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A VM style program: `Main.main` calls `Main.double` with one argument
+    /// using the translator's calling convention, trimmed down to what the
+    /// debugger relies on.
+    const PROGRAM: &str = "\
+@261
+D=A
+@SP
+M=D
+(Main.main)
+@Main.main$ret.0
+D=A
+@SP
+AM=M+1
+A=A-1
+M=D
+@Main.double
+0;JMP
+(Main.main$ret.0)
+(END)
+@END
+0;JMP
+(Main.double)
+@SP
+M=M-1
+A=M
+A=M
+0;JMP
+";
+
+    fn debugger(asm: &str) -> Debugger {
+        let dir = std::env::temp_dir().join(format!("hack-debugger-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("Test{}.asm", asm.len()));
+        fs::write(&path, asm).unwrap();
+        let (program, info) = load(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        Debugger::new(&program, info)
+    }
+
+    #[test]
+    fn breakpoints_on_labels() {
+        let mut debugger = debugger(PROGRAM);
+        assert_eq!(debugger.add_breakpoint("Main.double"), Ok(14));
+        assert_eq!(debugger.cont(), Pause::Breakpoint(14));
+        assert_eq!(debugger.cont(), Pause::Halted);
+        assert_eq!(debugger.machine.pc, 13);
+        assert!(debugger.add_breakpoint("Nowhere").is_err());
+    }
+
+    #[test]
+    fn watchpoints_on_ram() {
+        let mut debugger = debugger(PROGRAM);
+        debugger.add_watchpoint("SP").unwrap();
+        assert_eq!(
+            debugger.cont(),
+            Pause::Watchpoint {
+                name: "SP".into(),
+                old: 0,
+                new: 261
+            }
+        );
+        assert_eq!(debugger.machine.pc, 4);
+        assert!(matches!(
+            debugger.cont(),
+            Pause::Watchpoint { new: 262, .. }
+        ));
+    }
+
+    #[test]
+    fn steps_over_calls() {
+        let mut debugger = debugger(PROGRAM);
+        debugger.execute("step 4").unwrap();
+        assert_eq!(debugger.machine.pc, 4);
+        assert_eq!(debugger.step_over(), Pause::Done);
+        assert_eq!(debugger.machine.pc, 12);
+        assert_eq!(debugger.info.source_line(12).unwrap().text, "@END");
+        // outside of call sequences, next is a plain step
+        assert_eq!(debugger.step_over(), Pause::Done);
+        assert_eq!(debugger.machine.pc, 13);
+    }
+
+    #[test]
+    fn steps_over_translated_calls() {
+        use vm_translator::{generate_program_code, Bootstrap, Parser};
+
+        let main = Path::new("Main.vm");
+        let mut parser = Parser::new(main);
+        parser
+            .parse_source(
+                "function Main.main 0\npush constant 20\ncall Main.double 1\n\
+                 label END\ngoto END\n\
+                 function Main.double 0\npush argument 0\npush argument 0\nadd\nreturn\n",
+            )
+            .unwrap();
+        let asm = generate_program_code(&[parser], Some(&Bootstrap::calling("Main.main"))).unwrap();
+        let mut debugger = debugger(&asm);
+
+        // the bootstrap sets SP, then calls the entry function
+        let bootstrap_ret = debugger.info.label("__bootstrap$ret").unwrap();
+        let return_address = |debugger: &Debugger| {
+            let machine = &debugger.machine;
+            debugger.info.return_address(machine.rom(), machine.pc)
+        };
+        assert_eq!(return_address(&debugger), None);
+        debugger.execute("step 4").unwrap();
+        assert_eq!(return_address(&debugger), Some(bootstrap_ret));
+
+        debugger.add_breakpoint("Main.main").unwrap();
+        assert_eq!(debugger.cont(), Pause::Breakpoint(debugger.machine.pc));
+        let ret = debugger.info.label("Main.main$ret.0").unwrap();
+        while debugger.machine.rom()[debugger.machine.pc as usize] != ret {
+            assert_eq!(debugger.step(), None);
+        }
+        assert_eq!(debugger.step_over(), Pause::Done);
+        assert_eq!(debugger.machine.pc, ret);
+        // above the bootstrap's frame, the argument replaced by the result
+        assert_eq!(debugger.machine.read(0), 262);
+        assert_eq!(debugger.machine.read(261), 40);
+    }
+
+    #[test]
+    fn describes_source_lines() {
+        let mut debugger = debugger(PROGRAM);
+        let out = debugger.execute("step 6").unwrap();
+        assert!(out.starts_with("=> 00006  <Main.main+2>  "));
+        assert!(out.ends_with(".asm:8  @SP\n"));
+        assert!(debugger
+            .execute("")
+            .unwrap()
+            .starts_with("=> 00014  <Main.double>  "));
+        assert!(debugger.execute("bogus").is_err());
+    }
+
+    #[test]
+    fn reads_symbol_maps() {
+        let info = DebugInfo::from_symbol_map(
+            "// kind address name\nlabel 0 LOOP\nvariable 16 i\npredefined 0 SP\n",
+        )
+        .unwrap();
+        assert_eq!(info.rom_address("LOOP"), Some(0));
+        assert_eq!(info.ram_address("i"), Some(16));
+        assert_eq!(info.ram_address("LCL"), Some(1));
+        assert_eq!(info.ram_address("RAM[300]"), Some(300));
+        assert!(DebugInfo::from_symbol_map("label LOOP\n").is_err());
+    }
+}
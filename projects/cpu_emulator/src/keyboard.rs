@@ -0,0 +1,144 @@
+//! Scripted keyboard input.
+//!
+//! A key script lists which key is held down from which cycle on, one event
+//! per line:
+//!
+//! ```text
+//! // cycle  key
+//! 1000      'a'
+//! 1500      none
+//! 2000      newline
+//! 2500      75
+//! ```
+//!
+//! Keys are quoted characters, special key names or raw scan codes. Each key
+//! stays pressed until the next event.
+
+use crate::machine::Machine;
+
+/// Scan codes of the keys that don't produce a character.
+const SPECIAL_KEYS: [(&str, u16); 15] = [
+    ("none", 0),
+    ("space", 32),
+    ("newline", 128),
+    ("backspace", 129),
+    ("left", 130),
+    ("up", 131),
+    ("right", 132),
+    ("down", 133),
+    ("home", 134),
+    ("end", 135),
+    ("pageup", 136),
+    ("pagedown", 137),
+    ("insert", 138),
+    ("delete", 139),
+    ("esc", 140),
+];
+
+const F1: u16 = 141;
+
+fn scan_code(key: &str) -> Option<u16> {
+    if let Some(&(_, code)) = SPECIAL_KEYS.iter().find(|(name, _)| *name == key) {
+        return Some(code);
+    }
+    if let Some(n) = key.strip_prefix('f').and_then(|n| n.parse::<u16>().ok()) {
+        return (1..=12).contains(&n).then_some(F1 + n - 1);
+    }
+
+    let quoted = key
+        .strip_prefix('\'')
+        .and_then(|key| key.strip_suffix('\''));
+    if let Some(quoted) = quoted {
+        let mut chars = quoted.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) if (' '..='~').contains(&c) => Some(c as u16),
+            _ => None,
+        };
+    }
+    key.parse().ok()
+}
+
+/// Key events sorted by cycle, applied as the machine runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyScript {
+    events: Vec<(u64, u16)>,
+    next: usize,
+}
+
+impl KeyScript {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events = Vec::new();
+        for (row, line) in text.lines().enumerate() {
+            let line = line.split("//").next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (cycle, key) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let cycle: u64 = cycle
+                .parse()
+                .map_err(|_| format!("line {}: invalid cycle `{cycle}`", row + 1))?;
+            let key = key.trim();
+            let code = scan_code(key).ok_or(format!("line {}: unknown key `{key}`", row + 1))?;
+
+            if events.last().is_some_and(|&(last, _)| last > cycle) {
+                return Err(format!("line {}: events must be in cycle order", row + 1));
+            }
+            events.push((cycle, code));
+        }
+        Ok(KeyScript { events, next: 0 })
+    }
+
+    /// Presses the key of every event that is due by the machine's cycle count.
+    pub fn apply(&mut self, machine: &mut Machine) {
+        while let Some(&(cycle, code)) = self.events.get(self.next) {
+            if cycle > machine.cycles() {
+                break;
+            }
+            machine.keyboard = code;
+            self.next += 1;
+        }
+    }
+
+    /// Whether every event has been applied.
+    pub fn finished(&self) -> bool {
+        self.next == self.events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys() {
+        let script = KeyScript::parse(
+            "// cycle key\n10 'a'\n20 newline // enter\n\n30 f12\n40 75\n50 none\n60 ' '\n",
+        )
+        .unwrap();
+        assert_eq!(
+            script.events,
+            [(10, 97), (20, 128), (30, 152), (40, 75), (50, 0), (60, 32)]
+        );
+
+        assert!(KeyScript::parse("10 f13\n").is_err());
+        assert!(KeyScript::parse("x 'a'\n").is_err());
+        assert!(KeyScript::parse("20 'a'\n10 'b'\n").is_err());
+    }
+
+    #[test]
+    fn presses_keys_in_time() {
+        let mut machine = Machine::new();
+        machine.load(&[0; 8]);
+        let mut script = KeyScript::parse("0 'x'\n3 none\n").unwrap();
+
+        script.apply(&mut machine);
+        assert_eq!(machine.keyboard, 'x' as u16);
+        for _ in 0..3 {
+            machine.step().unwrap();
+            script.apply(&mut machine);
+        }
+        assert_eq!(machine.keyboard, 0);
+        assert!(script.finished());
+    }
+}
@@ -0,0 +1,8 @@
+pub mod debugger;
+pub mod keyboard;
+pub mod machine;
+pub mod profiler;
+pub mod screen;
+
+pub use debugger::Debugger;
+pub use machine::{Fault, Machine, Stop, KBD, RAM_SIZE, ROM_SIZE, SCREEN, SCREEN_SIZE};
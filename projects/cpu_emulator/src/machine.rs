@@ -0,0 +1,290 @@
+use assembler::parser::{CComp, CDest, CJump, ComputationInst};
+use std::fmt;
+
+pub const ROM_SIZE: usize = 32768;
+pub const RAM_SIZE: usize = 32768;
+pub const SCREEN: u16 = 16384;
+pub const SCREEN_SIZE: usize = 8192;
+pub const KBD: u16 = 24576;
+
+/// Reasons the machine can't execute an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// A C-instruction whose computation bits are not part of the instruction set.
+    InvalidInstruction { pc: u16, word: u16 },
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::InvalidInstruction { pc, word } => {
+                write!(f, "invalid instruction {word:016b} at ROM[{pc}]")
+            }
+        }
+    }
+}
+
+/// Why [`Machine::run`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The program reached the conventional `(END) @END 0;JMP` loop.
+    Halted,
+    CycleLimit,
+}
+
+/// The Hack computer: 32K words of ROM and RAM, the A, D and PC registers, and
+/// the memory mapped screen and keyboard.
+pub struct Machine {
+    rom: Vec<u16>,
+    ram: Vec<u16>,
+    pub a: u16,
+    pub d: u16,
+    pub pc: u16,
+    /// Scan code of the key currently pressed, read at `KBD`.
+    pub keyboard: u16,
+    cycles: u64,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        Self {
+            rom: vec![0; ROM_SIZE],
+            ram: vec![0; RAM_SIZE],
+            a: 0,
+            d: 0,
+            pc: 0,
+            keyboard: 0,
+            cycles: 0,
+        }
+    }
+
+    /// Loads machine code into ROM, clearing the rest of it, and resets the PC.
+    pub fn load(&mut self, program: &[u16]) {
+        assert!(program.len() <= ROM_SIZE, "program doesn't fit in ROM");
+        self.rom.fill(0);
+        self.rom[..program.len()].copy_from_slice(program);
+        self.reset();
+    }
+
+    /// Same as the computer's reset input: only the PC is cleared.
+    pub fn reset(&mut self) {
+        self.pc = 0;
+    }
+
+    pub fn rom(&self) -> &[u16] {
+        &self.rom
+    }
+
+    pub fn rom_mut(&mut self) -> &mut [u16] {
+        &mut self.rom
+    }
+
+    /// Raw RAM, including the screen memory map. The keyboard register is
+    /// [`Machine::keyboard`].
+    pub fn ram(&self) -> &[u16] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u16] {
+        &mut self.ram
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Reads memory as the CPU sees it.
+    pub fn read(&self, addr: u16) -> u16 {
+        match addr {
+            KBD => self.keyboard,
+            addr if addr > KBD => 0,
+            addr => self.ram[addr as usize],
+        }
+    }
+
+    /// Writes memory as the CPU does. The keyboard and addresses past it are
+    /// read-only.
+    pub fn write(&mut self, addr: u16, value: u16) {
+        if addr < KBD {
+            self.ram[addr as usize] = value;
+        }
+    }
+
+    /// Whether the instruction at the PC is the conventional end of a program,
+    /// an unconditional jump to itself.
+    pub fn is_halted(&self) -> bool {
+        let pc = self.pc as usize;
+        pc > 0
+            && self.rom[pc - 1] == self.pc - 1
+            && ComputationInst::decode(self.rom[pc]).is_some_and(|inst| inst.jump == CJump::JMP)
+    }
+
+    pub fn step(&mut self) -> Result<(), Fault> {
+        let word = self.rom[self.pc as usize % ROM_SIZE];
+        self.cycles += 1;
+
+        if word & 0x8000 == 0 {
+            self.a = word;
+            self.pc = self.pc.wrapping_add(1) % ROM_SIZE as u16;
+            return Ok(());
+        }
+
+        let Some(inst) = ComputationInst::decode(word) else {
+            return Err(Fault::InvalidInstruction { pc: self.pc, word });
+        };
+
+        let out = self.compute(inst.comp);
+        let addr = self.a;
+
+        if matches!(inst.dest, CDest::M | CDest::MD | CDest::AM | CDest::AMD) {
+            self.write(addr, out);
+        }
+        if matches!(inst.dest, CDest::A | CDest::AM | CDest::AD | CDest::AMD) {
+            self.a = out;
+        }
+        if matches!(inst.dest, CDest::D | CDest::MD | CDest::AD | CDest::AMD) {
+            self.d = out;
+        }
+
+        let out = out as i16;
+        let jump = match inst.jump {
+            CJump::Null => false,
+            CJump::JGT => out > 0,
+            CJump::JEQ => out == 0,
+            CJump::JGE => out >= 0,
+            CJump::JLT => out < 0,
+            CJump::JNE => out != 0,
+            CJump::JLE => out <= 0,
+            CJump::JMP => true,
+        };
+
+        self.pc = if jump {
+            addr % ROM_SIZE as u16
+        } else {
+            self.pc.wrapping_add(1) % ROM_SIZE as u16
+        };
+
+        Ok(())
+    }
+
+    /// Steps until the program halts or `max_cycles` instructions were executed.
+    pub fn run(&mut self, max_cycles: u64) -> Result<Stop, Fault> {
+        for _ in 0..max_cycles {
+            if self.is_halted() {
+                return Ok(Stop::Halted);
+            }
+            self.step()?;
+        }
+
+        Ok(if self.is_halted() {
+            Stop::Halted
+        } else {
+            Stop::CycleLimit
+        })
+    }
+
+    fn compute(&self, comp: CComp) -> u16 {
+        let (a, d) = (self.a, self.d);
+        let m = || self.read(a);
+
+        match comp {
+            CComp::Zero => 0,
+            CComp::One => 1,
+            CComp::NegOne => u16::MAX,
+            CComp::D => d,
+            CComp::A => a,
+            CComp::NotD => !d,
+            CComp::NotA => !a,
+            CComp::NegD => d.wrapping_neg(),
+            CComp::NegA => a.wrapping_neg(),
+            CComp::DPlusOne => d.wrapping_add(1),
+            CComp::APlusOne => a.wrapping_add(1),
+            CComp::DMinusOne => d.wrapping_sub(1),
+            CComp::AMinusOne => a.wrapping_sub(1),
+            CComp::DPlusA => d.wrapping_add(a),
+            CComp::DMinusA => d.wrapping_sub(a),
+            CComp::AMinusD => a.wrapping_sub(d),
+            CComp::DAndA => d & a,
+            CComp::DOrA => d | a,
+            CComp::M => m(),
+            CComp::NotM => !m(),
+            CComp::NegM => m().wrapping_neg(),
+            CComp::MPlusOne => m().wrapping_add(1),
+            CComp::MMinusOne => m().wrapping_sub(1),
+            CComp::DPlusM => d.wrapping_add(m()),
+            CComp::DMinusM => d.wrapping_sub(m()),
+            CComp::MMinusD => m().wrapping_sub(d),
+            CComp::DAndM => d & m(),
+            CComp::DOrM => d | m(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assembler::assemble_source;
+
+    fn machine(asm: &str) -> Machine {
+        let mut machine = Machine::new();
+        machine.load(&assemble_source(asm).unwrap().instructions);
+        machine
+    }
+
+    #[test]
+    fn computes_max() {
+        let mut machine = machine(include_str!("../../6/max/Max.asm"));
+        for (x, y) in [(3, 5), (23456, 12345)] {
+            machine.reset();
+            machine.ram_mut()[0] = x;
+            machine.ram_mut()[1] = y;
+            assert_eq!(machine.run(100), Ok(Stop::Halted));
+            assert_eq!(machine.ram()[2], x.max(y));
+        }
+    }
+
+    #[test]
+    fn multiplies() {
+        let mut machine = machine(include_str!("../../4/mult/Mult.asm"));
+        machine.ram_mut()[0] = 6;
+        machine.ram_mut()[1] = 7;
+        machine.run(200).unwrap();
+        assert_eq!(machine.ram()[2], 42);
+    }
+
+    #[test]
+    fn memory_map() {
+        let mut machine = machine("@KBD\nD=M\n@SCREEN\nM=D\n@KBD\nM=1\n");
+        machine.keyboard = 75;
+        assert_eq!(machine.run(6), Ok(Stop::CycleLimit));
+        assert_eq!(machine.ram()[SCREEN as usize], 75);
+        assert_eq!(machine.read(KBD), 75);
+    }
+
+    #[test]
+    fn jumps_use_alu_output() {
+        let mut machine = machine("@5\nD=-A\n@10\nD;JLT\n");
+        machine.run(4).unwrap();
+        assert_eq!(machine.d as i16, -5);
+        assert_eq!(machine.pc, 10);
+    }
+
+    #[test]
+    fn invalid_instruction_faults() {
+        let mut machine = Machine::new();
+        machine.load(&[0b1110111110000000]);
+        assert_eq!(
+            machine.step(),
+            Err(Fault::InvalidInstruction {
+                pc: 0,
+                word: 0b1110111110000000
+            })
+        );
+    }
+}
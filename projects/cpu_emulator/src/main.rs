@@ -0,0 +1,267 @@
+use cpu_emulator::debugger;
+use cpu_emulator::keyboard::KeyScript;
+use cpu_emulator::profiler::Profiler;
+use cpu_emulator::screen::{self, TextMode};
+use cpu_emulator::{Fault, Machine, Stop};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+const DEFAULT_CYCLES: u64 = 1_000_000;
+
+/// Cycles between two frames of the live terminal display.
+const LIVE_INTERVAL: u64 = 200_000;
+
+/// Instructions listed in the profile report.
+const PROFILE_TOP: usize = 30;
+
+const USAGE: &str = "\
+Usage: cpu_emulator [OPTIONS] <FILE.hack|FILE.asm> [MAX_CYCLES]
+
+Runs a Hack program until it halts or MAX_CYCLES instructions were executed
+(default 1000000).
+
+Options:
+  --keys <FILE>        Feed the keyboard from a key script
+  --png <FILE>         Save the screen as a PNG file when done
+  --expect-png <FILE>  Fail unless the screen matches a PNG saved with --png
+  --show <MODE>        Print the screen when done: braille or blocks
+  --live <MODE>        Draw the screen in the terminal while running
+  --profile <FILE>     Write a report of where the cycles were spent
+  --collapsed <FILE>   Write the profiled call stacks for flame graph tools
+  -h, --help           Print this help";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Options {
+    file: String,
+    max_cycles: Option<u64>,
+    keys: Option<String>,
+    png: Option<String>,
+    expect_png: Option<String>,
+    show: Option<TextMode>,
+    live: Option<TextMode>,
+    profile: Option<String>,
+    collapsed: Option<String>,
+    help: bool,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{arg} expects a value"));
+        let mode = |name: String| {
+            TextMode::from_name(&name).ok_or(format!(
+                "Unknown screen mode {name}, expected braille or blocks"
+            ))
+        };
+
+        match arg.as_str() {
+            "--keys" => options.keys = Some(value()?),
+            "--png" => options.png = Some(value()?),
+            "--expect-png" => options.expect_png = Some(value()?),
+            "--show" => options.show = Some(mode(value()?)?),
+            "--live" => options.live = Some(mode(value()?)?),
+            "--profile" => options.profile = Some(value()?),
+            "--collapsed" => options.collapsed = Some(value()?),
+            "-h" | "--help" => options.help = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {arg}")),
+            _ => positional.push(arg),
+        }
+    }
+
+    if options.help {
+        return Ok(options);
+    }
+    let mut positional = positional.into_iter();
+    options.file = positional.next().ok_or("No program was provided.")?;
+    if let Some(cycles) = positional.next() {
+        options.max_cycles = Some(cycles.parse().map_err(|_| "MAX_CYCLES must be a number")?);
+    }
+    if let Some(extra) = positional.next() {
+        return Err(format!("Unexpected argument {extra}"));
+    }
+
+    Ok(options)
+}
+
+/// Like [`Machine::run`], feeding the keyboard, drawing the screen and
+/// profiling along the way.
+fn run(
+    machine: &mut Machine,
+    max_cycles: u64,
+    mut keys: Option<KeyScript>,
+    live: Option<TextMode>,
+    mut profiler: Option<&mut Profiler>,
+) -> Result<Stop, Fault> {
+    for _ in 0..max_cycles {
+        if let Some(keys) = &mut keys {
+            keys.apply(machine);
+        }
+        if machine.is_halted() {
+            return Ok(Stop::Halted);
+        }
+        match &mut profiler {
+            Some(profiler) => profiler.step(machine)?,
+            None => machine.step()?,
+        }
+
+        if let Some(mode) = live {
+            if machine.cycles().is_multiple_of(LIVE_INTERVAL) {
+                // move the cursor home and draw over the previous frame
+                print!("\x1b[H{}", screen::to_text(screen::screen(machine), mode));
+            }
+        }
+    }
+
+    Ok(if machine.is_halted() {
+        Stop::Halted
+    } else {
+        Stop::CycleLimit
+    })
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{msg}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if options.help {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    let path = Path::new(&options.file);
+    let (program, info) = match debugger::load(path) {
+        Ok(loaded) => loaded,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let keys = match &options.keys {
+        Some(file) => match fs::read_to_string(file)
+            .map_err(|err| err.to_string())
+            .and_then(|text| KeyScript::parse(&text))
+        {
+            Ok(keys) => Some(keys),
+            Err(msg) => {
+                eprintln!("{file}: {msg}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
+    let mut machine = Machine::new();
+    machine.load(&program);
+    if options.live.is_some() {
+        print!("\x1b[2J");
+    }
+
+    let profiling = options.profile.is_some() || options.collapsed.is_some();
+    let root = path
+        .file_stem()
+        .map_or("program".into(), |stem| stem.to_string_lossy());
+    let mut profiler = profiling.then(|| Profiler::new(&info, &root));
+
+    let max_cycles = options.max_cycles.unwrap_or(DEFAULT_CYCLES);
+    match run(
+        &mut machine,
+        max_cycles,
+        keys,
+        options.live,
+        profiler.as_mut(),
+    ) {
+        Ok(Stop::Halted) => println!("halted after {} cycles", machine.cycles()),
+        Ok(Stop::CycleLimit) => println!("stopped after {} cycles", machine.cycles()),
+        Err(fault) => {
+            eprintln!("{fault}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    println!("A={} D={} PC={}", machine.a, machine.d, machine.pc);
+    for (addr, value) in machine.ram()[..16].iter().enumerate() {
+        println!("RAM[{addr}] = {}", *value as i16);
+    }
+
+    if let Some(profiler) = &profiler {
+        let outputs = [
+            (&options.profile, profiler.report(&info, PROFILE_TOP)),
+            (&options.collapsed, profiler.collapsed_stacks()),
+        ];
+        for (file, contents) in outputs {
+            let Some(file) = file else { continue };
+            if let Err(err) = fs::write(file, contents) {
+                eprintln!("Could not write {file}: {err}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let screen = screen::screen(&machine);
+    if let Some(mode) = options.show {
+        print!("{}", screen::to_text(screen, mode));
+    }
+    if let Some(file) = &options.png {
+        if let Err(err) = fs::write(file, screen::to_png(screen)) {
+            eprintln!("Could not write {file}: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
+    if let Some(file) = &options.expect_png {
+        // the pixels are compared, not the bytes of the file
+        let golden = fs::read(file)
+            .map_err(|err| err.to_string())
+            .and_then(|png| screen::from_png(&png));
+        match golden {
+            Ok(golden) if golden == screen => println!("screen matches {file}"),
+            Ok(golden) => {
+                let differing: u32 = golden
+                    .iter()
+                    .zip(screen)
+                    .map(|(golden, word)| (golden ^ word).count_ones())
+                    .sum();
+                eprintln!("screen differs from {file} in {differing} pixels");
+                return ExitCode::FAILURE;
+            }
+            Err(err) => {
+                eprintln!("Could not read {file}: {err}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_options() {
+        let options =
+            parse(&["Pong.asm", "5000", "--keys", "keys.txt", "--live", "blocks"]).unwrap();
+        assert_eq!(options.file, "Pong.asm");
+        assert_eq!(options.max_cycles, Some(5000));
+        assert_eq!(options.keys.as_deref(), Some("keys.txt"));
+        assert_eq!(options.live, Some(TextMode::HalfBlock));
+
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["a.asm", "many"]).is_err());
+        assert!(parse(&["a.asm", "--show", "ascii"]).is_err());
+        assert!(parse(&["a.asm", "--png"]).is_err());
+    }
+}
@@ -0,0 +1,376 @@
+//! Counts where a program spends its cycles.
+//!
+//! Besides per instruction counts, cycles are attributed to VM functions
+//! using the labels the VM translator emits: a function `Main.main` starts at
+//! the label `(Main.main)`, while labels with a `$` in their name, such as
+//! `Main.main$ret.1`, are internal to a function. Calls are followed at run
+//! time to build the call stacks written in the collapsed stack format of
+//! flame graph tools.
+
+use crate::debugger::DebugInfo;
+use crate::machine::{Fault, Machine, ROM_SIZE};
+use std::collections::{BTreeMap, HashMap};
+
+/// Whether a label starts a VM function: `Class.name`, both parts being
+/// identifiers. Labels internal to a function contain a `$`, and those local
+/// to an assembler macro expansion have two dots, `NAME.N.LABEL`.
+fn is_function(label: &str) -> bool {
+    let is_identifier = |part: &str| {
+        part.chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    label
+        .split_once('.')
+        .is_some_and(|(class, name)| is_identifier(class) && is_identifier(name))
+}
+
+/// A node of the call tree: a function called from its parent node.
+struct Frame {
+    parent: usize,
+    function: usize,
+}
+
+pub struct Profiler {
+    counts: Vec<u64>,
+    functions: Vec<String>,
+    /// Function index by entry address.
+    entries: HashMap<u16, usize>,
+    /// The label naming each labelled address.
+    labels: BTreeMap<u16, String>,
+    frames: Vec<Frame>,
+    frame_ids: HashMap<(usize, usize), usize>,
+    frame_counts: Vec<u64>,
+    /// The frame each active call returns to, and where it may return to.
+    stack: Vec<(usize, [u16; 2])>,
+    frame: usize,
+}
+
+impl Profiler {
+    /// `root` names the code running outside of any function, such as the
+    /// bootstrap code.
+    pub fn new(info: &DebugInfo, root: &str) -> Self {
+        let mut functions = vec![root.to_string()];
+        let mut entries = HashMap::new();
+        let mut labels = BTreeMap::new();
+        // a function's label names its address over the other labels there
+        for (label, addr) in info.labels() {
+            if is_function(label) {
+                labels.insert(addr, label.to_string());
+                entries.insert(addr, functions.len());
+                functions.push(label.to_string());
+            } else {
+                labels.entry(addr).or_insert_with(|| label.to_string());
+            }
+        }
+
+        Self {
+            counts: vec![0; ROM_SIZE],
+            functions,
+            entries,
+            labels,
+            frames: vec![Frame {
+                parent: 0,
+                function: 0,
+            }],
+            frame_ids: HashMap::new(),
+            frame_counts: vec![0],
+            stack: Vec::new(),
+            frame: 0,
+        }
+    }
+
+    /// Executes one instruction, counting it.
+    pub fn step(&mut self, machine: &mut Machine) -> Result<(), Fault> {
+        let (pc, target) = (machine.pc, machine.a);
+        let word = machine.rom()[pc as usize];
+        machine.step()?;
+        self.counts[pc as usize] += 1;
+        self.frame_counts[self.frame] += 1;
+
+        // a jump can land on the next instruction, so look at the instruction
+        // rather than at whether the PC moved on
+        let next = machine.pc;
+        let is_jump = word & 0x8000 != 0 && word & 0b111 != 0;
+        if !is_jump || next != target {
+            return Ok(());
+        }
+
+        if let Some(depth) = self.stack.iter().rposition(|(_, ret)| ret.contains(&next)) {
+            self.frame = self.stack[depth].0;
+            self.stack.truncate(depth);
+        } else if let (Some(&function), true) = (
+            self.entries.get(&next),
+            // calls set LCL to SP right before jumping to the callee
+            machine.read(1) == machine.read(0),
+        ) {
+            // the translator's calls return right after their jump, others
+            // may jump through shared code, but every call saves the return
+            // address five words below the callee's LCL
+            let frame_ret = machine.read(machine.read(1).wrapping_sub(5));
+            self.stack
+                .push((self.frame, [pc.wrapping_add(1), frame_ret]));
+            self.frame = self.child(self.frame, function);
+        }
+        Ok(())
+    }
+
+    fn child(&mut self, parent: usize, function: usize) -> usize {
+        *self.frame_ids.entry((parent, function)).or_insert_with(|| {
+            self.frames.push(Frame { parent, function });
+            self.frame_counts.push(0);
+            self.frames.len() - 1
+        })
+    }
+
+    pub fn count(&self, addr: u16) -> u64 {
+        self.counts[addr as usize]
+    }
+
+    fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Sums the counts of every address by the closest label before it,
+    /// keeping the labels `keep` accepts.
+    fn by_region(&self, keep: impl Fn(&str) -> bool, outside: &str) -> Vec<(String, u64)> {
+        let mut totals: HashMap<&str, u64> = HashMap::new();
+        let mut region = outside;
+        let mut labels = self.labels.iter().peekable();
+
+        for (addr, &count) in self.counts.iter().enumerate() {
+            while let Some((_, label)) = labels.next_if(|(&at, _)| at as usize <= addr) {
+                if keep(label) {
+                    region = label;
+                }
+            }
+            if count > 0 {
+                *totals.entry(region).or_default() += count;
+            }
+        }
+
+        let mut totals: Vec<_> = totals
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), count))
+            .collect();
+        totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        totals
+    }
+
+    /// Cycles spent in each VM function's own code, most expensive first.
+    pub fn by_function(&self) -> Vec<(String, u64)> {
+        self.by_region(is_function, &self.functions[0])
+    }
+
+    /// Cycles spent after each label, most expensive first.
+    pub fn by_label(&self) -> Vec<(String, u64)> {
+        self.by_region(|_| true, &self.functions[0])
+    }
+
+    /// Renders a report of where the cycles went: by function, by label, and
+    /// the `top` most executed instructions.
+    pub fn report(&self, info: &DebugInfo, top: usize) -> String {
+        let total = self.total().max(1);
+        let percent = |count: u64| count as f64 * 100.0 / total as f64;
+
+        let mut out = format!("{} cycles\n", self.total());
+        for (title, rows) in [("function", self.by_function()), ("label", self.by_label())] {
+            out += &format!("\n{:>12}  {:>6}  {title}\n", "cycles", "%");
+            for (name, count) in rows {
+                out += &format!("{count:>12}  {:>5.1}%  {name}\n", percent(count));
+            }
+        }
+
+        let mut hot: Vec<_> = (0..ROM_SIZE as u16)
+            .filter(|&addr| self.count(addr) > 0)
+            .collect();
+        hot.sort_by_key(|&addr| (std::cmp::Reverse(self.count(addr)), addr));
+        out += &format!("\n{:>12}  {:>6}  instruction\n", "cycles", "%");
+        for addr in hot.into_iter().take(top) {
+            let label = self
+                .labels
+                .range(..=addr)
+                .next_back()
+                .map(|(&at, label)| (label.as_str(), at));
+            let location = match (label, info.source_line(addr)) {
+                (Some((label, at)), Some(line)) => {
+                    format!("<{label}+{}>  line {}  {}", addr - at, line.line, line.text)
+                }
+                (Some((label, at)), None) => format!("<{label}+{}>", addr - at),
+                (None, Some(line)) => format!("line {}  {}", line.line, line.text),
+                (None, None) => String::new(),
+            };
+            out += &format!(
+                "{:>12}  {:>5.1}%  {addr:05}  {location}\n",
+                self.count(addr),
+                percent(self.count(addr))
+            );
+        }
+        out
+    }
+
+    /// The call stacks cycles were spent in, one `caller;callee count` line
+    /// per stack, as read by flame graph tools.
+    pub fn collapsed_stacks(&self) -> String {
+        let mut lines: Vec<_> = (0..self.frames.len())
+            .filter(|&frame| self.frame_counts[frame] > 0)
+            .map(|frame| {
+                let mut names = Vec::new();
+                let mut id = frame;
+                loop {
+                    names.push(self.functions[self.frames[id].function].as_str());
+                    if id == 0 {
+                        break;
+                    }
+                    id = self.frames[id].parent;
+                }
+                names.reverse();
+                format!("{} {}", names.join(";"), self.frame_counts[frame])
+            })
+            .collect();
+        lines.sort();
+        lines.join("\n") + "\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger;
+    use std::fs;
+
+    /// `Sys.init` calls `Main.twice`, which calls `Main.once` twice, each
+    /// call pushing its return address and setting LCL the way the VM
+    /// translator does. `Main.once` shares its address with the label of a
+    /// macro expansion.
+    const PROGRAM: &str = "\
+@256
+D=A
+@SP
+M=D
+@LCL
+M=D
+@Sys.init
+0;JMP
+(Sys.init)
+@Sys.init$ret.0
+D=A
+@SP
+AM=M+1
+A=A-1
+M=D
+@SP
+D=M
+@LCL
+M=D
+@Main.twice
+0;JMP
+(Sys.init$ret.0)
+(END)
+@END
+0;JMP
+(Main.twice)
+@Main.twice$ret.0
+D=A
+@SP
+AM=M+1
+A=A-1
+M=D
+@SP
+D=M
+@LCL
+M=D
+@Main.once
+0;JMP
+(Main.twice$ret.0)
+@Main.twice$ret.1
+D=A
+@SP
+AM=M+1
+A=A-1
+M=D
+@SP
+D=M
+@LCL
+M=D
+@Main.once
+0;JMP
+(Main.twice$ret.1)
+@SP
+AM=M-1
+A=M
+0;JMP
+(RETURN.0.START)
+(Main.once)
+@SP
+AM=M-1
+A=M
+0;JMP
+";
+
+    fn profile() -> (Profiler, DebugInfo) {
+        let path = std::env::temp_dir().join(format!("hack-profile-{}.asm", std::process::id()));
+        fs::write(&path, PROGRAM).unwrap();
+        let (program, info) = debugger::load(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let mut machine = Machine::new();
+        machine.load(&program);
+        let mut profiler = Profiler::new(&info, "bootstrap");
+        while !machine.is_halted() {
+            profiler.step(&mut machine).unwrap();
+        }
+        (profiler, info)
+    }
+
+    #[test]
+    fn aggregates_by_function() {
+        let (profiler, _) = profile();
+        assert_eq!(profiler.count(0), 1);
+        assert_eq!(profiler.count(50), 2);
+        assert_eq!(
+            profiler.by_function(),
+            [
+                ("Main.twice".to_string(), 28),
+                ("Sys.init".to_string(), 13),
+                ("Main.once".to_string(), 8),
+                ("bootstrap".to_string(), 8),
+            ]
+        );
+        let labels = profiler.by_label();
+        assert!(labels.contains(&("Main.twice$ret.1".to_string(), 4)));
+        assert!(labels.contains(&("END".to_string(), 1)));
+    }
+
+    #[test]
+    fn recognizes_function_labels() {
+        assert!(is_function("Main.main"));
+        assert!(is_function("Screen_2.draw_line"));
+        assert!(!is_function("Main.main$ret.0"));
+        assert!(!is_function("RETURN.0.START"));
+        assert!(!is_function("LOOP"));
+        assert!(!is_function("Main."));
+        assert!(!is_function("1.main"));
+    }
+
+    #[test]
+    fn follows_calls() {
+        let (profiler, _) = profile();
+        assert_eq!(
+            profiler.collapsed_stacks(),
+            "bootstrap 8\n\
+             bootstrap;Sys.init 13\n\
+             bootstrap;Sys.init;Main.twice 28\n\
+             bootstrap;Sys.init;Main.twice;Main.once 8\n"
+        );
+    }
+
+    #[test]
+    fn reports_hot_instructions() {
+        let (profiler, info) = profile();
+        let report = profiler.report(&info, 1);
+        assert!(report.starts_with("57 cycles\n"));
+        assert!(report.ends_with("  00050  <Main.once+0>  line 59  @SP\n"));
+    }
+}
@@ -0,0 +1,306 @@
+//! Renders the memory mapped screen: 512x256 pixels, 32 words per row, the
+//! least significant bit of a word being its leftmost pixel and a set bit a
+//! black pixel.
+
+use crate::machine::{Machine, SCREEN, SCREEN_SIZE};
+
+pub const WIDTH: usize = 512;
+pub const HEIGHT: usize = 256;
+const WORDS_PER_ROW: usize = WIDTH / 16;
+
+/// How the screen is drawn in a terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextMode {
+    /// Braille patterns, 2x4 pixels per character: 256x64 characters.
+    Braille,
+    /// Half blocks, 1x2 pixels per character: 512x128 characters.
+    HalfBlock,
+}
+
+impl TextMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "braille" => Some(TextMode::Braille),
+            "blocks" => Some(TextMode::HalfBlock),
+            _ => None,
+        }
+    }
+}
+
+/// The screen memory map of a machine.
+pub fn screen(machine: &Machine) -> &[u16] {
+    &machine.ram()[SCREEN as usize..SCREEN as usize + SCREEN_SIZE]
+}
+
+/// Whether the pixel at `x`, `y` is black.
+pub fn pixel(screen: &[u16], x: usize, y: usize) -> bool {
+    screen[y * WORDS_PER_ROW + x / 16] >> (x % 16) & 1 == 1
+}
+
+/// Encodes the screen as a 1-bit grayscale PNG.
+pub fn to_png(screen: &[u16]) -> Vec<u8> {
+    // each row is a filter type byte followed by the pixels, 8 per byte with
+    // the leftmost one in the most significant bit and 1 meaning white
+    let mut pixels = Vec::with_capacity(HEIGHT * (1 + WIDTH / 8));
+    for row in screen.chunks(WORDS_PER_ROW) {
+        pixels.push(0);
+        for word in row {
+            let word = !word.reverse_bits();
+            pixels.extend(word.to_be_bytes());
+        }
+    }
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png_chunk(&mut png, b"IHDR", &png_header());
+    png_chunk(&mut png, b"IDAT", &zlib_stored(&pixels));
+    png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Decodes a PNG written by [`to_png`]. Other PNG files are rejected rather
+/// than decoded: only the uncompressed, unfiltered 1-bit encoding used here
+/// is understood.
+pub fn from_png(png: &[u8]) -> Result<Vec<u16>, String> {
+    const NOT_SAVED: &str = "only PNG files saved with --png can be compared";
+
+    let mut rest = png
+        .strip_prefix(b"\x89PNG\r\n\x1a\n")
+        .ok_or("not a PNG file")?;
+    let (mut header, mut data) = (None, Vec::new());
+    while let Some(len) = rest.get(..4) {
+        let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+        let chunk = rest.get(8..8 + len).ok_or("the PNG file ends early")?;
+        match &rest[4..8] {
+            b"IHDR" => header = Some(chunk),
+            b"IDAT" => data.extend(chunk),
+            b"IEND" => break,
+            _ => {}
+        }
+        // the chunk's CRC follows its data
+        rest = rest.get(8 + len + 4..).ok_or("the PNG file ends early")?;
+    }
+    if header != Some(&png_header()[..]) {
+        return Err(format!(
+            "{NOT_SAVED}: the image is not {WIDTH}x{HEIGHT} 1-bit grayscale"
+        ));
+    }
+
+    let pixels = zlib_unstored(&data).ok_or(format!("{NOT_SAVED}: the image is compressed"))?;
+    if pixels.len() != HEIGHT * (1 + WIDTH / 8) {
+        return Err("the PNG image data has the wrong size".into());
+    }
+    let mut screen = Vec::with_capacity(SCREEN_SIZE);
+    for row in pixels.chunks(1 + WIDTH / 8) {
+        if row[0] != 0 {
+            return Err(format!("{NOT_SAVED}: the rows are filtered"));
+        }
+        for bytes in row[1..].chunks(2) {
+            let word = u16::from_be_bytes([bytes[0], bytes[1]]);
+            screen.push(!word.reverse_bits());
+        }
+    }
+    Ok(screen)
+}
+
+/// The IHDR chunk's data.
+fn png_header() -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend((WIDTH as u32).to_be_bytes());
+    header.extend((HEIGHT as u32).to_be_bytes());
+    // bit depth, color type (grayscale), compression, filter, interlace
+    header.extend([1, 0, 0, 0, 0]);
+    header
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+/// Wraps data in a zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xFFFF;
+
+    let mut out = vec![0x78, 0x01];
+    let blocks = data.len().div_ceil(MAX_BLOCK).max(1);
+    for i in 0..blocks {
+        let block = &data[i * MAX_BLOCK..data.len().min((i + 1) * MAX_BLOCK)];
+        let last = i + 1 == blocks;
+        out.push(last as u8);
+        out.extend((block.len() as u16).to_le_bytes());
+        out.extend((!(block.len() as u16)).to_le_bytes());
+        out.extend(block);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+/// The data of a zlib stream of uncompressed deflate blocks, as written by
+/// [`zlib_stored`]. `None` if any block is compressed.
+fn zlib_unstored(zlib: &[u8]) -> Option<Vec<u8>> {
+    let mut rest = zlib.strip_prefix(&[0x78, 0x01])?;
+    let mut data = Vec::new();
+    loop {
+        let &[last @ (0 | 1), len_lo, len_hi, ..] = rest else {
+            return None;
+        };
+        let len = u16::from_le_bytes([len_lo, len_hi]) as usize;
+        data.extend(rest.get(5..5 + len)?);
+        rest = &rest[5 + len..];
+        if last == 1 {
+            return Some(data);
+        }
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+/// Draws the screen with Unicode characters, black pixels being drawn.
+pub fn to_text(screen: &[u16], mode: TextMode) -> String {
+    let (cell_width, cell_height) = match mode {
+        TextMode::Braille => (2, 4),
+        TextMode::HalfBlock => (1, 2),
+    };
+
+    let mut out = String::new();
+    for top in (0..HEIGHT).step_by(cell_height) {
+        for left in (0..WIDTH).step_by(cell_width) {
+            let set = |dx: usize, dy: usize| pixel(screen, left + dx, top + dy);
+            out.push(match mode {
+                TextMode::Braille => {
+                    // braille dots are numbered down the left column, then
+                    // down the right one, with the bottom row last
+                    const DOTS: [(usize, usize); 8] = [
+                        (0, 0),
+                        (0, 1),
+                        (0, 2),
+                        (1, 0),
+                        (1, 1),
+                        (1, 2),
+                        (0, 3),
+                        (1, 3),
+                    ];
+                    let bits = DOTS
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, &(dx, dy))| set(dx, dy))
+                        .fold(0, |bits, (dot, _)| bits | 1 << dot);
+                    char::from_u32(0x2800 + bits).unwrap()
+                }
+                TextMode::HalfBlock => match (set(0, 0), set(0, 1)) {
+                    (false, false) => ' ',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (true, true) => '█',
+                },
+            });
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blank() -> Vec<u16> {
+        vec![0; SCREEN_SIZE]
+    }
+
+    #[test]
+    fn pixels_are_least_significant_bit_first() {
+        let mut screen = blank();
+        screen[0] = 0b10;
+        screen[WORDS_PER_ROW + 1] = 0x8000;
+        assert!(pixel(&screen, 1, 0));
+        assert!(!pixel(&screen, 0, 0));
+        assert!(pixel(&screen, 31, 1));
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn encodes_png() {
+        let mut screen = blank();
+        screen[0] = 1;
+        let png = to_png(&screen);
+
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR"));
+        assert!(png.ends_with(b"\x00\x00\x00\x00IEND\xae\x42\x60\x82"));
+        // the first pixel is black, the rest of the row white
+        let pixels = &png[33 + 8 + 2 + 5..];
+        assert_eq!(pixels[..3], [0, 0x7F, 0xFF]);
+        assert_eq!(png.len(), 33 + 12 + 2 + 5 + HEIGHT * 65 + 4 + 12);
+    }
+
+    #[test]
+    fn decodes_png() {
+        let mut screen = blank();
+        screen[0] = 1;
+        screen[SCREEN_SIZE - 1] = 0x8001;
+        assert_eq!(from_png(&to_png(&screen)).as_ref(), Ok(&screen));
+
+        assert_eq!(
+            from_png(&to_png(&screen)[..100]),
+            Err("the PNG file ends early".into())
+        );
+        assert!(from_png(b"GIF89a").is_err());
+
+        // the same image, compressed by another encoder
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &png_header());
+        png_chunk(&mut png, b"IDAT", &[0x78, 0x9c, 0xed, 0xc1, 0x01, 0x0d]);
+        png_chunk(&mut png, b"IEND", &[]);
+        assert_eq!(
+            from_png(&png),
+            Err("only PNG files saved with --png can be compared: the image is compressed".into())
+        );
+    }
+
+    #[test]
+    fn renders_text() {
+        let mut screen = blank();
+        screen[0] = 0b11;
+        screen[3 * WORDS_PER_ROW] = 0b10;
+
+        let braille = to_text(&screen, TextMode::Braille);
+        assert_eq!(braille.lines().count(), 64);
+        assert!(braille.starts_with("\u{2889}\u{2800}"));
+
+        let blocks = to_text(&screen, TextMode::HalfBlock);
+        assert_eq!(blocks.lines().count(), 128);
+        assert!(blocks.starts_with("▀▀ "));
+        assert!(blocks.lines().nth(1).unwrap().starts_with(" ▄ "));
+    }
+}
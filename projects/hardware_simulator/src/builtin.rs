@@ -0,0 +1,351 @@
+//! The chips of `tools/builtInChips`, implemented by the simulator.
+
+use crate::parser::{parse_chip, ChipDef};
+
+/// Definitions of the built-in chips, used when no `.hdl` file of that name
+/// is found next to the chips using them.
+const DEFINITIONS: &[(&str, &str)] = &[
+    ("ALU", include_str!("../../../tools/builtInChips/ALU.hdl")),
+    (
+        "ARegister",
+        include_str!("../../../tools/builtInChips/ARegister.hdl"),
+    ),
+    (
+        "Add16",
+        include_str!("../../../tools/builtInChips/Add16.hdl"),
+    ),
+    ("And", include_str!("../../../tools/builtInChips/And.hdl")),
+    (
+        "And16",
+        include_str!("../../../tools/builtInChips/And16.hdl"),
+    ),
+    ("Bit", include_str!("../../../tools/builtInChips/Bit.hdl")),
+    ("DFF", include_str!("../../../tools/builtInChips/DFF.hdl")),
+    ("DMux", include_str!("../../../tools/builtInChips/DMux.hdl")),
+    (
+        "DMux4Way",
+        include_str!("../../../tools/builtInChips/DMux4Way.hdl"),
+    ),
+    (
+        "DMux8Way",
+        include_str!("../../../tools/builtInChips/DMux8Way.hdl"),
+    ),
+    (
+        "DRegister",
+        include_str!("../../../tools/builtInChips/DRegister.hdl"),
+    ),
+    (
+        "FullAdder",
+        include_str!("../../../tools/builtInChips/FullAdder.hdl"),
+    ),
+    (
+        "HalfAdder",
+        include_str!("../../../tools/builtInChips/HalfAdder.hdl"),
+    ),
+    (
+        "Inc16",
+        include_str!("../../../tools/builtInChips/Inc16.hdl"),
+    ),
+    (
+        "Keyboard",
+        include_str!("../../../tools/builtInChips/Keyboard.hdl"),
+    ),
+    ("Mux", include_str!("../../../tools/builtInChips/Mux.hdl")),
+    (
+        "Mux16",
+        include_str!("../../../tools/builtInChips/Mux16.hdl"),
+    ),
+    (
+        "Mux4Way16",
+        include_str!("../../../tools/builtInChips/Mux4Way16.hdl"),
+    ),
+    (
+        "Mux8Way16",
+        include_str!("../../../tools/builtInChips/Mux8Way16.hdl"),
+    ),
+    ("Nand", include_str!("../../../tools/builtInChips/Nand.hdl")),
+    ("Not", include_str!("../../../tools/builtInChips/Not.hdl")),
+    (
+        "Not16",
+        include_str!("../../../tools/builtInChips/Not16.hdl"),
+    ),
+    ("Or", include_str!("../../../tools/builtInChips/Or.hdl")),
+    ("Or16", include_str!("../../../tools/builtInChips/Or16.hdl")),
+    (
+        "Or8Way",
+        include_str!("../../../tools/builtInChips/Or8Way.hdl"),
+    ),
+    ("PC", include_str!("../../../tools/builtInChips/PC.hdl")),
+    (
+        "RAM16K",
+        include_str!("../../../tools/builtInChips/RAM16K.hdl"),
+    ),
+    (
+        "RAM4K",
+        include_str!("../../../tools/builtInChips/RAM4K.hdl"),
+    ),
+    (
+        "RAM512",
+        include_str!("../../../tools/builtInChips/RAM512.hdl"),
+    ),
+    (
+        "RAM64",
+        include_str!("../../../tools/builtInChips/RAM64.hdl"),
+    ),
+    ("RAM8", include_str!("../../../tools/builtInChips/RAM8.hdl")),
+    (
+        "ROM32K",
+        include_str!("../../../tools/builtInChips/ROM32K.hdl"),
+    ),
+    (
+        "Register",
+        include_str!("../../../tools/builtInChips/Register.hdl"),
+    ),
+    (
+        "Screen",
+        include_str!("../../../tools/builtInChips/Screen.hdl"),
+    ),
+    ("Xor", include_str!("../../../tools/builtInChips/Xor.hdl")),
+];
+
+/// The definition of a built-in chip.
+pub fn definition(name: &str) -> Option<ChipDef> {
+    let (_, source) = DEFINITIONS.iter().find(|(chip, _)| *chip == name)?;
+    Some(parse_chip(source).expect("the built-in chips are valid"))
+}
+
+/// What a `BUILTIN` chip does. The logic gates work on pins of any width, so
+/// that `And16` is `BUILTIN And` like in the course's simulator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Nand,
+    Not,
+    And,
+    Or,
+    Xor,
+    Mux,
+    DMux,
+    DMux4Way,
+    DMux8Way,
+    Mux4Way16,
+    Mux8Way16,
+    Or8Way,
+    HalfAdder,
+    FullAdder,
+    Add16,
+    Inc16,
+    Alu,
+    Dff,
+    Bit,
+    /// `Register`, `ARegister` and `DRegister`.
+    Register,
+    Pc,
+    /// A RAM of that many words.
+    Ram(usize),
+    Rom32K,
+    Screen,
+    Keyboard,
+    /// Copies its input to its output: what a part's output connected to
+    /// several signals becomes. It can't be named in HDL.
+    Wire,
+}
+
+/// Words of the screen's memory map.
+pub const SCREEN_SIZE: usize = 8192;
+/// Words of the ROM.
+pub const ROM_SIZE: usize = 32768;
+
+impl Kind {
+    /// The implementation named by `BUILTIN`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "Nand" => Kind::Nand,
+            "Not" | "Not16" => Kind::Not,
+            "And" | "And16" => Kind::And,
+            "Or" | "Or16" => Kind::Or,
+            "Xor" => Kind::Xor,
+            "Mux" | "Mux16" => Kind::Mux,
+            "DMux" => Kind::DMux,
+            "DMux4Way" => Kind::DMux4Way,
+            "DMux8Way" => Kind::DMux8Way,
+            "Mux4Way16" => Kind::Mux4Way16,
+            "Mux8Way16" => Kind::Mux8Way16,
+            "Or8Way" => Kind::Or8Way,
+            "HalfAdder" => Kind::HalfAdder,
+            "FullAdder" => Kind::FullAdder,
+            "Add16" => Kind::Add16,
+            "Inc16" => Kind::Inc16,
+            "ALU" => Kind::Alu,
+            "DFF" => Kind::Dff,
+            "Bit" => Kind::Bit,
+            "Register" | "ARegister" | "DRegister" => Kind::Register,
+            "PC" => Kind::Pc,
+            "RAM8" => Kind::Ram(8),
+            "RAM64" => Kind::Ram(64),
+            "RAM512" => Kind::Ram(512),
+            "RAM4K" => Kind::Ram(4096),
+            "RAM16K" => Kind::Ram(16384),
+            "ROM32K" => Kind::Rom32K,
+            "Screen" => Kind::Screen,
+            "Keyboard" => Kind::Keyboard,
+            _ => return None,
+        })
+    }
+
+    /// Names of the inputs, in the order [`Kind::eval`] takes them.
+    pub fn inputs(self) -> &'static [&'static str] {
+        match self {
+            Kind::Nand | Kind::And | Kind::Or | Kind::Xor | Kind::HalfAdder | Kind::Add16 => {
+                &["a", "b"]
+            }
+            Kind::Not | Kind::Or8Way | Kind::Inc16 | Kind::Dff | Kind::Wire => &["in"],
+            Kind::Mux => &["a", "b", "sel"],
+            Kind::DMux | Kind::DMux4Way | Kind::DMux8Way => &["in", "sel"],
+            Kind::Mux4Way16 => &["a", "b", "c", "d", "sel"],
+            Kind::Mux8Way16 => &["a", "b", "c", "d", "e", "f", "g", "h", "sel"],
+            Kind::FullAdder => &["a", "b", "c"],
+            Kind::Alu => &["x", "y", "zx", "nx", "zy", "ny", "f", "no"],
+            Kind::Bit | Kind::Register => &["in", "load"],
+            Kind::Pc => &["in", "load", "inc", "reset"],
+            Kind::Ram(_) | Kind::Screen => &["in", "load", "address"],
+            Kind::Rom32K => &["address"],
+            Kind::Keyboard => &[],
+        }
+    }
+
+    /// Names of the outputs, in the order [`Kind::eval`] sets them.
+    pub fn outputs(self) -> &'static [&'static str] {
+        match self {
+            Kind::DMux => &["a", "b"],
+            Kind::DMux4Way => &["a", "b", "c", "d"],
+            Kind::DMux8Way => &["a", "b", "c", "d", "e", "f", "g", "h"],
+            Kind::HalfAdder | Kind::FullAdder => &["sum", "carry"],
+            Kind::Alu => &["out", "zr", "ng"],
+            _ => &["out"],
+        }
+    }
+
+    /// Words of state the chip keeps: its registers or memory.
+    pub fn memory_size(self) -> usize {
+        match self {
+            Kind::Dff | Kind::Bit | Kind::Register | Kind::Pc | Kind::Keyboard => 1,
+            Kind::Ram(size) => size,
+            Kind::Rom32K => ROM_SIZE,
+            Kind::Screen => SCREEN_SIZE,
+            _ => 0,
+        }
+    }
+
+    /// Computes the outputs from the inputs and the state. Values wider than
+    /// their pin are cut to its width by the caller.
+    pub fn eval(self, inputs: &[u16], memory: &[u16], outputs: &mut [u16]) {
+        let word = |index: usize| memory[index % memory.len()];
+        match self {
+            Kind::Nand => outputs[0] = !(inputs[0] & inputs[1]),
+            Kind::Not => outputs[0] = !inputs[0],
+            Kind::And => outputs[0] = inputs[0] & inputs[1],
+            Kind::Or => outputs[0] = inputs[0] | inputs[1],
+            Kind::Xor => outputs[0] = inputs[0] ^ inputs[1],
+            Kind::Mux => outputs[0] = inputs[(inputs[2] & 1) as usize],
+            Kind::Mux4Way16 | Kind::Mux8Way16 => {
+                let sel = inputs[inputs.len() - 1] as usize;
+                outputs[0] = inputs[sel % (inputs.len() - 1)];
+            }
+            Kind::DMux | Kind::DMux4Way | Kind::DMux8Way => {
+                outputs.fill(0);
+                outputs[inputs[1] as usize % outputs.len()] = inputs[0];
+            }
+            Kind::Or8Way => outputs[0] = (inputs[0] != 0) as u16,
+            Kind::HalfAdder | Kind::FullAdder => {
+                let sum: u16 = inputs.iter().map(|bit| bit & 1).sum();
+                outputs[0] = sum & 1;
+                outputs[1] = sum >> 1;
+            }
+            Kind::Add16 => outputs[0] = inputs[0].wrapping_add(inputs[1]),
+            Kind::Inc16 => outputs[0] = inputs[0].wrapping_add(1),
+            Kind::Alu => {
+                let flag = |index: usize| inputs[index] & 1 == 1;
+                let operand = |value: u16, zero: bool, negate: bool| {
+                    let value = if zero { 0 } else { value };
+                    if negate {
+                        !value
+                    } else {
+                        value
+                    }
+                };
+                let x = operand(inputs[0], flag(2), flag(3));
+                let y = operand(inputs[1], flag(4), flag(5));
+                let out = if flag(6) { x.wrapping_add(y) } else { x & y };
+                let out = if flag(7) { !out } else { out };
+                outputs[0] = out;
+                outputs[1] = (out == 0) as u16;
+                outputs[2] = out >> 15;
+            }
+            Kind::Dff | Kind::Bit | Kind::Register | Kind::Pc | Kind::Keyboard => {
+                outputs[0] = memory[0]
+            }
+            Kind::Ram(_) | Kind::Screen => outputs[0] = word(inputs[2] as usize),
+            Kind::Rom32K => outputs[0] = word(inputs[0] as usize),
+            Kind::Wire => outputs[0] = inputs[0],
+        }
+    }
+
+    /// What a clocked chip stores on the next clock edge, given its inputs
+    /// when the clock rises: a word of memory and its new value.
+    pub fn clock(self, inputs: &[u16], memory: &[u16]) -> Option<(usize, u16)> {
+        let load = |index: usize| inputs[index] & 1 == 1;
+        match self {
+            Kind::Dff => Some((0, inputs[0])),
+            Kind::Bit | Kind::Register if load(1) => Some((0, inputs[0])),
+            Kind::Pc if load(3) => Some((0, 0)),
+            Kind::Pc if load(1) => Some((0, inputs[0])),
+            Kind::Pc if load(2) => Some((0, memory[0].wrapping_add(1))),
+            Kind::Ram(_) | Kind::Screen if load(1) => {
+                Some((inputs[2] as usize % memory.len(), inputs[0]))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defines_every_built_in_chip() {
+        for (name, _) in DEFINITIONS {
+            let chip = definition(name).unwrap();
+            let crate::parser::Body::Builtin { name: builtin, .. } = &chip.body else {
+                panic!("{name} is not built in");
+            };
+            let kind = Kind::from_name(builtin).unwrap();
+            for pin in kind.inputs() {
+                assert!(chip.input(pin).is_some(), "{name} has no input {pin}");
+            }
+            for pin in kind.outputs() {
+                assert!(chip.output(pin).is_some(), "{name} has no output {pin}");
+            }
+        }
+    }
+
+    #[test]
+    fn computes_the_alu() {
+        let mut out = [0; 3];
+        // x - y: zx=0 nx=1 zy=0 ny=0 f=1 no=1
+        Kind::Alu.eval(&[7, 9, 0, 1, 0, 0, 1, 1], &[], &mut out);
+        assert_eq!(out, [(-2i16) as u16, 0, 1]);
+        // 0
+        Kind::Alu.eval(&[7, 9, 1, 0, 1, 0, 1, 0], &[], &mut out);
+        assert_eq!(out, [0, 1, 0]);
+    }
+
+    #[test]
+    fn clocks_the_counter() {
+        let memory = [41];
+        assert_eq!(Kind::Pc.clock(&[5, 1, 1, 0], &memory), Some((0, 5)));
+        assert_eq!(Kind::Pc.clock(&[5, 0, 1, 0], &memory), Some((0, 42)));
+        assert_eq!(Kind::Pc.clock(&[5, 1, 1, 1], &memory), Some((0, 0)));
+        assert_eq!(Kind::Pc.clock(&[5, 0, 0, 0], &memory), None);
+    }
+}
@@ -0,0 +1,9 @@
+pub mod builtin;
+pub mod library;
+pub mod netlist;
+pub mod parser;
+pub mod simulator;
+
+pub use library::{Chip, Library};
+pub use parser::{parse_chip, ChipDef};
+pub use simulator::Simulator;
@@ -0,0 +1,85 @@
+//! Finds the definition of the chips used as parts.
+
+use crate::builtin;
+use crate::parser::{parse_chip, ChipDef};
+use assembler::Diagnostic;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// A chip definition and the file it was read from.
+#[derive(Debug)]
+pub struct Chip {
+    pub def: ChipDef,
+    pub file: String,
+}
+
+/// Chip definitions looked up by name: first as `Name.hdl` in each directory,
+/// in order, then among the built-in chips. Definitions are parsed once.
+pub struct Library {
+    dirs: Vec<PathBuf>,
+    chips: HashMap<String, Rc<Chip>>,
+}
+
+impl Library {
+    pub fn new(dirs: impl IntoIterator<Item = PathBuf>) -> Self {
+        Self {
+            dirs: dirs.into_iter().collect(),
+            chips: HashMap::new(),
+        }
+    }
+
+    /// Parses a chip file, which must define the chip it's named after.
+    pub fn load_file(&mut self, path: &Path) -> Result<Rc<Chip>, Diagnostic> {
+        let file = path.display().to_string();
+        let source = fs::read_to_string(path)
+            .map_err(|err| Diagnostic::error(0, 0..0, format!("Could not read {file}: {err}")))?;
+        let def = parse_chip(&source).map_err(|err| err.with_file(file.clone()))?;
+
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy());
+        if stem.as_deref() != Some(def.name.as_str()) {
+            return Err(Diagnostic::error(
+                0,
+                0..0,
+                format!(
+                    "the file defines chip {}, not the chip it's named after",
+                    def.name
+                ),
+            )
+            .with_file(file));
+        }
+
+        let chip = Rc::new(Chip { def, file });
+        self.chips.insert(chip.def.name.clone(), chip.clone());
+        Ok(chip)
+    }
+
+    /// The chip of that name, or `None` if it's defined nowhere.
+    pub fn chip(&mut self, name: &str) -> Result<Option<Rc<Chip>>, Diagnostic> {
+        if let Some(chip) = self.chips.get(name) {
+            return Ok(Some(chip.clone()));
+        }
+
+        let file = self
+            .dirs
+            .iter()
+            .map(|dir| dir.join(format!("{name}.hdl")))
+            .find(|file| file.is_file());
+        let chip = match file {
+            Some(file) => self.load_file(&file)?,
+            None => {
+                let Some(def) = builtin::definition(name) else {
+                    return Ok(None);
+                };
+                let chip = Rc::new(Chip {
+                    def,
+                    file: format!("builtInChips/{name}.hdl"),
+                });
+                self.chips.insert(name.to_string(), chip.clone());
+                chip
+            }
+        };
+        Ok(Some(chip))
+    }
+}
@@ -0,0 +1,145 @@
+use hardware_simulator::Simulator;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: hardware_simulator [OPTIONS] <CHIP.hdl> [PIN=VALUE]...
+
+Simulates a chip: sets its input pins, lets its logic settle and prints its
+pins. Parts are looked up next to the chip, then among the built-in chips.
+Values are decimal, or binary with a %B prefix.
+
+Options:
+  --ticks <N>  Run N clock cycles after setting the inputs
+  -h, --help   Print this help";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Options {
+    file: String,
+    inputs: Vec<(String, u16)>,
+    ticks: u64,
+    help: bool,
+}
+
+fn parse_value(text: &str) -> Option<u16> {
+    match text.strip_prefix("%B") {
+        Some(bits) => u16::from_str_radix(bits, 2).ok(),
+        None => text
+            .parse::<u16>()
+            .ok()
+            .or_else(|| text.parse::<i16>().ok().map(|value| value as u16)),
+    }
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut file = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ticks" => {
+                let ticks = args.next().ok_or("--ticks expects a value")?;
+                options.ticks = ticks.parse().map_err(|_| "--ticks must be a number")?;
+            }
+            "-h" | "--help" => options.help = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {arg}")),
+            _ if file.is_none() => file = Some(arg),
+            _ => {
+                let input = arg
+                    .split_once('=')
+                    .and_then(|(pin, value)| Some((pin.to_string(), parse_value(value)?)));
+                options
+                    .inputs
+                    .push(input.ok_or(format!("Expected PIN=VALUE, found {arg}"))?);
+            }
+        }
+    }
+
+    if !options.help {
+        options.file = file.ok_or("No chip was provided.")?;
+    }
+    Ok(options)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{msg}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if options.help {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    let mut chip = match Simulator::load(Path::new(&options.file)) {
+        Ok(chip) => chip,
+        Err(diag) => {
+            let source = diag
+                .file
+                .as_ref()
+                .and_then(|file| fs::read_to_string(file).ok())
+                .unwrap_or_default();
+            eprintln!("{}", diag.render(&source));
+            return ExitCode::FAILURE;
+        }
+    };
+    for (pin, value) in &options.inputs {
+        if let Err(msg) = chip.set(pin, *value) {
+            eprintln!("{msg}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    chip.eval();
+    for _ in 0..options.ticks {
+        chip.tick();
+        chip.tock();
+    }
+
+    println!("time {}", chip.time());
+    let netlist = chip.netlist();
+    for pin in netlist.inputs.iter().chain(&netlist.outputs) {
+        let value = chip.get(&pin.name).unwrap_or_default();
+        match pin.width {
+            1 => println!("{} = {value}", pin.name),
+            width => println!(
+                "{} = {} (%B{value:0width$b})",
+                pin.name,
+                value as i16,
+                width = width as usize
+            ),
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_inputs() {
+        assert_eq!(
+            args(&["Bit.hdl", "in=1", "load=%B1", "--ticks", "2"]),
+            Ok(Options {
+                file: "Bit.hdl".into(),
+                inputs: vec![("in".into(), 1), ("load".into(), 1)],
+                ticks: 2,
+                help: false,
+            })
+        );
+        assert_eq!(args(&["Inc16.hdl", "in=-1"]).unwrap().inputs[0].1, 0xFFFF);
+        assert!(args(&["Bit.hdl", "in"]).is_err());
+        assert!(args(&[]).is_err());
+    }
+}
@@ -0,0 +1,447 @@
+//! Flattens a chip and the parts it's made of, down to the built-in chips,
+//! into a list of primitives connected by one-bit nets.
+
+use crate::builtin::Kind;
+use crate::library::{Chip, Library};
+use crate::parser::{Body, Bus, Connection, Part, Pin, Signal};
+use assembler::Diagnostic;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Index of a one-bit net.
+pub type Net = usize;
+
+/// The net that is always 0.
+pub const FALSE: Net = 0;
+/// The net that is always 1.
+pub const TRUE: Net = 1;
+
+/// The nets of each bit of a pin, least significant bit first.
+pub type Nets = Vec<Net>;
+
+/// An instance of a built-in chip.
+#[derive(Debug)]
+pub struct Primitive {
+    pub kind: Kind,
+    /// The name of the chip, such as `DRegister`, by which scripts access its
+    /// memory.
+    pub chip: String,
+    /// The nets of each input, in the order of [`Kind::inputs`].
+    pub inputs: Vec<Nets>,
+    pub outputs: Vec<Nets>,
+    /// Whether each input affects the outputs right away, rather than on the
+    /// next clock edge.
+    pub combinational: Vec<bool>,
+}
+
+/// A flattened chip: its primitives, in an order where each one comes after
+/// those its combinational inputs depend on.
+#[derive(Debug)]
+pub struct Netlist {
+    pub nets: usize,
+    pub primitives: Vec<Primitive>,
+    pub inputs: Vec<Pin>,
+    pub outputs: Vec<Pin>,
+    /// The nets of the chip's pins and internal pins.
+    pub signals: HashMap<String, Nets>,
+}
+
+impl Netlist {
+    /// Flattens a chip. Its parts are looked up in `library`.
+    pub fn build(chip: &Rc<Chip>, library: &mut Library) -> Result<Self, Diagnostic> {
+        let mut builder = Builder {
+            library,
+            nets: 2,
+            primitives: Vec::new(),
+            stack: Vec::new(),
+        };
+
+        let mut signals = HashMap::new();
+        let mut pins = |pins: &[Pin], builder: &mut Builder| {
+            for pin in pins {
+                let nets = (0..pin.width).map(|_| builder.net()).collect();
+                signals.insert(pin.name.clone(), nets);
+            }
+        };
+        pins(&chip.def.inputs, &mut builder);
+        pins(&chip.def.outputs, &mut builder);
+        let internal = builder.instantiate(chip, &signals)?;
+        signals.extend(internal);
+
+        let primitives = order(builder.primitives, builder.nets)
+            .map_err(|message| Diagnostic::error(0, 0..0, message).with_file(chip.file.clone()))?;
+        Ok(Self {
+            nets: builder.nets,
+            primitives,
+            inputs: chip.def.inputs.clone(),
+            outputs: chip.def.outputs.clone(),
+            signals,
+        })
+    }
+}
+
+struct Builder<'a> {
+    library: &'a mut Library,
+    nets: usize,
+    primitives: Vec<Primitive>,
+    /// The chips being instantiated, to catch chips made of themselves.
+    stack: Vec<String>,
+}
+
+/// A pin's bits, `first..=last`, given as a range or the whole pin.
+fn bits(bus: &Bus, width: u16) -> Option<(u16, u16)> {
+    match bus.range {
+        Some((first, last)) if last < width => Some((first, last)),
+        Some(_) => None,
+        None => Some((0, width - 1)),
+    }
+}
+
+impl Builder<'_> {
+    fn net(&mut self) -> Net {
+        self.nets += 1;
+        self.nets - 1
+    }
+
+    /// Adds the primitives of a chip whose pins are connected to `pins`,
+    /// returning the nets of its internal pins.
+    fn instantiate(
+        &mut self,
+        chip: &Rc<Chip>,
+        pins: &HashMap<String, Nets>,
+    ) -> Result<HashMap<String, Nets>, Diagnostic> {
+        let parts = match &chip.def.body {
+            Body::Parts(parts) => parts,
+            Body::Builtin { name, clocked } => {
+                self.builtin(chip, name, clocked, pins)?;
+                return Ok(HashMap::new());
+            }
+        };
+
+        let error = |line: usize, span: &std::ops::Range<usize>, message: String| {
+            Diagnostic::error(line, span.clone(), message).with_file(chip.file.clone())
+        };
+
+        self.stack.push(chip.def.name.clone());
+        let mut children = Vec::with_capacity(parts.len());
+        for part in parts {
+            let child = match self.library.chip(&part.chip)? {
+                Some(child) => child,
+                None => {
+                    return Err(error(
+                        part.line,
+                        &part.span,
+                        format!("chip {} is not defined", part.chip),
+                    ))
+                }
+            };
+            if self.stack.contains(&child.def.name) {
+                return Err(error(
+                    part.line,
+                    &part.span,
+                    format!("chip {} is made of itself", part.chip),
+                ));
+            }
+            children.push(child);
+        }
+
+        // internal pins take the width of the part outputs they're connected
+        // to
+        let mut internal: HashMap<String, Nets> = HashMap::new();
+        for (part, child) in parts.iter().zip(&children) {
+            for connection in &part.connections {
+                let (Some(pin), Signal::Bus(bus)) =
+                    (child.def.output(&connection.pin.name), &connection.signal)
+                else {
+                    continue;
+                };
+                if chip.def.input(&bus.name).is_some() || chip.def.output(&bus.name).is_some() {
+                    continue;
+                }
+                let Some((first, last)) = bits(&connection.pin, pin.width) else {
+                    continue;
+                };
+                if bus.range.is_some() {
+                    return Err(error(
+                        connection.line,
+                        &connection.span,
+                        format!("internal pin {} can't be subscripted", bus.name),
+                    ));
+                }
+                let width = (last - first + 1) as usize;
+                match internal.get(&bus.name) {
+                    Some(nets) if nets.len() != width => return Err(error(
+                        connection.line,
+                        &connection.span,
+                        format!(
+                            "internal pin {} is {width} bits wide here, but {} bits wide elsewhere",
+                            bus.name,
+                            nets.len(),
+                        ),
+                    )),
+                    Some(_) => {}
+                    None => {
+                        let nets = (0..width).map(|_| self.net()).collect();
+                        internal.insert(bus.name.clone(), nets);
+                    }
+                }
+            }
+        }
+
+        let mut driven = HashSet::new();
+        for (part, child) in parts.iter().zip(&children) {
+            let child_pins = self
+                .connect(chip, pins, &internal, &mut driven, part, child)
+                .map_err(|(connection, message)| {
+                    error(connection.line, &connection.span, message)
+                })?;
+            self.instantiate(child, &child_pins)?;
+        }
+        self.stack.pop();
+
+        Ok(internal)
+    }
+
+    /// The nets of the pins of a part, given its connections.
+    fn connect<'p>(
+        &mut self,
+        chip: &Chip,
+        pins: &HashMap<String, Nets>,
+        internal: &HashMap<String, Nets>,
+        driven: &mut HashSet<Net>,
+        part: &'p Part,
+        child: &Chip,
+    ) -> Result<HashMap<String, Nets>, (&'p Connection, String)> {
+        let mut child_inputs: HashMap<&str, Nets> = child
+            .def
+            .inputs
+            .iter()
+            .map(|pin| (pin.name.as_str(), vec![FALSE; pin.width as usize]))
+            .collect();
+        // the nets each bit of each output drives
+        let mut child_outputs: HashMap<&str, Vec<Nets>> = child
+            .def
+            .outputs
+            .iter()
+            .map(|pin| (pin.name.as_str(), vec![Vec::new(); pin.width as usize]))
+            .collect();
+
+        for connection in &part.connections {
+            let fail = |message: String| (connection, message);
+            let name = &connection.pin.name;
+            let (pin, is_input) = match (child.def.input(name), child.def.output(name)) {
+                (Some(pin), _) => (pin, true),
+                (None, Some(pin)) => (pin, false),
+                (None, None) => return Err(fail(format!("chip {} has no pin {name}", part.chip))),
+            };
+            let Some((first, last)) = bits(&connection.pin, pin.width) else {
+                return Err(fail(format!(
+                    "{} is out of the {} bits of pin {name}",
+                    connection.pin, pin.width
+                )));
+            };
+            let width = (last - first + 1) as usize;
+
+            let nets = match &connection.signal {
+                // the course's simulator lets outputs be thrown away this way
+                Signal::Const(_) if !is_input => continue,
+                Signal::Const(value) => vec![if *value { TRUE } else { FALSE }; width],
+                Signal::Bus(bus) => {
+                    let (nets, external) = if let Some(pin) = chip.def.input(&bus.name) {
+                        if !is_input {
+                            return Err(fail(format!(
+                                "output {name} can't be connected to input pin {}",
+                                pin.name
+                            )));
+                        }
+                        (&pins[&bus.name], true)
+                    } else if let Some(pin) = chip.def.output(&bus.name) {
+                        if is_input {
+                            return Err(fail(format!(
+                                "output pin {} can't be used as an input of a part",
+                                pin.name
+                            )));
+                        }
+                        (&pins[&bus.name], true)
+                    } else if let Some(nets) = internal.get(&bus.name) {
+                        (nets, false)
+                    } else {
+                        return Err(fail(format!(
+                            "internal pin {} is not connected to any part's output",
+                            bus.name
+                        )));
+                    };
+                    if !external && bus.range.is_some() {
+                        return Err(fail(format!(
+                            "internal pin {} can't be subscripted",
+                            bus.name
+                        )));
+                    }
+                    let Some((from, to)) = bits(bus, nets.len() as u16) else {
+                        return Err(fail(format!(
+                            "{bus} is out of the {} bits of {}",
+                            nets.len(),
+                            bus.name
+                        )));
+                    };
+                    let nets = &nets[from as usize..=to as usize];
+                    if nets.len() != width {
+                        return Err(fail(format!(
+                            "{} is {width} bits wide, but {bus} is {} bits wide",
+                            connection.pin,
+                            nets.len()
+                        )));
+                    }
+                    nets.to_vec()
+                }
+            };
+
+            let bits = first as usize..=last as usize;
+            if is_input {
+                child_inputs.get_mut(name.as_str()).unwrap()[bits].copy_from_slice(&nets);
+            } else {
+                let outputs = child_outputs.get_mut(name.as_str()).unwrap();
+                for (targets, net) in outputs[bits].iter_mut().zip(nets) {
+                    if !driven.insert(net) {
+                        return Err(fail(format!(
+                            "{} is driven by more than one output",
+                            connection.signal
+                        )));
+                    }
+                    targets.push(net);
+                }
+            }
+        }
+
+        let mut child_pins: HashMap<String, Nets> = child_inputs
+            .into_iter()
+            .map(|(name, nets)| (name.to_string(), nets))
+            .collect();
+        for (name, outputs) in child_outputs {
+            let mut nets = Vec::with_capacity(outputs.len());
+            for targets in outputs {
+                let Some((&first, others)) = targets.split_first() else {
+                    nets.push(self.net());
+                    continue;
+                };
+                for &other in others {
+                    self.primitives.push(Primitive {
+                        kind: Kind::Wire,
+                        chip: String::new(),
+                        inputs: vec![vec![first]],
+                        outputs: vec![vec![other]],
+                        combinational: vec![true],
+                    });
+                }
+                nets.push(first);
+            }
+            child_pins.insert(name.to_string(), nets);
+        }
+        Ok(child_pins)
+    }
+
+    fn builtin(
+        &mut self,
+        chip: &Chip,
+        name: &str,
+        clocked: &[String],
+        pins: &HashMap<String, Nets>,
+    ) -> Result<(), Diagnostic> {
+        let error =
+            |message: String| Diagnostic::error(0, 0..0, message).with_file(chip.file.clone());
+        let Some(kind) = Kind::from_name(name) else {
+            return Err(error(format!("there is no built-in chip {name}")));
+        };
+
+        let nets = |names: &[&str], declared: &[Pin]| {
+            names
+                .iter()
+                .map(|name| match declared.iter().find(|pin| pin.name == *name) {
+                    Some(pin) => Ok(pins[&pin.name].clone()),
+                    None => Err(error(format!(
+                        "built-in chip {} needs a pin named {name}",
+                        chip.def.name
+                    ))),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let inputs = nets(kind.inputs(), &chip.def.inputs)?;
+        let outputs = nets(kind.outputs(), &chip.def.outputs)?;
+
+        self.primitives.push(Primitive {
+            kind,
+            chip: chip.def.name.clone(),
+            inputs,
+            outputs,
+            combinational: kind
+                .inputs()
+                .iter()
+                .map(|input| !clocked.iter().any(|name| name == input))
+                .collect(),
+        });
+        Ok(())
+    }
+}
+
+/// Sorts primitives so that each comes after those driving its combinational
+/// inputs, failing if they drive each other in a loop.
+fn order(primitives: Vec<Primitive>, nets: usize) -> Result<Vec<Primitive>, String> {
+    let mut driver = vec![None; nets];
+    for (index, primitive) in primitives.iter().enumerate() {
+        for &net in primitive.outputs.iter().flatten() {
+            driver[net] = Some(index);
+        }
+    }
+
+    let mut dependents = vec![Vec::new(); primitives.len()];
+    let mut pending = vec![0; primitives.len()];
+    for (index, primitive) in primitives.iter().enumerate() {
+        let mut inputs: Vec<usize> = primitive
+            .inputs
+            .iter()
+            .zip(&primitive.combinational)
+            .filter(|(_, combinational)| **combinational)
+            .flat_map(|(nets, _)| nets)
+            .filter_map(|&net| driver[net])
+            .collect();
+        inputs.sort_unstable();
+        inputs.dedup();
+        pending[index] = inputs.len();
+        for input in inputs {
+            dependents[input].push(index);
+        }
+    }
+
+    let mut ready: Vec<usize> = (0..primitives.len())
+        .filter(|&index| pending[index] == 0)
+        .collect();
+    let mut sorted = Vec::with_capacity(primitives.len());
+    while let Some(index) = ready.pop() {
+        sorted.push(index);
+        for &dependent in &dependents[index] {
+            pending[dependent] -= 1;
+            if pending[dependent] == 0 {
+                ready.push(dependent);
+            }
+        }
+    }
+
+    if sorted.len() < primitives.len() {
+        let mut chips: Vec<&str> = (0..primitives.len())
+            .filter(|&index| pending[index] > 0 && primitives[index].kind != Kind::Wire)
+            .map(|index| primitives[index].chip.as_str())
+            .collect();
+        chips.sort_unstable();
+        chips.dedup();
+        return Err(format!(
+            "the chip has a combinational loop through {}",
+            chips.join(", ")
+        ));
+    }
+
+    let mut primitives: Vec<Option<Primitive>> = primitives.into_iter().map(Some).collect();
+    Ok(sorted
+        .into_iter()
+        .map(|index| primitives[index].take().unwrap())
+        .collect())
+}
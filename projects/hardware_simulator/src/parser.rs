@@ -0,0 +1,507 @@
+//! Parses chip definitions written in the Nand2Tetris HDL.
+
+use assembler::Diagnostic;
+use std::fmt;
+use std::ops::Range;
+
+/// Widest bus the HDL allows.
+pub const MAX_WIDTH: u16 = 16;
+
+/// An `IN` or `OUT` pin of a chip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pin {
+    pub name: String,
+    pub width: u16,
+}
+
+/// A pin or signal, optionally narrowed to the bits `first..=last`, as in
+/// `a`, `a[3]` or `a[0..7]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bus {
+    pub name: String,
+    pub range: Option<(u16, u16)>,
+}
+
+impl fmt::Display for Bus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.range {
+            Some((first, last)) if first == last => write!(f, "{}[{first}]", self.name),
+            Some((first, last)) => write!(f, "{}[{first}..{last}]", self.name),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+/// What a part's pin is connected to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signal {
+    Bus(Bus),
+    /// `true` or `false`, which fill every bit of the pin they're connected
+    /// to.
+    Const(bool),
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Signal::Bus(bus) => write!(f, "{bus}"),
+            Signal::Const(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// `pin=signal` in a part.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    pub pin: Bus,
+    pub signal: Signal,
+    pub line: usize,
+    pub span: Range<usize>,
+}
+
+/// A chip used as a part of another, such as `Not(in=a, out=b)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    pub chip: String,
+    pub connections: Vec<Connection>,
+    pub line: usize,
+    pub span: Range<usize>,
+}
+
+/// How a chip is implemented.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body {
+    Parts(Vec<Part>),
+    /// `BUILTIN Name;`, implemented by the simulator. `CLOCKED` lists the
+    /// inputs that only matter on a clock edge.
+    Builtin {
+        name: String,
+        clocked: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChipDef {
+    pub name: String,
+    pub inputs: Vec<Pin>,
+    pub outputs: Vec<Pin>,
+    pub body: Body,
+}
+
+impl ChipDef {
+    pub fn input(&self, name: &str) -> Option<&Pin> {
+        self.inputs.iter().find(|pin| pin.name == name)
+    }
+
+    pub fn output(&self, name: &str) -> Option<&Pin> {
+        self.outputs.iter().find(|pin| pin.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    Word,
+    Number(u16),
+    /// One of `{}()[],;:=`, or `..`.
+    Symbol,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    text: String,
+    line: usize,
+    span: Range<usize>,
+}
+
+impl Token {
+    fn error(&self, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::error(self.line, self.span.clone(), message)
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, Diagnostic> {
+    let mut tokens = Vec::new();
+    let mut in_comment = false;
+
+    for (row, line) in source.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut col = 0;
+
+        while col < chars.len() {
+            if in_comment {
+                if chars[col..].starts_with(&['*', '/']) {
+                    in_comment = false;
+                    col += 1;
+                }
+                col += 1;
+                continue;
+            }
+
+            let start = col;
+            let c = chars[col];
+            let kind = match c {
+                _ if c.is_whitespace() => {
+                    col += 1;
+                    continue;
+                }
+                '/' if chars.get(col + 1) == Some(&'/') => break,
+                '/' if chars.get(col + 1) == Some(&'*') => {
+                    in_comment = true;
+                    col += 2;
+                    continue;
+                }
+                '.' if chars.get(col + 1) == Some(&'.') => {
+                    col += 2;
+                    TokenKind::Symbol
+                }
+                '{' | '}' | '(' | ')' | '[' | ']' | ',' | ';' | ':' | '=' => {
+                    col += 1;
+                    TokenKind::Symbol
+                }
+                _ if c.is_ascii_digit() => {
+                    while col < chars.len() && chars[col].is_ascii_digit() {
+                        col += 1;
+                    }
+                    let text: String = chars[start..col].iter().collect();
+                    let value = text.parse().map_err(|_| {
+                        Diagnostic::error(row + 1, start..col, format!("{text} is too large"))
+                    })?;
+                    TokenKind::Number(value)
+                }
+                _ if c.is_alphabetic() || c == '_' => {
+                    while col < chars.len() && (chars[col].is_alphanumeric() || chars[col] == '_') {
+                        col += 1;
+                    }
+                    TokenKind::Word
+                }
+                _ => {
+                    return Err(Diagnostic::error(
+                        row + 1,
+                        start..start + 1,
+                        format!("unexpected character `{c}`"),
+                    ))
+                }
+            };
+
+            tokens.push(Token {
+                kind,
+                text: chars[start..col].iter().collect(),
+                line: row + 1,
+                span: start..col,
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.peek().is_some_and(|token| token.text == text)
+    }
+
+    /// An error at the next token, or past the last one.
+    fn error_here(&self, message: impl Into<String>) -> Diagnostic {
+        match self.peek().or(self.tokens.last()) {
+            Some(token) => token.error(message),
+            None => Diagnostic::error(0, 0..0, message),
+        }
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token, Diagnostic> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => Err(self.error_here(format!("expected {expected}, found the end of the file"))),
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<Token, Diagnostic> {
+        let token = self.next(&format!("`{symbol}`"))?;
+        if token.text != symbol {
+            return Err(token.error(format!("expected `{symbol}`, found `{}`", token.text)));
+        }
+        Ok(token)
+    }
+
+    fn name(&mut self, what: &str) -> Result<Token, Diagnostic> {
+        let token = self.next(what)?;
+        if token.kind != TokenKind::Word {
+            return Err(token.error(format!("expected {what}, found `{}`", token.text)));
+        }
+        Ok(token)
+    }
+
+    fn number(&mut self) -> Result<u16, Diagnostic> {
+        let token = self.next("a number")?;
+        match token.kind {
+            TokenKind::Number(value) => Ok(value),
+            _ => Err(token.error(format!("expected a number, found `{}`", token.text))),
+        }
+    }
+
+    fn chip(&mut self) -> Result<ChipDef, Diagnostic> {
+        self.expect("CHIP")?;
+        let name = self.name("the chip's name")?.text;
+        self.expect("{")?;
+
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        if self.peek_is("IN") {
+            self.pos += 1;
+            inputs = self.pins()?;
+        }
+        if self.peek_is("OUT") {
+            self.pos += 1;
+            outputs = self.pins()?;
+        }
+
+        let body = match self.peek().map(|token| token.text.as_str()) {
+            Some("PARTS") => {
+                self.pos += 1;
+                self.expect(":")?;
+                let mut parts = Vec::new();
+                while !self.peek_is("}") && self.peek().is_some() {
+                    parts.push(self.part()?);
+                }
+                Body::Parts(parts)
+            }
+            Some("BUILTIN") => {
+                self.pos += 1;
+                let name = self.name("the built-in chip's name")?.text;
+                self.expect(";")?;
+                let mut clocked = Vec::new();
+                if self.peek_is("CLOCKED") {
+                    self.pos += 1;
+                    loop {
+                        clocked.push(self.name("a pin name")?.text);
+                        if self.expect_either(",", ";")? == ";" {
+                            break;
+                        }
+                    }
+                }
+                Body::Builtin { name, clocked }
+            }
+            _ => return Err(self.error_here("expected `PARTS:` or `BUILTIN`")),
+        };
+
+        self.expect("}")?;
+        if let Some(token) = self.peek() {
+            return Err(token.error("unexpected text after the chip"));
+        }
+
+        let chip = ChipDef {
+            name,
+            inputs,
+            outputs,
+            body,
+        };
+        Ok(chip)
+    }
+
+    fn expect_either(&mut self, first: &str, second: &str) -> Result<String, Diagnostic> {
+        let token = self.next(&format!("`{first}` or `{second}`"))?;
+        if token.text != first && token.text != second {
+            return Err(token.error(format!(
+                "expected `{first}` or `{second}`, found `{}`",
+                token.text
+            )));
+        }
+        Ok(token.text)
+    }
+
+    /// `a, b[16], c;`
+    fn pins(&mut self) -> Result<Vec<Pin>, Diagnostic> {
+        let mut pins: Vec<Pin> = Vec::new();
+        loop {
+            let name = self.name("a pin name")?;
+            let mut width = 1;
+            if self.peek_is("[") {
+                self.pos += 1;
+                let token = self.peek().cloned();
+                width = self.number()?;
+                if !(1..=MAX_WIDTH).contains(&width) {
+                    let token = token.unwrap();
+                    return Err(
+                        token.error(format!("pins are 1 to {MAX_WIDTH} bits wide, not {width}"))
+                    );
+                }
+                self.expect("]")?;
+            }
+            if pins.iter().any(|pin| pin.name == name.text) {
+                return Err(name.error(format!("pin {} is declared twice", name.text)));
+            }
+            pins.push(Pin {
+                name: name.text,
+                width,
+            });
+            if self.expect_either(",", ";")? == ";" {
+                return Ok(pins);
+            }
+        }
+    }
+
+    /// `Chip(pin=signal, ...);`
+    fn part(&mut self) -> Result<Part, Diagnostic> {
+        let chip = self.name("a part")?;
+        self.expect("(")?;
+        let mut connections = Vec::new();
+        if !self.peek_is(")") {
+            loop {
+                connections.push(self.connection()?);
+                if self.expect_either(",", ")")? == ")" {
+                    break;
+                }
+            }
+        } else {
+            self.pos += 1;
+        }
+        let end = self.expect(";")?;
+        let span = match end.line == chip.line {
+            true => chip.span.start..end.span.end,
+            false => chip.span.clone(),
+        };
+
+        Ok(Part {
+            chip: chip.text,
+            connections,
+            line: chip.line,
+            span,
+        })
+    }
+
+    fn connection(&mut self) -> Result<Connection, Diagnostic> {
+        let start = self.peek().cloned();
+        let pin = self.bus("a pin name")?;
+        self.expect("=")?;
+        let signal = match self.peek().map(|token| token.text.as_str()) {
+            Some("true") | Some("false") => {
+                let token = self.next("a signal")?;
+                Signal::Const(token.text == "true")
+            }
+            _ => Signal::Bus(self.bus("a signal")?),
+        };
+
+        let start = start.unwrap();
+        let end = &self.tokens[self.pos - 1];
+        Ok(Connection {
+            pin,
+            signal,
+            line: start.line,
+            span: match end.line == start.line {
+                true => start.span.start..end.span.end,
+                false => start.span,
+            },
+        })
+    }
+
+    /// `name`, `name[i]` or `name[i..j]`.
+    fn bus(&mut self, what: &str) -> Result<Bus, Diagnostic> {
+        let name = self.name(what)?;
+        if !self.peek_is("[") {
+            return Ok(Bus {
+                name: name.text,
+                range: None,
+            });
+        }
+        self.pos += 1;
+        let first = self.number()?;
+        let mut last = first;
+        if self.peek_is("..") {
+            self.pos += 1;
+            last = self.number()?;
+        }
+        let close = self.expect("]")?;
+        if first > last || last >= MAX_WIDTH {
+            return Err(Diagnostic::error(
+                name.line,
+                name.span.start..close.span.end,
+                format!(
+                    "{}[{first}..{last}] is not a valid range of bits",
+                    name.text
+                ),
+            ));
+        }
+        Ok(Bus {
+            name: name.text,
+            range: Some((first, last)),
+        })
+    }
+}
+
+/// Parses the definition of a single chip.
+pub fn parse_chip(source: &str) -> Result<ChipDef, Diagnostic> {
+    let tokens = tokenize(source)?;
+    Parser { tokens, pos: 0 }.chip()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_parts() {
+        let chip = parse_chip(
+            "/** A chip. */\nCHIP Foo {\n  IN a, b[16]; // inputs\n  OUT out[8];\n  PARTS:\n  \
+             Not16(in=b, out[0..7]=out, out[15]=msb);\n  And(a=a, b=true, out=x);\n}\n",
+        )
+        .unwrap();
+
+        assert_eq!(chip.name, "Foo");
+        assert_eq!(chip.input("b").map(|pin| pin.width), Some(16));
+        assert_eq!(chip.output("out").map(|pin| pin.width), Some(8));
+        let Body::Parts(parts) = &chip.body else {
+            panic!("{:?}", chip.body);
+        };
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].line, 6);
+        assert_eq!(
+            parts[0].connections[1].pin,
+            Bus {
+                name: "out".into(),
+                range: Some((0, 7))
+            }
+        );
+        assert_eq!(parts[0].connections[2].pin.to_string(), "out[15]");
+        assert_eq!(parts[1].connections[1].signal, Signal::Const(true));
+    }
+
+    #[test]
+    fn parses_builtins() {
+        let chip = parse_chip(include_str!("../../../tools/builtInChips/PC.hdl")).unwrap();
+        assert_eq!(
+            chip.body,
+            Body::Builtin {
+                name: "PC".into(),
+                clocked: vec!["in".into(), "load".into(), "inc".into(), "reset".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn reports_where_parsing_failed() {
+        let err = parse_chip("CHIP Foo {\n  IN a;\n  OUT out;\n  PARTS:\n  Not(in=a out=out);\n}")
+            .unwrap_err();
+        assert_eq!((err.line, err.span.clone()), (5, 11..14));
+        assert_eq!(err.message, "expected `,` or `)`, found `out`");
+
+        let err = parse_chip("CHIP Foo {\n  IN a[17];\n").unwrap_err();
+        assert_eq!(err.message, "pins are 1 to 16 bits wide, not 17");
+
+        let err =
+            parse_chip("CHIP Foo { IN a; OUT b; PARTS: Not(in=a[3..1], out=b); }").unwrap_err();
+        assert_eq!(err.message, "a[3..1] is not a valid range of bits");
+    }
+}
@@ -0,0 +1,282 @@
+//! Runs a flattened chip: combinational logic settles on `eval`, and clocked
+//! chips store their inputs when the clock rises (`tick`) and show them when
+//! it falls (`tock`).
+
+use crate::library::{Chip, Library};
+use crate::netlist::{Net, Netlist, TRUE};
+use assembler::Diagnostic;
+use std::path::Path;
+use std::rc::Rc;
+
+pub struct Simulator {
+    netlist: Netlist,
+    values: Vec<bool>,
+    /// The registers or memory of each primitive.
+    memories: Vec<Vec<u16>>,
+    /// What each clocked primitive stores when the clock falls.
+    pending: Vec<Option<(usize, u16)>>,
+    /// Clock cycles completed.
+    time: u64,
+    /// Whether the clock rose and didn't fall yet.
+    ticked: bool,
+}
+
+fn word(values: &[bool], nets: &[Net]) -> u16 {
+    nets.iter()
+        .enumerate()
+        .fold(0, |word, (bit, &net)| word | (values[net] as u16) << bit)
+}
+
+impl Simulator {
+    /// Loads a chip file. Its parts are looked up in its directory, then
+    /// among the built-in chips.
+    pub fn load(path: &Path) -> Result<Self, Diagnostic> {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut library = Library::new([dir.to_path_buf()]);
+        let chip = library.load_file(path)?;
+        Self::new(&chip, &mut library)
+    }
+
+    pub fn new(chip: &Rc<Chip>, library: &mut Library) -> Result<Self, Diagnostic> {
+        let netlist = Netlist::build(chip, library)?;
+        let mut values = vec![false; netlist.nets];
+        values[TRUE] = true;
+        let memories = netlist
+            .primitives
+            .iter()
+            .map(|primitive| vec![0; primitive.kind.memory_size()])
+            .collect();
+
+        let mut simulator = Self {
+            pending: vec![None; netlist.primitives.len()],
+            netlist,
+            values,
+            memories,
+            time: 0,
+            ticked: false,
+        };
+        simulator.eval();
+        Ok(simulator)
+    }
+
+    pub fn netlist(&self) -> &Netlist {
+        &self.netlist
+    }
+
+    /// The value of a pin or internal pin of the chip.
+    pub fn get(&self, name: &str) -> Option<u16> {
+        let nets = self.netlist.signals.get(name)?;
+        Some(word(&self.values, nets))
+    }
+
+    /// Sets an input pin, to bits its width allows. The outputs change on the
+    /// next `eval`, `tick` or `tock`.
+    pub fn set(&mut self, name: &str, value: u16) -> Result<(), String> {
+        if self.netlist.inputs.iter().all(|pin| pin.name != name) {
+            return Err(match self.netlist.signals.contains_key(name) {
+                true => format!("{name} is not an input pin"),
+                false => format!("the chip has no pin {name}"),
+            });
+        }
+        for (bit, &net) in self.netlist.signals[name].iter().enumerate() {
+            self.values[net] = value >> bit & 1 == 1;
+        }
+        Ok(())
+    }
+
+    /// Propagates the inputs through the combinational logic.
+    pub fn eval(&mut self) {
+        let (mut inputs, mut outputs) = (Vec::new(), Vec::new());
+        for (primitive, memory) in self.netlist.primitives.iter().zip(&self.memories) {
+            inputs.clear();
+            inputs.extend(primitive.inputs.iter().map(|nets| word(&self.values, nets)));
+            outputs.clear();
+            outputs.resize(primitive.outputs.len(), 0);
+            primitive.kind.eval(&inputs, memory, &mut outputs);
+
+            for (nets, value) in primitive.outputs.iter().zip(&outputs) {
+                for (bit, &net) in nets.iter().enumerate() {
+                    self.values[net] = value >> bit & 1 == 1;
+                }
+            }
+        }
+    }
+
+    /// The clock rises: clocked chips read their inputs.
+    pub fn tick(&mut self) {
+        self.eval();
+        for (index, primitive) in self.netlist.primitives.iter().enumerate() {
+            let inputs: Vec<u16> = primitive
+                .inputs
+                .iter()
+                .map(|nets| word(&self.values, nets))
+                .collect();
+            self.pending[index] = primitive.kind.clock(&inputs, &self.memories[index]);
+        }
+        self.ticked = true;
+    }
+
+    /// The clock falls: clocked chips store what they read, and the
+    /// combinational logic settles again.
+    pub fn tock(&mut self) {
+        if !self.ticked {
+            self.tick();
+        }
+        for (memory, pending) in self.memories.iter_mut().zip(&mut self.pending) {
+            if let Some((addr, value)) = pending.take() {
+                memory[addr] = value;
+            }
+        }
+        self.time += 1;
+        self.ticked = false;
+        self.eval();
+    }
+
+    /// The clock as the course's simulator shows it: the cycles completed,
+    /// followed by `+` between a tick and a tock.
+    pub fn time(&self) -> String {
+        match self.ticked {
+            true => format!("{}+", self.time),
+            false => self.time.to_string(),
+        }
+    }
+
+    fn primitive(&self, chip: &str) -> Option<usize> {
+        self.netlist
+            .primitives
+            .iter()
+            .position(|primitive| primitive.chip == chip && primitive.kind.memory_size() > 0)
+    }
+
+    /// The registers or memory of the first part using the built-in chip of
+    /// that name, such as `RAM16K` or `DRegister`.
+    pub fn memory(&self, chip: &str) -> Option<&[u16]> {
+        Some(&self.memories[self.primitive(chip)?])
+    }
+
+    pub fn memory_mut(&mut self, chip: &str) -> Option<&mut [u16]> {
+        let index = self.primitive(chip)?;
+        Some(&mut self.memories[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Writes chips to a directory of their own and loads the first one.
+    fn load(test: &str, chips: &[(&str, &str)]) -> Result<Simulator, Diagnostic> {
+        let dir = std::env::temp_dir().join(format!("hardware_simulator_{test}"));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, source) in chips {
+            fs::write(dir.join(format!("{name}.hdl")), source).unwrap();
+        }
+        Simulator::load(&dir.join(format!("{}.hdl", chips[0].0)))
+    }
+
+    fn course(path: &str) -> PathBuf {
+        Path::new("..").join(path)
+    }
+
+    #[test]
+    fn simulates_course_chips() {
+        let mut xor = Simulator::load(&course("1/Xor.hdl")).unwrap();
+        for (a, b) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            xor.set("a", a).unwrap();
+            xor.set("b", b).unwrap();
+            xor.eval();
+            assert_eq!(xor.get("out"), Some(a ^ b));
+        }
+
+        let mut alu = Simulator::load(&course("2/ALU.hdl")).unwrap();
+        // x - y
+        for (pin, value) in [("x", 7), ("y", 9), ("nx", 1), ("f", 1), ("no", 1)] {
+            alu.set(pin, value).unwrap();
+        }
+        alu.eval();
+        assert_eq!(alu.get("out"), Some((-2i16) as u16));
+        assert_eq!((alu.get("zr"), alu.get("ng")), (Some(0), Some(1)));
+    }
+
+    #[test]
+    fn clocks_course_chips() {
+        // the course's RAM8, made of Registers made of Bits made of DFFs
+        let mut ram = Simulator::load(&course("3/a/RAM8.hdl")).unwrap();
+        ram.set("in", 11111).unwrap();
+        ram.set("load", 1).unwrap();
+        ram.set("address", 3).unwrap();
+        ram.tick();
+        assert_eq!((ram.time().as_str(), ram.get("out")), ("0+", Some(0)));
+        ram.tock();
+        assert_eq!((ram.time().as_str(), ram.get("out")), ("1", Some(11111)));
+
+        ram.set("load", 0).unwrap();
+        ram.set("address", 2).unwrap();
+        ram.eval();
+        assert_eq!(ram.get("out"), Some(0));
+    }
+
+    #[test]
+    fn accesses_built_in_memory() {
+        let mut sim = load(
+            "memory",
+            &[(
+                "Counter",
+                "CHIP Counter {\n  IN inc;\n  OUT out[16];\n  PARTS:\n  \
+                 PC(in=false, load=false, inc=inc, reset=false, out=out, out=copy);\n  \
+                 DRegister(in=copy, load=true);\n}\n",
+            )],
+        )
+        .unwrap();
+        sim.set("inc", 1).unwrap();
+        for _ in 0..3 {
+            sim.tick();
+            sim.tock();
+        }
+        assert_eq!(sim.get("out"), Some(3));
+        assert_eq!(sim.memory("DRegister"), Some(&[2][..]));
+
+        sim.memory_mut("PC").unwrap()[0] = 40;
+        sim.eval();
+        assert_eq!(sim.get("copy"), Some(40));
+    }
+
+    #[test]
+    fn reports_bad_connections() {
+        let err = |test: &str, parts: &str| {
+            let chip = format!("CHIP Foo {{\n  IN a, b[4];\n  OUT out;\n  PARTS:\n{parts}}}\n");
+            let err = load(test, &[("Foo", &chip)]).err().unwrap();
+            (err.line, err.message)
+        };
+
+        assert_eq!(
+            err("width", "  Not(in=b, out=out);\n"),
+            (5, "in is 1 bits wide, but b is 4 bits wide".into())
+        );
+        assert_eq!(
+            err("undriven", "  And(a=a, b=x, out=out);\n"),
+            (
+                5,
+                "internal pin x is not connected to any part's output".into()
+            )
+        );
+        assert_eq!(
+            err("twice", "  Not(in=a, out=out);\n  Not(in=a, out=out);\n"),
+            (6, "out is driven by more than one output".into())
+        );
+        assert_eq!(
+            err("pin", "  Not(a=a, out=out);\n"),
+            (5, "chip Not has no pin a".into())
+        );
+        assert_eq!(
+            err("unknown", "  Nope(a=a);\n"),
+            (5, "chip Nope is not defined".into())
+        );
+        assert_eq!(
+            err("loop", "  Not(in=x, out=y);\n  Not(in=y, out=x);\n"),
+            (0, "the chip has a combinational loop through Not".into())
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use crate::script::{Step, Var};
+use crate::target::{index, unknown_variable, Target};
+use cpu_emulator::{Machine, KBD, ROM_SIZE};
+use std::fs;
+use std::path::Path;
+
+/// Runs CPU emulator scripts on [`Machine`].
+#[derive(Default)]
+pub struct CpuTarget {
+    pub machine: Machine,
+}
+
+/// Reads a `.hack` file, or assembles a `.asm` file. Scripts often load the
+/// `.hack` file produced by the assembler, so a missing `.hack` file falls
+/// back to the `.asm` file next to it.
+pub fn load_program(path: &Path) -> Result<Vec<u16>, String> {
+    let asm = path.with_extension("asm");
+    let is_asm =
+        path.extension().is_some_and(|ext| ext == "asm") || (!path.exists() && asm.exists());
+    let path = if is_asm { asm.as_path() } else { path };
+
+    let source = fs::read_to_string(path)
+        .map_err(|err| format!("Could not read {}: {err}", path.display()))?;
+    let result = if is_asm {
+        assembler::assemble_source(&source).map(|program| program.instructions)
+    } else {
+        assembler::disassembler::read_hack_text(&source)
+    };
+
+    result.map_err(|diagnostics| {
+        diagnostics
+            .into_iter()
+            .map(|diag| diag.with_file(path.display().to_string()).to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
+}
+
+impl Target for CpuTarget {
+    fn load(&mut self, path: &Path) -> Result<(), String> {
+        let program = load_program(path)?;
+        if program.len() > ROM_SIZE {
+            return Err(format!("{} doesn't fit in ROM", path.display()));
+        }
+        self.machine.load(&program);
+        Ok(())
+    }
+
+    fn get(&self, var: &Var) -> Result<i16, String> {
+        let value = match var.name.as_str() {
+            "A" => self.machine.a,
+            "D" => self.machine.d,
+            "PC" => self.machine.pc,
+            "time" => self.machine.cycles() as u16,
+            "RAM" => self.machine.read(index(var, KBD as usize + 1)? as u16),
+            "ROM" => self.machine.rom()[index(var, ROM_SIZE)?],
+            _ => return Err(unknown_variable(var)),
+        };
+        Ok(value as i16)
+    }
+
+    fn set(&mut self, var: &Var, value: i16) -> Result<(), String> {
+        let value = value as u16;
+        match var.name.as_str() {
+            "A" => self.machine.a = value,
+            "D" => self.machine.d = value,
+            "PC" => self.machine.pc = value % ROM_SIZE as u16,
+            "RAM" => match index(var, KBD as usize + 1)? as u16 {
+                KBD => self.machine.keyboard = value,
+                addr => self.machine.write(addr, value),
+            },
+            "ROM" => self.machine.rom_mut()[index(var, ROM_SIZE)?] = value,
+            _ => return Err(unknown_variable(var)),
+        }
+        Ok(())
+    }
+
+    fn step(&mut self, step: Step) -> Result<(), String> {
+        match step {
+            Step::TickTock => self.machine.step().map_err(|fault| fault.to_string()),
+            _ => Err(format!("the CPU emulator doesn't support {}", step.name())),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::script::{Step, Var};
+use crate::target::{index, unknown_variable, Target};
+use hardware_simulator::Simulator;
+use std::path::Path;
+
+/// Runs hardware simulator scripts on a chip loaded from its `.hdl` file.
+#[derive(Default)]
+pub struct HdlTarget {
+    pub chip: Option<Simulator>,
+}
+
+impl HdlTarget {
+    fn chip(&self) -> Result<&Simulator, String> {
+        self.chip
+            .as_ref()
+            .ok_or_else(|| "no chip was loaded".into())
+    }
+
+    fn chip_mut(&mut self) -> Result<&mut Simulator, String> {
+        self.chip
+            .as_mut()
+            .ok_or_else(|| "no chip was loaded".into())
+    }
+}
+
+/// The built-in chip and the register a variable such as `RAM16K[3]` or
+/// `PC[]` names, if it names one.
+fn register(var: &Var) -> Option<&str> {
+    match var.index {
+        Some(_) => Some(&var.name),
+        None => var.name.strip_suffix("[]"),
+    }
+}
+
+/// The entry of the chip's memory a register variable names.
+fn entry(var: &Var, memory: &[u16]) -> Result<usize, String> {
+    match var.index {
+        Some(_) => index(var, memory.len()),
+        None => Ok(0),
+    }
+}
+
+impl Target for HdlTarget {
+    fn load(&mut self, path: &Path) -> Result<(), String> {
+        self.chip = Some(Simulator::load(path).map_err(|err| err.to_string())?);
+        Ok(())
+    }
+
+    fn get(&self, var: &Var) -> Result<i16, String> {
+        let chip = self.chip()?;
+        if let Some(value) = chip.get(&var.name).filter(|_| var.index.is_none()) {
+            return Ok(value as i16);
+        }
+        let memory = register(var)
+            .and_then(|name| chip.memory(name))
+            .ok_or_else(|| unknown_variable(var))?;
+        Ok(memory[entry(var, memory)?] as i16)
+    }
+
+    fn text(&self, var: &Var) -> Option<String> {
+        let chip = self.chip.as_ref()?;
+        (var.name == "time" && chip.get("time").is_none()).then(|| chip.time())
+    }
+
+    fn set(&mut self, var: &Var, value: i16) -> Result<(), String> {
+        let chip = self.chip_mut()?;
+        if var.index.is_none() && chip.get(&var.name).is_some() {
+            return chip.set(&var.name, value as u16);
+        }
+        let memory = register(var)
+            .and_then(|name| chip.memory_mut(name))
+            .ok_or_else(|| unknown_variable(var))?;
+        memory[entry(var, memory)?] = value as u16;
+        Ok(())
+    }
+
+    fn step(&mut self, step: Step) -> Result<(), String> {
+        let chip = self.chip_mut()?;
+        match step {
+            Step::Tick => chip.tick(),
+            Step::Tock => chip.tock(),
+            Step::TickTock => {
+                chip.tick();
+                chip.tock();
+            }
+            Step::Eval => chip.eval(),
+            Step::VmStep => return Err("the hardware simulator doesn't support vmstep".into()),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+pub mod cpu;
+pub mod hdl;
+pub mod output;
+pub mod runner;
+pub mod script;
+pub mod target;
+pub mod vm;
+
+pub use cpu::CpuTarget;
+pub use hdl::HdlTarget;
+pub use output::Mismatch;
+pub use runner::{Report, Runner};
+pub use script::parse_script;
+pub use target::Target;
+pub use vm::VmTarget;
+
+use assembler::Diagnostic;
+use script::{Command, Statement, Step};
+use std::fs;
+use std::path::Path;
+
+/// Whether a script is meant for the VM emulator: it loads `.vm` files or
+/// steps through VM commands.
+fn is_vm_script(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match &statement.command {
+        Command::Load(None) | Command::Step(Step::VmStep) => true,
+        Command::Load(Some(file)) => file.ends_with(".vm"),
+        Command::Repeat(_, body) | Command::While(_, _, _, body) => is_vm_script(body),
+        _ => false,
+    })
+}
+
+/// Whether a script is meant for the hardware simulator: it loads a chip.
+fn is_hdl_script(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match &statement.command {
+        Command::Load(Some(file)) => file.ends_with(".hdl"),
+        _ => false,
+    })
+}
+
+fn run_on<T: Target>(
+    target: T,
+    dir: &Path,
+    statements: &[Statement],
+) -> Result<Report, Diagnostic> {
+    let mut runner = Runner::new(target, dir);
+    runner.run(statements).and_then(|()| runner.finish())
+}
+
+/// Runs a CPU emulator, VM emulator or hardware simulator test script, writing its output file
+/// and comparing it with its compare file.
+pub fn run_script(path: &Path) -> Result<Report, Vec<Diagnostic>> {
+    let file = path.display().to_string();
+    let with_file = |diag: Diagnostic| diag.with_file(file.clone());
+
+    let script = fs::read_to_string(path).map_err(|err| {
+        vec![Diagnostic::error(
+            0,
+            0..0,
+            format!("Could not read {file}: {err}"),
+        )]
+    })?;
+    let statements = parse_script(&script)
+        .map_err(|errors| errors.into_iter().map(with_file).collect::<Vec<_>>())?;
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let result = if is_hdl_script(&statements) {
+        run_on(HdlTarget::default(), dir, &statements)
+    } else if is_vm_script(&statements) {
+        run_on(VmTarget::default(), dir, &statements)
+    } else {
+        run_on(CpuTarget::default(), dir, &statements)
+    };
+    result.map_err(|err| vec![with_file(err)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn runs_mult_test() {
+        let dir = env::temp_dir().join("test_runner_mult");
+        fs::create_dir_all(&dir).unwrap();
+        for file in ["Mult.tst", "Mult.cmp", "Mult.asm"] {
+            fs::copy(Path::new("../4/mult").join(file), dir.join(file)).unwrap();
+        }
+
+        let report = run_script(&dir.join("Mult.tst")).unwrap();
+        assert!(report.passed(), "{:?}", report.mismatch);
+        assert_eq!(report.output, include_str!("../../4/mult/Mult.out"));
+        assert_eq!(
+            fs::read_to_string(dir.join("Mult.out")).unwrap(),
+            report.output
+        );
+    }
+
+    #[test]
+    fn runs_vm_scripts() {
+        let dir = env::temp_dir().join("test_runner_statics");
+        fs::create_dir_all(&dir).unwrap();
+        let course = Path::new("../8/FunctionCalls/StaticsTest");
+        let files = [
+            "Class1.vm",
+            "Class2.vm",
+            "Sys.vm",
+            "StaticsTestVME.tst",
+            "StaticsTest.cmp",
+        ];
+        for file in files {
+            fs::copy(course.join(file), dir.join(file)).unwrap();
+        }
+
+        // the script loads every .vm file of its directory
+        let report = run_script(&dir.join("StaticsTestVME.tst")).unwrap();
+        assert!(report.passed(), "{:?}", report.mismatch);
+        assert_eq!(
+            report.output.lines().nth(1),
+            Some("|    263 |     -2 |      8 |")
+        );
+    }
+
+    #[test]
+    fn runs_hdl_scripts() {
+        let dir = env::temp_dir().join("test_runner_hdl");
+        fs::create_dir_all(&dir).unwrap();
+        let course = Path::new("../3/a");
+        let files = ["PC.hdl", "Register.hdl", "Bit.hdl", "PC.tst", "PC.cmp"];
+        for file in files {
+            fs::copy(course.join(file), dir.join(file)).unwrap();
+        }
+
+        let report = run_script(&dir.join("PC.tst")).unwrap();
+        assert!(report.passed(), "{:?}", report.mismatch);
+        assert_eq!(report.output, include_str!("../../3/a/PC.out"));
+    }
+
+    #[test]
+    fn reports_first_mismatch() {
+        let dir = env::temp_dir().join("test_runner_mismatch");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Add.asm"), "@R0\nD=M\n@R1\nD=D+M\n@R2\nM=D\n").unwrap();
+        fs::write(dir.join("Add.cmp"), "| RAM[2] |\n|      5 |\n|      6 |\n").unwrap();
+        fs::write(
+            dir.join("Add.tst"),
+            "load Add.asm, output-file Add.out, compare-to Add.cmp,\n\
+             output-list RAM[2]%D1.6.1;\n\
+             set RAM[0] 2, set RAM[1] 3; repeat 6 { ticktock; } output;\n\
+             set PC 0, set RAM[1] 5; repeat 6 { ticktock; } output;\n",
+        )
+        .unwrap();
+
+        let report = run_script(&dir.join("Add.tst")).unwrap();
+        assert_eq!(
+            report.mismatch,
+            Some(Mismatch {
+                line: 3,
+                expected: Some("|      6 |".into()),
+                actual: Some("|      7 |".into()),
+            })
+        );
+    }
+
+    #[test]
+    fn runtime_errors_point_at_the_command() {
+        let dir = env::temp_dir().join("test_runner_error");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Bad.tst"), "set RAM[0] 1;\nset X 2;\n").unwrap();
+
+        let errors = run_script(&dir.join("Bad.tst")).unwrap_err();
+        assert_eq!(errors[0].line, 2);
+        assert_eq!(errors[0].message, "unknown variable X");
+
+        // inside a block, the error points at the failing statement
+        fs::write(
+            dir.join("Block.tst"),
+            "repeat 2 {\n  set RAM[0] 1;\n  set Y 2;\n}\n",
+        )
+        .unwrap();
+        let errors = run_script(&dir.join("Block.tst")).unwrap_err();
+        assert_eq!((errors[0].line, errors[0].span.clone()), (3, 2..9));
+        assert_eq!(errors[0].message, "unknown variable Y");
+    }
+
+    #[test]
+    fn runs_endless_scripts() {
+        let dir = env::temp_dir().join("test_runner_fill");
+        fs::create_dir_all(&dir).unwrap();
+        for file in ["Fill.tst", "Fill.asm"] {
+            fs::copy(Path::new("../4/fill").join(file), dir.join(file)).unwrap();
+        }
+
+        // `repeat` without a count stops after a while in a headless run
+        let report = run_script(&dir.join("Fill.tst")).unwrap();
+        assert!(report.passed());
+        assert_eq!(report.echo.len(), 1);
+    }
+}
@@ -0,0 +1,89 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+use test_runner::{run_script, Report};
+
+const USAGE: &str = "\
+Usage: test_runner <SCRIPT.tst>...
+
+Runs CPU emulator, VM emulator and hardware simulator test scripts, writing
+each script's output file and comparing it with its compare file.
+
+Exit codes:
+  0  every script passed
+  1  an output differs from its compare file
+  2  invalid command line
+  3  a script is invalid or failed to run";
+
+/// Why a run failed, ordered by precedence when several scripts fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Failure {
+    Comparison = 1,
+    Usage = 2,
+    Script = 3,
+}
+
+fn print_report(script: &str, report: &Report) -> Result<(), Failure> {
+    for message in &report.echo {
+        println!("{script}: {message}");
+    }
+
+    let Some(mismatch) = &report.mismatch else {
+        println!("PASS {script}");
+        return Ok(());
+    };
+
+    println!(
+        "FAIL {script}: comparison failure at line {}",
+        mismatch.line
+    );
+    println!(
+        "  expected: {}",
+        mismatch.expected.as_deref().unwrap_or("<end of file>")
+    );
+    println!(
+        "  actual:   {}",
+        mismatch.actual.as_deref().unwrap_or("<end of file>")
+    );
+    Err(Failure::Comparison)
+}
+
+fn main() -> ExitCode {
+    let scripts: Vec<String> = env::args().skip(1).collect();
+    if scripts.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    if let Some(option) = scripts.iter().find(|arg| arg.starts_with('-')) {
+        eprintln!("Unknown option {option}\n\n{USAGE}");
+        return ExitCode::from(Failure::Usage as u8);
+    }
+    if scripts.is_empty() {
+        eprintln!("No test script was provided.\n\n{USAGE}");
+        return ExitCode::from(Failure::Usage as u8);
+    }
+
+    let mut failure = None;
+    for script in &scripts {
+        let result = match run_script(Path::new(script)) {
+            Ok(report) => print_report(script, &report),
+            Err(diagnostics) => {
+                let source = fs::read_to_string(script).unwrap_or_default();
+                for diag in diagnostics {
+                    eprintln!("{}", diag.render(&source));
+                }
+                println!("ERROR {script}");
+                Err(Failure::Script)
+            }
+        };
+        if let Err(err) = result {
+            failure = failure.max(Some(err));
+        }
+    }
+
+    match failure {
+        Some(failure) => ExitCode::from(failure as u8),
+        None => ExitCode::SUCCESS,
+    }
+}
@@ -0,0 +1,200 @@
+use crate::script::{Column, Radix};
+
+/// The header line of an output table: every variable name centered in its
+/// column, cut off if the column is too narrow.
+pub fn header(columns: &[Column]) -> String {
+    let mut line = String::from("|");
+    for column in columns {
+        let size = column.left + column.width + column.right;
+        let name: String = column.var.to_string().chars().take(size).collect();
+        let left = (size - name.chars().count()) / 2;
+        line += &format!("{:left$}{name:<rest$}|", "", rest = size - left);
+    }
+    line
+}
+
+/// Formats a value for `column`. Binary and hex values show their lowest
+/// digits, zero-padded; decimal values are right-aligned.
+pub fn cell(column: &Column, value: i16) -> String {
+    let width = column.width;
+    let text = match column.radix {
+        Radix::Binary => low_digits(format!("{:016b}", value as u16), width),
+        Radix::Hex => low_digits(format!("{:04X}", value as u16), width),
+        Radix::Decimal => format!("{value:>width$}"),
+        Radix::String => format!("{value:<width$}"),
+    };
+    format!(
+        "{:left$}{text}{:right$}",
+        "",
+        "",
+        left = column.left,
+        right = column.right
+    )
+}
+
+/// Formats a value a simulator shows as text, such as the clock's `time`.
+pub fn text_cell(column: &Column, text: &str) -> String {
+    let text: String = text.chars().take(column.width).collect();
+    format!(
+        "{:left$}{text:<width$}{:right$}",
+        "",
+        "",
+        left = column.left,
+        width = column.width,
+        right = column.right
+    )
+}
+
+fn low_digits(digits: String, width: usize) -> String {
+    if digits.len() >= width {
+        digits[digits.len() - width..].to_string()
+    } else {
+        format!("{digits:0>width$}")
+    }
+}
+
+/// The line written by `output`, given the cell of every column.
+pub fn row(cells: &[String]) -> String {
+    let mut line = String::from("|");
+    for cell in cells {
+        line += cell;
+        line.push('|');
+    }
+    line
+}
+
+/// Where an output differs from what it was compared to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// 1-based line number.
+    pub line: usize,
+    /// `None` when the output has more lines than the compare file.
+    pub expected: Option<String>,
+    /// `None` when the output has fewer lines than the compare file.
+    pub actual: Option<String>,
+}
+
+/// Whether an output line matches a compare file line, where `*` matches any
+/// character, as the hardware simulator's compare files use for values that
+/// don't matter.
+fn matches(actual: &str, expected: &str) -> bool {
+    actual.chars().count() == expected.chars().count()
+        && actual
+            .chars()
+            .zip(expected.chars())
+            .all(|(actual, expected)| actual == expected || expected == '*')
+}
+
+/// Compares an output with a `.cmp` file line by line, ignoring line endings
+/// and trailing whitespace.
+pub fn compare(output: &str, expected: &str) -> Option<Mismatch> {
+    let mut actual_lines = output.lines().map(str::trim_end);
+    let mut expected_lines = expected.lines().map(str::trim_end);
+
+    for line in 1.. {
+        let (actual, expected) = (actual_lines.next(), expected_lines.next());
+        if actual.is_none() && expected.is_none() {
+            return None;
+        }
+        let same = match (actual, expected) {
+            (Some(actual), Some(expected)) => matches(actual, expected),
+            _ => false,
+        };
+        if !same {
+            return Some(Mismatch {
+                line,
+                expected: expected.map(str::to_string),
+                actual: actual.map(str::to_string),
+            });
+        }
+    }
+    unreachable!()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Var;
+
+    fn column(name: &str, index: u16, radix: Radix, sizes: [usize; 3]) -> Column {
+        Column {
+            var: Var {
+                name: name.into(),
+                index: Some(index),
+            },
+            radix,
+            left: sizes[0],
+            width: sizes[1],
+            right: sizes[2],
+        }
+    }
+
+    #[test]
+    fn formats_columns() {
+        let columns = [
+            column("RAM", 0, Radix::Decimal, [1, 6, 1]),
+            column("RAM", 3006, Radix::Decimal, [1, 6, 1]),
+            column("RAM", 11, Radix::Decimal, [1, 6, 1]),
+            column("RAM", 5, Radix::Binary, [1, 16, 1]),
+            column("ROM", 1, Radix::Hex, [1, 4, 1]),
+        ];
+        assert_eq!(
+            header(&columns),
+            "| RAM[0] |RAM[3006|RAM[11] |      RAM[5]      |ROM[1]|"
+        );
+        let cells: Vec<_> = columns
+            .iter()
+            .zip([263, -2, 32767, 5, -1])
+            .map(|(column, value)| cell(column, value))
+            .collect();
+        assert_eq!(
+            row(&cells),
+            "|    263 |     -2 |  32767 | 0000000000000101 | FFFF |"
+        );
+    }
+
+    #[test]
+    fn narrow_binary_columns_show_low_bits() {
+        assert_eq!(
+            cell(&column("out", 0, Radix::Binary, [3, 1, 3]), 3),
+            "   1   "
+        );
+    }
+
+    #[test]
+    fn text_is_left_aligned() {
+        let time = Column {
+            var: Var {
+                name: "time".into(),
+                index: None,
+            },
+            radix: Radix::String,
+            left: 1,
+            width: 4,
+            right: 1,
+        };
+        assert_eq!(text_cell(&time, "12+"), " 12+  ");
+    }
+
+    #[test]
+    fn finds_first_mismatch() {
+        assert_eq!(compare("|  1 |\n|  2 |\n", "|  1 |\r\n|  2 |\r\n"), None);
+        assert_eq!(
+            compare("|  1 |\n|  3 |\n", "|  1 |\n|  2 |\n"),
+            Some(Mismatch {
+                line: 2,
+                expected: Some("|  2 |".into()),
+                actual: Some("|  3 |".into()),
+            })
+        );
+        assert_eq!(
+            compare("|  1 |\n", "|  1 |\n|  2 |\n"),
+            Some(Mismatch {
+                line: 2,
+                expected: Some("|  2 |".into()),
+                actual: None,
+            })
+        );
+        assert_eq!(compare("| 12 |  3 |\n", "| 12 |****|\n"), None);
+    }
+}
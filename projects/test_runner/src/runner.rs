@@ -0,0 +1,131 @@
+use crate::output::{self, Mismatch};
+use crate::script::{Column, Command, Statement};
+use crate::target::Target;
+use assembler::Diagnostic;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Iterations a `repeat` without a count runs for. In the course's simulator
+/// it runs until the user stops it, which a headless run can't wait for.
+pub const REPEAT_FOREVER_LIMIT: u64 = 1_000_000;
+
+/// What a finished script produced.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Contents of the output file.
+    pub output: String,
+    pub output_file: Option<PathBuf>,
+    pub compare_to: Option<PathBuf>,
+    /// The first line where the output differs from the compare file, if any.
+    pub mismatch: Option<Mismatch>,
+    /// Messages the script printed with `echo`.
+    pub echo: Vec<String>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.mismatch.is_none()
+    }
+}
+
+/// Executes test scripts on a [`Target`]. File names in the script are
+/// resolved against `dir`, the script's directory.
+pub struct Runner<T> {
+    pub target: T,
+    dir: PathBuf,
+    columns: Vec<Column>,
+    report: Report,
+}
+
+impl<T: Target> Runner<T> {
+    pub fn new(target: T, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            target,
+            dir: dir.into(),
+            columns: Vec::new(),
+            report: Report::default(),
+        }
+    }
+
+    pub fn run(&mut self, statements: &[Statement]) -> Result<(), Diagnostic> {
+        for statement in statements {
+            let error = |message: String| {
+                Diagnostic::error(statement.line, statement.span.clone(), message)
+            };
+            // errors inside a block point at the statement of the block
+            // that failed
+            match &statement.command {
+                Command::Repeat(count, body) => {
+                    for _ in 0..count.unwrap_or(REPEAT_FOREVER_LIMIT) {
+                        self.run(body)?;
+                    }
+                }
+                Command::While(var, comparison, value, body) => {
+                    while comparison.holds(self.target.get(var).map_err(error)?, *value) {
+                        self.run(body)?;
+                    }
+                }
+                command => self.execute(command).map_err(error)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, command: &Command) -> Result<(), String> {
+        match command {
+            Command::Load(Some(file)) => self.target.load(&self.dir.join(file))?,
+            Command::Load(None) => self.target.load(&self.dir)?,
+            Command::OutputFile(file) => self.report.output_file = Some(self.dir.join(file)),
+            Command::CompareTo(file) => self.report.compare_to = Some(self.dir.join(file)),
+            Command::OutputList(columns) => {
+                self.columns = columns.clone();
+                self.write_line(output::header(columns));
+            }
+            Command::Output => {
+                if self.columns.is_empty() {
+                    return Err("`output` before any `output-list`".into());
+                }
+                let cells = self
+                    .columns
+                    .iter()
+                    .map(|column| match self.target.text(&column.var) {
+                        Some(text) => Ok(output::text_cell(column, &text)),
+                        None => Ok(output::cell(column, self.target.get(&column.var)?)),
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                self.write_line(output::row(&cells));
+            }
+            Command::Set(var, value) => self.target.set(var, *value)?,
+            Command::Step(step) => self.target.step(*step)?,
+            Command::Repeat(..) | Command::While(..) => unreachable!("blocks are run by `run`"),
+            Command::Echo(text) => self.report.echo.push(text.clone()),
+            Command::ClearEcho => {}
+        }
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: String) {
+        self.report.output += &line;
+        self.report.output.push('\n');
+    }
+
+    /// Writes the output file and compares it with the compare file.
+    pub fn finish(mut self) -> Result<Report, Diagnostic> {
+        let io_error = |path: &Path, err| {
+            Diagnostic::error(
+                0,
+                0..0,
+                format!("Could not access {}: {err}", path.display()),
+            )
+        };
+
+        if let Some(path) = &self.report.output_file {
+            fs::write(path, &self.report.output).map_err(|err| io_error(path, err))?;
+        }
+        if let Some(path) = &self.report.compare_to {
+            let expected = fs::read_to_string(path).map_err(|err| io_error(path, err))?;
+            self.report.mismatch = output::compare(&self.report.output, &expected);
+        }
+        Ok(self.report)
+    }
+}
@@ -0,0 +1,630 @@
+use assembler::Diagnostic;
+use std::fmt;
+use std::ops::Range;
+
+/// A simulator variable such as `PC`, `RAM[256]` or `local[1]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Var {
+    pub name: String,
+    pub index: Option<u16>,
+}
+
+impl Var {
+    fn parse(text: &str) -> Option<Self> {
+        let Some((name, rest)) = text.split_once('[') else {
+            return Some(Var {
+                name: text.to_string(),
+                index: None,
+            });
+        };
+        let index = rest.strip_suffix(']')?;
+        // `DRegister[]` names the value of a chip with a single register
+        if index.is_empty() {
+            return Some(Var {
+                name: text.to_string(),
+                index: None,
+            });
+        }
+        let index = index.parse().ok()?;
+        Some(Var {
+            name: name.to_string(),
+            index: Some(index),
+        })
+    }
+}
+
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "{}[{index}]", self.name),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+/// How an output column prints its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    Binary,
+    Decimal,
+    Hex,
+    String,
+}
+
+/// One entry of an `output-list`, e.g. `RAM[0]%D2.6.2`: the variable, its
+/// format, and the padding left of, the width of, and the padding right of
+/// the value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub var: Var,
+    pub radix: Radix,
+    pub left: usize,
+    pub width: usize,
+    pub right: usize,
+}
+
+impl Column {
+    fn parse(text: &str) -> Option<Self> {
+        let (var, format) = match text.split_once('%') {
+            Some((var, format)) => (var, Some(format)),
+            None => (text, None),
+        };
+        let var = Var::parse(var)?;
+        let Some(format) = format else {
+            return Some(Column {
+                var,
+                radix: Radix::Binary,
+                left: 1,
+                width: 16,
+                right: 1,
+            });
+        };
+
+        let mut chars = format.chars();
+        let radix = match chars.next()? {
+            'B' => Radix::Binary,
+            'D' => Radix::Decimal,
+            'X' => Radix::Hex,
+            'S' => Radix::String,
+            _ => return None,
+        };
+        let sizes: Vec<usize> = chars
+            .as_str()
+            .split('.')
+            .map(|size| size.parse().ok())
+            .collect::<Option<_>>()?;
+        let [left, width, right] = sizes[..] else {
+            return None;
+        };
+
+        Some(Column {
+            var,
+            radix,
+            left,
+            width,
+            right,
+        })
+    }
+}
+
+/// What a simulator is asked to advance by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Tick,
+    Tock,
+    TickTock,
+    Eval,
+    VmStep,
+}
+
+impl Step {
+    pub fn name(self) -> &'static str {
+        match self {
+            Step::Tick => "tick",
+            Step::Tock => "tock",
+            Step::TickTock => "ticktock",
+            Step::Eval => "eval",
+            Step::VmStep => "vmstep",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+impl Comparison {
+    fn parse(text: &str) -> Option<Self> {
+        Some(match text {
+            "=" => Comparison::Eq,
+            "<>" => Comparison::Ne,
+            "<" => Comparison::Lt,
+            ">" => Comparison::Gt,
+            "<=" => Comparison::Le,
+            ">=" => Comparison::Ge,
+            _ => return None,
+        })
+    }
+
+    pub fn holds(self, left: i16, right: i16) -> bool {
+        match self {
+            Comparison::Eq => left == right,
+            Comparison::Ne => left != right,
+            Comparison::Lt => left < right,
+            Comparison::Gt => left > right,
+            Comparison::Le => left <= right,
+            Comparison::Ge => left >= right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Loads a program; without a file name, every file of the script's
+    /// directory.
+    Load(Option<String>),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<Column>),
+    Output,
+    Set(Var, i16),
+    Step(Step),
+    /// Repeats a block a number of times, or forever without one.
+    Repeat(Option<u64>, Vec<Statement>),
+    While(Var, Comparison, i16, Vec<Statement>),
+    Echo(String),
+    ClearEcho,
+}
+
+/// A command and where it is in the script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    pub line: usize,
+    pub span: Range<usize>,
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    Word,
+    Text,
+    Open,
+    Close,
+    /// `,`, `;` or `!`. A headless run doesn't pause, so they all just end
+    /// the command.
+    End,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    text: String,
+    line: usize,
+    span: Range<usize>,
+}
+
+impl Token {
+    fn error(&self, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::error(self.line, self.span.clone(), message)
+    }
+}
+
+fn tokenize(script: &str) -> Result<Vec<Token>, Diagnostic> {
+    let mut tokens = Vec::new();
+    let mut in_comment = false;
+
+    for (row, line) in script.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut col = 0;
+
+        while col < chars.len() {
+            if in_comment {
+                if chars[col..].starts_with(&['*', '/']) {
+                    in_comment = false;
+                    col += 1;
+                }
+                col += 1;
+                continue;
+            }
+
+            let start = col;
+            let c = chars[col];
+            let kind = match c {
+                _ if c.is_whitespace() => {
+                    col += 1;
+                    continue;
+                }
+                '/' if chars.get(col + 1) == Some(&'/') => break,
+                '/' if chars.get(col + 1) == Some(&'*') => {
+                    in_comment = true;
+                    col += 2;
+                    continue;
+                }
+                '{' => TokenKind::Open,
+                '}' => TokenKind::Close,
+                ',' | ';' | '!' => TokenKind::End,
+                '"' => {
+                    let Some(len) = chars[col + 1..].iter().position(|&c| c == '"') else {
+                        return Err(Diagnostic::error(
+                            row + 1,
+                            col..chars.len(),
+                            "unterminated string",
+                        ));
+                    };
+                    let text = chars[col + 1..col + 1 + len].iter().collect();
+                    col += len + 2;
+                    tokens.push(Token {
+                        kind: TokenKind::Text,
+                        text,
+                        line: row + 1,
+                        span: start..col,
+                    });
+                    continue;
+                }
+                _ => {
+                    while col < chars.len()
+                        && !chars[col].is_whitespace()
+                        && !"{},;!\"".contains(chars[col])
+                        && !chars[col..].starts_with(&['/', '/'])
+                    {
+                        col += 1;
+                    }
+                    tokens.push(Token {
+                        kind: TokenKind::Word,
+                        text: chars[start..col].iter().collect(),
+                        line: row + 1,
+                        span: start..col,
+                    });
+                    continue;
+                }
+            };
+
+            col += 1;
+            tokens.push(Token {
+                kind,
+                text: c.to_string(),
+                line: row + 1,
+                span: start..col,
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parses a value as written in `set` commands: decimal, or `%B`, `%X` and
+/// `%D` prefixed.
+fn parse_value(text: &str) -> Option<i16> {
+    let (digits, radix) = match text.get(..2) {
+        Some("%B") => (&text[2..], 2),
+        Some("%X") => (&text[2..], 16),
+        Some("%D") => (&text[2..], 10),
+        _ => (text, 10),
+    };
+
+    if radix == 10 {
+        let value: i32 = digits.parse().ok()?;
+        return (-32768..=65535).contains(&value).then_some(value as i16);
+    }
+    u16::from_str_radix(digits, radix)
+        .ok()
+        .map(|value| value as i16)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    errors: Vec<Diagnostic>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Parses statements up to the end of the script, or the `}` closing a
+    /// block when `in_block`.
+    fn block(&mut self, in_block: bool) -> Vec<Statement> {
+        let mut statements = Vec::new();
+
+        while let Some(token) = self.next() {
+            match token.kind {
+                TokenKind::End => continue,
+                TokenKind::Close if in_block => return statements,
+                TokenKind::Word => match self.statement(&token) {
+                    Ok(statement) => statements.push(statement),
+                    Err(err) => {
+                        self.errors.push(err);
+                        self.skip_command();
+                    }
+                },
+                _ => self
+                    .errors
+                    .push(token.error(format!("unexpected `{}`", token.text))),
+            }
+        }
+
+        if in_block {
+            let end = self.tokens.last().map_or(0, |token| token.line);
+            self.errors.push(Diagnostic::error(
+                end,
+                0..0,
+                "block is missing its closing `}`",
+            ));
+        }
+        statements
+    }
+
+    fn skip_command(&mut self) {
+        while let Some(token) = self.peek() {
+            if matches!(token.kind, TokenKind::End | TokenKind::Close) {
+                return;
+            }
+            if token.kind == TokenKind::Open {
+                self.pos += 1;
+                self.block(true);
+                return;
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Arguments of a command, up to the token that ends it.
+    fn arguments(&mut self) -> Vec<Token> {
+        let mut args = Vec::new();
+        while let Some(token) = self.peek() {
+            if !matches!(token.kind, TokenKind::Word | TokenKind::Text) {
+                break;
+            }
+            args.push(self.next().unwrap());
+        }
+        args
+    }
+
+    fn open_block(&mut self, command: &Token) -> Result<Vec<Statement>, Diagnostic> {
+        match self.next() {
+            Some(token) if token.kind == TokenKind::Open => Ok(self.block(true)),
+            _ => Err(command.error(format!("`{}` must be followed by a block", command.text))),
+        }
+    }
+
+    fn statement(&mut self, command: &Token) -> Result<Statement, Diagnostic> {
+        let args = self.arguments();
+        let span = match args.last() {
+            Some(last) if last.line == command.line => command.span.start..last.span.end,
+            _ => command.span.clone(),
+        };
+        let arity = |count: usize| {
+            if args.len() == count {
+                Ok(())
+            } else {
+                Err(Diagnostic::error(
+                    command.line,
+                    span.clone(),
+                    format!(
+                        "`{}` expects {count} argument{}, found {}",
+                        command.text,
+                        if count == 1 { "" } else { "s" },
+                        args.len()
+                    ),
+                ))
+            }
+        };
+        let value = |token: &Token| {
+            parse_value(&token.text)
+                .ok_or_else(|| token.error(format!("invalid value `{}`", token.text)))
+        };
+        let var = |token: &Token| {
+            Var::parse(&token.text)
+                .ok_or_else(|| token.error(format!("invalid variable `{}`", token.text)))
+        };
+
+        let command_kind = match command.text.as_str() {
+            "load" if args.is_empty() => Command::Load(None),
+            "load" => {
+                arity(1)?;
+                Command::Load(Some(args[0].text.clone()))
+            }
+            "output-file" => {
+                arity(1)?;
+                Command::OutputFile(args[0].text.clone())
+            }
+            "compare-to" => {
+                arity(1)?;
+                Command::CompareTo(args[0].text.clone())
+            }
+            "output-list" => Command::OutputList(
+                args.iter()
+                    .map(|arg| {
+                        Column::parse(&arg.text).ok_or_else(|| {
+                            arg.error(format!("invalid output column `{}`", arg.text))
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            "output" => {
+                arity(0)?;
+                Command::Output
+            }
+            "set" => {
+                arity(2)?;
+                Command::Set(var(&args[0])?, value(&args[1])?)
+            }
+            "tick" | "tock" | "ticktock" | "eval" | "vmstep" => {
+                arity(0)?;
+                Command::Step(match command.text.as_str() {
+                    "tick" => Step::Tick,
+                    "tock" => Step::Tock,
+                    "ticktock" => Step::TickTock,
+                    "eval" => Step::Eval,
+                    _ => Step::VmStep,
+                })
+            }
+            "repeat" if args.is_empty() => Command::Repeat(None, self.open_block(command)?),
+            "repeat" => {
+                arity(1)?;
+                let count = args[0].text.parse().map_err(|_| {
+                    args[0].error(format!("invalid repeat count `{}`", args[0].text))
+                })?;
+                Command::Repeat(Some(count), self.open_block(command)?)
+            }
+            "while" => {
+                arity(3)?;
+                let comparison = Comparison::parse(&args[1].text).ok_or_else(|| {
+                    args[1].error(format!("invalid comparison `{}`", args[1].text))
+                })?;
+                Command::While(
+                    var(&args[0])?,
+                    comparison,
+                    value(&args[2])?,
+                    self.open_block(command)?,
+                )
+            }
+            "echo" => {
+                arity(1)?;
+                Command::Echo(args[0].text.clone())
+            }
+            "clear-echo" => {
+                arity(0)?;
+                Command::ClearEcho
+            }
+            other => return Err(command.error(format!("unknown command `{other}`"))),
+        };
+
+        Ok(Statement {
+            line: command.line,
+            span,
+            command: command_kind,
+        })
+    }
+}
+
+/// Parses a test script, returning every error found.
+pub fn parse_script(script: &str) -> Result<Vec<Statement>, Vec<Diagnostic>> {
+    let tokens = tokenize(script).map_err(|err| vec![err])?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        errors: Vec::new(),
+    };
+
+    let statements = parser.block(false);
+    if parser.errors.is_empty() {
+        Ok(statements)
+    } else {
+        Err(parser.errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_course_script() {
+        let script = parse_script(include_str!("../../4/mult/Mult.tst")).unwrap();
+        assert_eq!(script[0].command, Command::Load(Some("Mult.hack".into())));
+        assert_eq!(script[0].line, 8);
+
+        let Command::OutputList(columns) = &script[3].command else {
+            panic!("expected output-list, found {:?}", script[3]);
+        };
+        assert_eq!(columns.len(), 3);
+        assert_eq!(
+            columns[2],
+            Column {
+                var: Var {
+                    name: "RAM".into(),
+                    index: Some(2),
+                },
+                radix: Radix::Decimal,
+                left: 2,
+                width: 6,
+                right: 2,
+            }
+        );
+
+        assert_eq!(
+            script[6].command,
+            Command::Set(
+                Var {
+                    name: "RAM".into(),
+                    index: Some(2)
+                },
+                -1
+            )
+        );
+        assert_eq!(
+            script[7].command,
+            Command::Repeat(
+                Some(20),
+                vec![Statement {
+                    line: 17,
+                    span: 2..10,
+                    command: Command::Step(Step::TickTock),
+                }]
+            )
+        );
+    }
+
+    #[test]
+    fn parses_endless_repeat() {
+        let script = parse_script(include_str!("../../4/fill/Fill.tst")).unwrap();
+        assert_eq!(
+            script[2].command,
+            Command::Repeat(
+                None,
+                vec![Statement {
+                    line: 12,
+                    span: 2..10,
+                    command: Command::Step(Step::TickTock),
+                }]
+            )
+        );
+    }
+
+    #[test]
+    fn values_and_comments() {
+        let script = parse_script(
+            "/* set up\n the keyboard */ set RAM[24576] %X4B, // key\n\
+             set A %B101; set D -32768; echo \"all set\";",
+        )
+        .unwrap();
+        let values: Vec<_> = script
+            .iter()
+            .map(|statement| statement.command.clone())
+            .collect();
+        assert_eq!(
+            values[..3],
+            [
+                Command::Set(Var::parse("RAM[24576]").unwrap(), 0x4B),
+                Command::Set(Var::parse("A").unwrap(), 5),
+                Command::Set(Var::parse("D").unwrap(), i16::MIN),
+            ]
+        );
+        assert_eq!(values[3], Command::Echo("all set".into()));
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let errors = parse_script(
+            "set RAM[0];\nfrobnicate;\noutput-list RAM[0]%Q1.6.1;\nrepeat 3 { ticktock;",
+        )
+        .unwrap_err();
+        let lines: Vec<_> = errors.iter().map(|err| err.line).collect();
+        assert_eq!(lines, [1, 2, 3, 4]);
+        assert_eq!(errors[1].message, "unknown command `frobnicate`");
+    }
+}
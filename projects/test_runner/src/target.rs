@@ -0,0 +1,35 @@
+use crate::script::{Step, Var};
+use std::path::Path;
+
+/// A simulator a test script can drive.
+pub trait Target {
+    /// Loads the program named by a `load` command, resolved against the
+    /// script's directory, or the directory itself when no file is named.
+    fn load(&mut self, path: &Path) -> Result<(), String>;
+
+    fn get(&self, var: &Var) -> Result<i16, String>;
+
+    /// A variable shown as text rather than a number, such as the clock's
+    /// `time`; `None` for the others.
+    fn text(&self, _var: &Var) -> Option<String> {
+        None
+    }
+
+    fn set(&mut self, var: &Var, value: i16) -> Result<(), String>;
+
+    fn step(&mut self, step: Step) -> Result<(), String>;
+}
+
+pub(crate) fn unknown_variable(var: &Var) -> String {
+    format!("unknown variable {var}")
+}
+
+/// The index of an array variable such as `RAM[12]`, checked against the
+/// array's size.
+pub(crate) fn index(var: &Var, size: usize) -> Result<usize, String> {
+    match var.index {
+        Some(index) if (index as usize) < size => Ok(index as usize),
+        Some(_) => Err(format!("{var} is out of range")),
+        None => Err(format!("{} needs an index", var.name)),
+    }
+}
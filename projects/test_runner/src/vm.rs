@@ -0,0 +1,69 @@
+use crate::script::{Step, Var};
+use crate::target::{index, unknown_variable, Target};
+use std::path::Path;
+use vm_emulator::vm::{ARG, LCL, SP, TEMP, TEMP_SIZE, THAT, THIS};
+use vm_emulator::{Program, Vm, RAM_SIZE};
+
+/// Runs VM emulator scripts on [`Vm`].
+pub struct VmTarget {
+    pub vm: Vm,
+}
+
+impl Default for VmTarget {
+    fn default() -> Self {
+        let empty = Program::from_sources([]).expect("an empty program links");
+        Self { vm: Vm::new(empty) }
+    }
+}
+
+impl VmTarget {
+    /// The RAM address a variable names: the segment pointers themselves, or
+    /// an entry of a segment when indexed.
+    fn address(&self, var: &Var) -> Result<usize, String> {
+        let pointer = match var.name.as_str() {
+            "sp" => SP,
+            "local" => LCL,
+            "argument" => ARG,
+            "this" => THIS,
+            "that" => THAT,
+            "temp" => return Ok(TEMP as usize + index(var, TEMP_SIZE as usize)?),
+            "RAM" => return index(var, RAM_SIZE),
+            _ => return Err(unknown_variable(var)),
+        };
+        if var.name == "sp" || var.index.is_none() {
+            return Ok(pointer as usize);
+        }
+
+        let base = self.vm.ram()[pointer as usize] as i32;
+        let addr = base + index(var, RAM_SIZE)? as i32;
+        if (0..RAM_SIZE as i32).contains(&addr) {
+            Ok(addr as usize)
+        } else {
+            Err(format!("{var} is at RAM[{addr}], out of range"))
+        }
+    }
+}
+
+impl Target for VmTarget {
+    fn load(&mut self, path: &Path) -> Result<(), String> {
+        self.vm = Vm::new(Program::load(path)?);
+        Ok(())
+    }
+
+    fn get(&self, var: &Var) -> Result<i16, String> {
+        Ok(self.vm.ram()[self.address(var)?])
+    }
+
+    fn set(&mut self, var: &Var, value: i16) -> Result<(), String> {
+        let addr = self.address(var)?;
+        self.vm.ram_mut()[addr] = value;
+        Ok(())
+    }
+
+    fn step(&mut self, step: Step) -> Result<(), String> {
+        match step {
+            Step::VmStep => self.vm.step().map_err(|trap| trap.to_string()),
+            _ => Err(format!("the VM emulator doesn't support {}", step.name())),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::env;
+use std::process::ExitCode;
+use vm_diff::fuzz::{self, Config};
+
+const DEFAULT_CASES: u64 = 1000;
+
+const USAGE: &str = "\
+Usage: vm_fuzz [--seed N] [--cases N] [--files N] [--functions N]
+
+Generates random VM programs, translates them with the VM translator and
+compares them on the VM and CPU emulators like vm_diff does. The first program
+they disagree on is shrunk to a smaller one they still disagree on, and both
+are printed along with the seed that reproduces them.
+
+Options:
+  --seed N       seed of the first program (default 0), the next ones
+                 following it
+  --cases N      programs to check (default 1000)
+  --files N      files the programs are spread over, 1 to 4 (default 3)
+  --functions N  functions besides Sys.init, at most (default 3)
+
+Exit codes:
+  0  the translation agreed on every program
+  1  a program was found they disagree on
+  2  invalid command line";
+
+fn main() -> ExitCode {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let mut config = Config::default();
+    let (mut seed, mut cases) = (0, DEFAULT_CASES);
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().and_then(|value| value.parse::<u64>().ok());
+        let Some(value) = value else {
+            eprintln!("{arg} needs a number\n\n{USAGE}");
+            return ExitCode::from(2);
+        };
+        match arg.as_str() {
+            "--seed" => seed = value,
+            "--cases" => cases = value,
+            "--files" if (1..=4).contains(&value) => config.files = value as usize,
+            "--functions" => config.functions = value as usize,
+            _ => {
+                eprintln!("{USAGE}");
+                return ExitCode::from(2);
+            }
+        }
+    }
+
+    match fuzz::fuzz(&config, seed, cases, fuzz::check) {
+        Ok(()) => {
+            println!("the translation agreed on {cases} programs");
+            ExitCode::SUCCESS
+        }
+        Err(failure) => {
+            println!(
+                "seed {} fails ({} commands):\n{}",
+                failure.seed,
+                failure.original.len(),
+                failure.original
+            );
+            println!(
+                "shrunk to {} commands, which fail since {}:\n{}",
+                failure.shrunk.len(),
+                failure.reason,
+                failure.shrunk
+            );
+            ExitCode::from(1)
+        }
+    }
+}
@@ -0,0 +1,879 @@
+//! Property-based testing of the VM translator: random well-formed VM
+//! programs are compared on the VM emulator and, translated, on the CPU
+//! emulator, and a program the two disagree on is shrunk to the smallest one
+//! they still disagree on.
+//!
+//! Programs are generated as functions of statements and expressions rather
+//! than as bare commands, so that every program, shrunk or not, keeps its
+//! stack balanced, only uses the segments its functions have, and ends.
+
+use crate::translation::{translate, Translation};
+use crate::{compare, compare_with, Outcome};
+use std::fmt;
+use vm_emulator::Program;
+use vm_translator::{Inst, SegmentAddr};
+
+/// Commands a generated program may execute before it's deemed stuck. The
+/// programs end well before that: loops run a few rounds and functions only
+/// call the functions generated after them.
+pub const MAX_STEPS: u64 = 100_000;
+
+/// Entries of the static, temp, this and that segments programs use.
+const SEGMENT_ENTRIES: u16 = 8;
+/// Range the `this` and `that` segments are placed in, on the heap.
+const HEAP: (u16, u16) = (2048, 16000);
+/// Names of the files, the first one holding `Sys.init`.
+const FILE_NAMES: [&str; 4] = ["Sys", "Main", "Math2", "Util"];
+
+/// A small, seedable xorshift generator, so that a failure can be
+/// reproduced from its seed.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64, so that close seeds give unrelated sequences
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Self((z ^ (z >> 31)) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..n`, `n` being positive.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len() as u64) as usize].clone()
+    }
+}
+
+/// What the generated programs look like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// Functions besides `Sys.init`, at most.
+    pub functions: usize,
+    /// Files the functions are spread over, at most four.
+    pub files: usize,
+    /// Statements of a block, at most.
+    pub statements: usize,
+    /// Nesting of expressions and of `if` and loop blocks, at most.
+    pub depth: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            functions: 3,
+            files: 3,
+            statements: 6,
+            depth: 3,
+        }
+    }
+}
+
+/// An expression, which pushes a single value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Push(SegmentAddr),
+    /// `neg` or `not`.
+    Unary(Inst, Box<Expr>),
+    /// One of the binary arithmetic and logical commands.
+    Binary(Inst, Box<Expr>, Box<Expr>),
+    /// A call of the function of that index, with its arguments.
+    Call(usize, Vec<Expr>),
+}
+
+/// A statement, which leaves the stack as it found it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stmt {
+    Pop(Expr, SegmentAddr),
+    /// Sets `pointer 0` or `pointer 1` to an address on the heap.
+    SetPointer(u16, u16),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    /// Runs its body `times` times, counting down in a local that the body
+    /// leaves alone.
+    Loop {
+        counter: u16,
+        times: u16,
+        body: Vec<Stmt>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub file: usize,
+    pub args: u16,
+    pub locals: u16,
+    pub body: Vec<Stmt>,
+    /// What the function returns.
+    pub result: Expr,
+}
+
+/// A generated program. Function 0 is `Sys.init`, which starts by pointing
+/// `this` and `that` to the heap unless the program doesn't use them, and
+/// functions only call the functions after them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    pub files: usize,
+    pub this: Option<u16>,
+    pub that: Option<u16>,
+    pub functions: Vec<Function>,
+}
+
+/// A program the translation disagrees with the VM on.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    /// The seed the program was generated from.
+    pub seed: u64,
+    pub original: Case,
+    pub shrunk: Case,
+    /// Why the shrunk program fails.
+    pub reason: String,
+}
+
+impl Case {
+    pub fn function_name(&self, function: usize) -> String {
+        match function {
+            0 => "Sys.init".to_string(),
+            _ => format!("{}.f{function}", FILE_NAMES[self.functions[function].file]),
+        }
+    }
+
+    /// The `(name, source)` pair of each file.
+    pub fn sources(&self) -> Vec<(String, String)> {
+        let mut sources: Vec<_> = FILE_NAMES[..self.files]
+            .iter()
+            .map(|name| (name.to_string(), String::new()))
+            .collect();
+        let mut labels = 0;
+        for (index, function) in self.functions.iter().enumerate() {
+            let mut insts = vec![Inst::Function(self.function_name(index), function.locals)];
+            if index == 0 {
+                for (pointer, addr) in [(0, self.this), (1, self.that)] {
+                    let Some(addr) = addr else { continue };
+                    insts.push(Inst::Push(SegmentAddr::Constant(addr)));
+                    insts.push(Inst::Pop(SegmentAddr::Pointer(pointer)));
+                }
+            }
+            self.lower_block(&function.body, &mut insts, &mut labels);
+            self.lower_expr(&function.result, &mut insts);
+            insts.push(Inst::Return);
+
+            let source = &mut sources[function.file].1;
+            for inst in insts {
+                *source += &format!("{inst}\n");
+            }
+        }
+        sources
+    }
+
+    /// Links the files of the program.
+    pub fn program(&self) -> Result<Program, String> {
+        let sources = self.sources();
+        Program::from_sources(
+            sources
+                .iter()
+                .map(|(name, source)| (name.as_str(), source.as_str())),
+        )
+    }
+
+    /// Commands of the program.
+    pub fn len(&self) -> usize {
+        self.sources()
+            .iter()
+            .map(|(_, source)| source.lines().count())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lower_expr(&self, expr: &Expr, insts: &mut Vec<Inst>) {
+        match expr {
+            Expr::Push(segment) => insts.push(Inst::Push(*segment)),
+            Expr::Unary(op, x) => {
+                self.lower_expr(x, insts);
+                insts.push(op.clone());
+            }
+            Expr::Binary(op, x, y) => {
+                self.lower_expr(x, insts);
+                self.lower_expr(y, insts);
+                insts.push(op.clone());
+            }
+            Expr::Call(function, args) => {
+                for arg in args {
+                    self.lower_expr(arg, insts);
+                }
+                insts.push(Inst::Call(self.function_name(*function), args.len() as u16));
+            }
+        }
+    }
+
+    /// Lowers statements, numbering their labels program-wide.
+    fn lower_block(&self, block: &[Stmt], insts: &mut Vec<Inst>, labels: &mut usize) {
+        for stmt in block {
+            match stmt {
+                Stmt::Pop(expr, segment) => {
+                    self.lower_expr(expr, insts);
+                    insts.push(Inst::Pop(*segment));
+                }
+                Stmt::SetPointer(pointer, addr) => {
+                    insts.push(Inst::Push(SegmentAddr::Constant(*addr)));
+                    insts.push(Inst::Pop(SegmentAddr::Pointer(*pointer)));
+                }
+                Stmt::If(cond, then, otherwise) => {
+                    *labels += 1;
+                    let (then_label, end_label) =
+                        (format!("IF_TRUE{labels}"), format!("IF_END{labels}"));
+                    self.lower_expr(cond, insts);
+                    insts.push(Inst::IfGoto(then_label.clone()));
+                    self.lower_block(otherwise, insts, labels);
+                    insts.push(Inst::Goto(end_label.clone()));
+                    insts.push(Inst::Label(then_label));
+                    self.lower_block(then, insts, labels);
+                    insts.push(Inst::Label(end_label));
+                }
+                Stmt::Loop {
+                    counter,
+                    times,
+                    body,
+                } => {
+                    *labels += 1;
+                    let (start, end) = (format!("LOOP{labels}"), format!("LOOP_END{labels}"));
+                    let counter = SegmentAddr::Local(*counter);
+                    insts.push(Inst::Push(SegmentAddr::Constant(*times)));
+                    insts.push(Inst::Pop(counter));
+                    insts.push(Inst::Label(start.clone()));
+                    insts.push(Inst::Push(counter));
+                    insts.push(Inst::Push(SegmentAddr::Constant(0)));
+                    insts.push(Inst::Eq);
+                    insts.push(Inst::IfGoto(end.clone()));
+                    self.lower_block(body, insts, labels);
+                    insts.push(Inst::Push(counter));
+                    insts.push(Inst::Push(SegmentAddr::Constant(1)));
+                    insts.push(Inst::Sub);
+                    insts.push(Inst::Pop(counter));
+                    insts.push(Inst::Goto(start));
+                    insts.push(Inst::Label(end));
+                }
+            }
+        }
+    }
+}
+
+/// Lists the program file by file.
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, source) in self.sources() {
+            if !source.is_empty() {
+                write!(f, "// {name}.vm\n{source}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Generates a program, its functions being laid out before their bodies so
+/// that calls know the arguments of the functions they call.
+pub fn generate(rng: &mut Rng, config: &Config) -> Case {
+    let count = 1 + rng.below(config.functions as u64 + 1) as usize;
+    let files = config.files.clamp(1, FILE_NAMES.len());
+    let mut case = Case {
+        files,
+        this: Some(heap_address(rng)),
+        that: Some(heap_address(rng)),
+        functions: (0..count)
+            .map(|index| Function {
+                file: if index == 0 {
+                    0
+                } else {
+                    rng.below(files as u64) as usize
+                },
+                args: if index == 0 { 0 } else { rng.below(4) as u16 },
+                locals: rng.below(5) as u16,
+                body: Vec::new(),
+                result: Expr::Push(SegmentAddr::Constant(0)),
+            })
+            .collect(),
+    };
+
+    for index in 0..count {
+        let mut gen = Generator {
+            rng: &mut *rng,
+            config,
+            case: &case,
+            function: index,
+            counters: Vec::new(),
+        };
+        let body = gen.block(config.depth);
+        let result = gen.expr(config.depth);
+        case.functions[index].body = body;
+        case.functions[index].result = result;
+    }
+    case
+}
+
+fn heap_address(rng: &mut Rng) -> u16 {
+    HEAP.0 + rng.below((HEAP.1 - HEAP.0) as u64) as u16
+}
+
+struct Generator<'a> {
+    rng: &'a mut Rng,
+    config: &'a Config,
+    case: &'a Case,
+    function: usize,
+    /// Locals counting the rounds of the enclosing loops.
+    counters: Vec<u16>,
+}
+
+impl Generator<'_> {
+    fn current(&self) -> &Function {
+        &self.case.functions[self.function]
+    }
+
+    fn block(&mut self, depth: usize) -> Vec<Stmt> {
+        let len = self.rng.below(self.config.statements as u64 + 1);
+        (0..len).map(|_| self.stmt(depth)).collect()
+    }
+
+    fn stmt(&mut self, depth: usize) -> Stmt {
+        let free_local = (0..self.current().locals).find(|local| !self.counters.contains(local));
+        match self.rng.below(10) {
+            0 => Stmt::SetPointer(self.rng.below(2) as u16, heap_address(self.rng)),
+            1 if depth > 0 => Stmt::If(
+                self.expr(depth),
+                self.block(depth - 1),
+                self.block(depth - 1),
+            ),
+            2 if depth > 0 && free_local.is_some() => {
+                let counter = free_local.unwrap();
+                self.counters.push(counter);
+                let body = self.block(depth - 1);
+                self.counters.pop();
+                Stmt::Loop {
+                    counter,
+                    times: self.rng.below(4) as u16,
+                    body,
+                }
+            }
+            _ => Stmt::Pop(self.expr(depth), self.target()),
+        }
+    }
+
+    /// A segment entry to pop to. The loop counters are left alone.
+    fn target(&mut self) -> SegmentAddr {
+        loop {
+            let segment = self.segment();
+            match segment {
+                SegmentAddr::Constant(_) | SegmentAddr::Pointer(_) => continue,
+                SegmentAddr::Local(local) if self.counters.contains(&local) => continue,
+                _ => return segment,
+            }
+        }
+    }
+
+    /// A segment entry the function can access.
+    fn segment(&mut self) -> SegmentAddr {
+        let (args, locals) = (self.current().args, self.current().locals);
+        loop {
+            let index = self.rng.below(SEGMENT_ENTRIES as u64) as u16;
+            return match self.rng.below(8) {
+                0 => SegmentAddr::Constant(self.constant()),
+                1 => SegmentAddr::Static(index),
+                2 => SegmentAddr::Temp(index),
+                3 => SegmentAddr::Pointer(index % 2),
+                4 => SegmentAddr::This(index),
+                5 => SegmentAddr::That(index),
+                6 if locals > 0 => SegmentAddr::Local(index % locals),
+                7 if args > 0 => SegmentAddr::Arg(index % args),
+                _ => continue,
+            };
+        }
+    }
+
+    /// A constant, small ones and the extremes being the likeliest.
+    fn constant(&mut self) -> u16 {
+        match self.rng.below(4) {
+            0 => self.rng.pick(&[0, 1, 2, 0x3FFF, 0x4000, 0x7FFF]),
+            1 => self.rng.below(0x8000) as u16,
+            _ => self.rng.below(16) as u16,
+        }
+    }
+
+    fn expr(&mut self, depth: usize) -> Expr {
+        let callees = self.function + 1..self.case.functions.len();
+        match self.rng.below(10) {
+            0..=3 if depth > 0 => Expr::Binary(
+                self.rng.pick(&[
+                    Inst::Add,
+                    Inst::Sub,
+                    Inst::Eq,
+                    Inst::Gt,
+                    Inst::Lt,
+                    Inst::And,
+                    Inst::Or,
+                ]),
+                Box::new(self.expr(depth - 1)),
+                Box::new(self.expr(depth - 1)),
+            ),
+            4 if depth > 0 => Expr::Unary(
+                self.rng.pick(&[Inst::Neg, Inst::Not]),
+                Box::new(self.expr(depth - 1)),
+            ),
+            5 if depth > 0 && !callees.is_empty() => {
+                let callee = callees.start + self.rng.below(callees.len() as u64) as usize;
+                let args = self.case.functions[callee].args;
+                Expr::Call(callee, (0..args).map(|_| self.expr(depth - 1)).collect())
+            }
+            _ => Expr::Push(self.segment()),
+        }
+    }
+}
+
+/// Translates a generated program and compares it with the VM, returning why
+/// they disagree if they do.
+pub fn check(case: &Case) -> Result<(), String> {
+    outcome(compare(case.program()?, MAX_STEPS))
+}
+
+/// Like [`check`], with the translated code edited before it's assembled.
+pub fn check_edited(case: &Case, edit: impl Fn(String) -> String) -> Result<(), String> {
+    let program = case.program()?;
+    let translation = Translation::assemble(&program, edit(translate(&program)?))?;
+    outcome(compare_with(program, &translation, MAX_STEPS))
+}
+
+fn outcome(outcome: Result<Outcome, String>) -> Result<(), String> {
+    match outcome? {
+        Outcome::Agreed { steps } if steps >= MAX_STEPS => {
+            Err(format!("the program didn't end within {MAX_STEPS} steps"))
+        }
+        Outcome::Agreed { .. } => Ok(()),
+        Outcome::Diverged(divergence) => Err(format!("the runs diverged {divergence}")),
+    }
+}
+
+/// Generates `cases` programs from `seed` on and checks each of them,
+/// shrinking the first one that fails. The program of seed `n` is the same
+/// whatever the seed the run started from.
+pub fn fuzz(
+    config: &Config,
+    seed: u64,
+    cases: u64,
+    check: impl Fn(&Case) -> Result<(), String>,
+) -> Result<(), Failure> {
+    for seed in seed..seed.saturating_add(cases) {
+        let original = generate(&mut Rng::new(seed), config);
+        if let Err(reason) = check(&original) {
+            let (shrunk, reason) = shrink(original.clone(), reason, &check);
+            return Err(Failure {
+                seed,
+                original,
+                shrunk,
+                reason,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Shrinks a failing program for as long as one of its simplifications still
+/// fails, returning the smallest one found and why it fails.
+pub fn shrink(
+    mut case: Case,
+    mut reason: String,
+    check: impl Fn(&Case) -> Result<(), String>,
+) -> (Case, String) {
+    'shrinking: loop {
+        for candidate in simplifications(&case) {
+            if let Err(why) = check(&candidate) {
+                (case, reason) = (candidate, why);
+                continue 'shrinking;
+            }
+        }
+        return (case, reason);
+    }
+}
+
+/// The programs one step simpler than `case`, the simplest first.
+fn simplifications(case: &Case) -> Vec<Case> {
+    let mut candidates = Vec::new();
+
+    // a function called by Sys.init taking its place, the functions before
+    // it being left uncalled
+    for entry in 1..case.functions.len() {
+        let function = &case.functions[entry];
+        if function_uses(function, &|s| matches!(s, SegmentAddr::Arg(_))) {
+            continue;
+        }
+        let mut simpler = case.clone();
+        simpler.functions.drain(..entry);
+        simpler.functions[0].file = 0;
+        simpler.functions[0].args = 0;
+        for function in &mut simpler.functions {
+            visit_function(function, &mut |expr| {
+                if let Expr::Call(callee, _) = expr {
+                    *callee -= entry;
+                }
+            });
+        }
+        candidates.push(simpler);
+    }
+
+    // whole functions, their calls pushing 0 instead
+    for removed in 1..case.functions.len() {
+        let mut simpler = case.clone();
+        simpler.functions.remove(removed);
+        for function in &mut simpler.functions {
+            visit_function(function, &mut |expr| match expr {
+                Expr::Call(callee, _) if *callee == removed => {
+                    *expr = Expr::Push(SegmentAddr::Constant(0));
+                }
+                Expr::Call(callee, _) if *callee > removed => *callee -= 1,
+                _ => {}
+            });
+        }
+        candidates.push(simpler);
+    }
+
+    // the last argument of a function, if it doesn't use it
+    for (index, function) in case.functions.iter().enumerate() {
+        let Some(last) = function.args.checked_sub(1) else {
+            continue;
+        };
+        if function_uses(function, &|s| *s == SegmentAddr::Arg(last)) {
+            continue;
+        }
+        let mut simpler = case.clone();
+        simpler.functions[index].args = last;
+        for function in &mut simpler.functions {
+            visit_function(function, &mut |expr| match expr {
+                Expr::Call(callee, args) if *callee == index => {
+                    args.pop();
+                }
+                _ => {}
+            });
+        }
+        candidates.push(simpler);
+    }
+
+    // the setup of the pointers, once nothing depends on it
+    let uses = |segment: &dyn Fn(&SegmentAddr) -> bool| {
+        case.functions
+            .iter()
+            .any(|function| function_uses(function, segment))
+    };
+    if case.this.is_some()
+        && !uses(&|s| matches!(s, SegmentAddr::This(_) | SegmentAddr::Pointer(0)))
+    {
+        candidates.push(Case {
+            this: None,
+            ..case.clone()
+        });
+    }
+    if case.that.is_some()
+        && !uses(&|s| matches!(s, SegmentAddr::That(_) | SegmentAddr::Pointer(1)))
+    {
+        candidates.push(Case {
+            that: None,
+            ..case.clone()
+        });
+    }
+
+    for (index, function) in case.functions.iter().enumerate() {
+        for body in simpler_blocks(&function.body) {
+            let mut simpler = case.clone();
+            simpler.functions[index].body = body;
+            candidates.push(simpler);
+        }
+        for result in simpler_exprs(&function.result) {
+            let mut simpler = case.clone();
+            simpler.functions[index].result = result;
+            candidates.push(simpler);
+        }
+        // unused locals
+        let used = used_locals(&function.body).max(used_locals_expr(&function.result));
+        if function.locals > used {
+            let mut simpler = case.clone();
+            simpler.functions[index].locals = used;
+            candidates.push(simpler);
+        }
+    }
+    candidates
+}
+
+fn function_uses(function: &Function, segment: &dyn Fn(&SegmentAddr) -> bool) -> bool {
+    block_uses(&function.body, segment) || expr_uses(&function.result, segment)
+}
+
+/// Whether a block accesses a segment entry, setting a pointer counting as
+/// accessing it.
+fn block_uses(block: &[Stmt], segment: &dyn Fn(&SegmentAddr) -> bool) -> bool {
+    block.iter().any(|stmt| match stmt {
+        Stmt::Pop(expr, target) => segment(target) || expr_uses(expr, segment),
+        Stmt::SetPointer(pointer, _) => segment(&SegmentAddr::Pointer(*pointer)),
+        Stmt::If(cond, then, otherwise) => {
+            expr_uses(cond, segment) || block_uses(then, segment) || block_uses(otherwise, segment)
+        }
+        Stmt::Loop { counter, body, .. } => {
+            segment(&SegmentAddr::Local(*counter)) || block_uses(body, segment)
+        }
+    })
+}
+
+fn expr_uses(expr: &Expr, segment: &dyn Fn(&SegmentAddr) -> bool) -> bool {
+    match expr {
+        Expr::Push(pushed) => segment(pushed),
+        Expr::Unary(_, x) => expr_uses(x, segment),
+        Expr::Binary(_, x, y) => expr_uses(x, segment) || expr_uses(y, segment),
+        Expr::Call(_, args) => args.iter().any(|arg| expr_uses(arg, segment)),
+    }
+}
+
+/// Calls `visit` on every expression of a function, outermost first.
+fn visit_function(function: &mut Function, visit: &mut dyn FnMut(&mut Expr)) {
+    visit_block(&mut function.body, visit);
+    visit_expr(&mut function.result, visit);
+}
+
+fn visit_block(block: &mut [Stmt], visit: &mut dyn FnMut(&mut Expr)) {
+    for stmt in block {
+        match stmt {
+            Stmt::Pop(expr, _) => visit_expr(expr, visit),
+            Stmt::SetPointer(..) => {}
+            Stmt::If(cond, then, otherwise) => {
+                visit_expr(cond, visit);
+                visit_block(then, visit);
+                visit_block(otherwise, visit);
+            }
+            Stmt::Loop { body, .. } => visit_block(body, visit),
+        }
+    }
+}
+
+fn visit_expr(expr: &mut Expr, visit: &mut dyn FnMut(&mut Expr)) {
+    visit(expr);
+    match expr {
+        Expr::Push(_) => {}
+        Expr::Unary(_, x) => visit_expr(x, visit),
+        Expr::Binary(_, x, y) => {
+            visit_expr(x, visit);
+            visit_expr(y, visit);
+        }
+        Expr::Call(_, args) => {
+            for arg in args {
+                visit_expr(arg, visit);
+            }
+        }
+    }
+}
+
+/// Locals a block needs: one past the highest it uses.
+fn used_locals(block: &[Stmt]) -> u16 {
+    let local = |segment: &SegmentAddr| match segment {
+        SegmentAddr::Local(index) => index + 1,
+        _ => 0,
+    };
+    block
+        .iter()
+        .map(|stmt| match stmt {
+            Stmt::Pop(expr, segment) => used_locals_expr(expr).max(local(segment)),
+            Stmt::SetPointer(..) => 0,
+            Stmt::If(cond, then, otherwise) => used_locals_expr(cond)
+                .max(used_locals(then))
+                .max(used_locals(otherwise)),
+            Stmt::Loop { counter, body, .. } => (counter + 1).max(used_locals(body)),
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn used_locals_expr(expr: &Expr) -> u16 {
+    match expr {
+        Expr::Push(SegmentAddr::Local(index)) => index + 1,
+        Expr::Push(_) => 0,
+        Expr::Unary(_, x) => used_locals_expr(x),
+        Expr::Binary(_, x, y) => used_locals_expr(x).max(used_locals_expr(y)),
+        Expr::Call(_, args) => args.iter().map(used_locals_expr).max().unwrap_or(0),
+    }
+}
+
+/// Blocks one step simpler: a statement removed, or replaced by simpler
+/// statements.
+fn simpler_blocks(block: &[Stmt]) -> Vec<Vec<Stmt>> {
+    let mut blocks = Vec::new();
+    for (at, stmt) in block.iter().enumerate() {
+        for replacement in std::iter::once(Vec::new()).chain(simpler_stmts(stmt)) {
+            let mut simpler = block[..at].to_vec();
+            simpler.extend(replacement);
+            simpler.extend_from_slice(&block[at + 1..]);
+            blocks.push(simpler);
+        }
+    }
+    blocks
+}
+
+/// The statements that can replace a statement.
+fn simpler_stmts(stmt: &Stmt) -> Vec<Vec<Stmt>> {
+    match stmt {
+        Stmt::Pop(expr, segment) => {
+            let mut stmts = Vec::new();
+            if *segment != SegmentAddr::Temp(0) {
+                stmts.push(vec![Stmt::Pop(expr.clone(), SegmentAddr::Temp(0))]);
+            }
+            stmts.extend(
+                simpler_exprs(expr)
+                    .into_iter()
+                    .map(|expr| vec![Stmt::Pop(expr, *segment)]),
+            );
+            stmts
+        }
+        Stmt::SetPointer(..) => Vec::new(),
+        Stmt::If(cond, then, otherwise) => {
+            let mut stmts = vec![then.clone(), otherwise.clone()];
+            stmts.extend(
+                simpler_exprs(cond)
+                    .into_iter()
+                    .map(|cond| vec![Stmt::If(cond, then.clone(), otherwise.clone())]),
+            );
+            stmts.extend(
+                simpler_blocks(then)
+                    .into_iter()
+                    .map(|then| vec![Stmt::If(cond.clone(), then, otherwise.clone())]),
+            );
+            stmts.extend(
+                simpler_blocks(otherwise)
+                    .into_iter()
+                    .map(|otherwise| vec![Stmt::If(cond.clone(), then.clone(), otherwise)]),
+            );
+            stmts
+        }
+        Stmt::Loop {
+            counter,
+            times,
+            body,
+        } => {
+            let mut stmts = vec![body.clone()];
+            if *times > 1 {
+                stmts.push(vec![Stmt::Loop {
+                    counter: *counter,
+                    times: 1,
+                    body: body.clone(),
+                }]);
+            }
+            stmts.extend(simpler_blocks(body).into_iter().map(|body| {
+                vec![Stmt::Loop {
+                    counter: *counter,
+                    times: *times,
+                    body,
+                }]
+            }));
+            stmts
+        }
+    }
+}
+
+/// The expressions that can replace an expression.
+fn simpler_exprs(expr: &Expr) -> Vec<Expr> {
+    let zero = Expr::Push(SegmentAddr::Constant(0));
+    match expr {
+        Expr::Push(SegmentAddr::Constant(0)) => Vec::new(),
+        Expr::Push(SegmentAddr::Constant(value)) => {
+            vec![zero, Expr::Push(SegmentAddr::Constant(value / 2))]
+        }
+        Expr::Push(_) => vec![zero],
+        Expr::Unary(op, x) => {
+            let mut exprs = vec![(**x).clone()];
+            exprs.extend(
+                simpler_exprs(x)
+                    .into_iter()
+                    .map(|x| Expr::Unary(op.clone(), Box::new(x))),
+            );
+            exprs
+        }
+        Expr::Binary(op, x, y) => {
+            let mut exprs = vec![(**x).clone(), (**y).clone()];
+            exprs.extend(
+                simpler_exprs(x)
+                    .into_iter()
+                    .map(|x| Expr::Binary(op.clone(), Box::new(x), y.clone())),
+            );
+            exprs.extend(
+                simpler_exprs(y)
+                    .into_iter()
+                    .map(|y| Expr::Binary(op.clone(), x.clone(), Box::new(y))),
+            );
+            exprs
+        }
+        Expr::Call(function, args) => {
+            let mut exprs = vec![zero];
+            for (at, arg) in args.iter().enumerate() {
+                exprs.extend(simpler_exprs(arg).into_iter().map(|arg| {
+                    let mut args = args.clone();
+                    args[at] = arg;
+                    Expr::Call(*function, args)
+                }));
+            }
+            exprs
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_programs_that_link() {
+        let config = Config::default();
+        for seed in 0..50 {
+            let case = generate(&mut Rng::new(seed), &config);
+            assert_eq!(case, generate(&mut Rng::new(seed), &config));
+            if let Err(err) = case.program() {
+                panic!("seed {seed}: {err}\n{case}");
+            }
+        }
+    }
+
+    #[test]
+    fn translation_agrees_with_the_vm() {
+        if let Err(failure) = fuzz(&Config::default(), 0, 60, check) {
+            panic!(
+                "seed {}: {}\n{}",
+                failure.seed, failure.reason, failure.shrunk
+            );
+        }
+    }
+
+    #[test]
+    fn shrinks_failures_to_a_few_commands() {
+        // a translation that subtracts where it should add
+        let broken = |asm: String| asm.replace("M=M+D", "M=M-D");
+        let failure = fuzz(&Config::default(), 0, 100, |case| {
+            check_edited(case, broken)
+        })
+        .expect_err("no program adds two values");
+        assert!(failure.shrunk.len() < failure.original.len());
+        assert!(
+            failure.shrunk.len() <= 8,
+            "{} commands left:\n{}",
+            failure.shrunk.len(),
+            failure.shrunk
+        );
+        assert!(
+            failure.shrunk.to_string().contains("add"),
+            "{}",
+            failure.shrunk
+        );
+    }
+}
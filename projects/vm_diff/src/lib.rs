@@ -0,0 +1,376 @@
+//! Differential testing of the VM translator: a VM program runs on the VM
+//! emulator and, translated and assembled, on the CPU emulator. The two are
+//! compared each time the CPU reaches the code of the command the VM runs
+//! next.
+
+pub mod fuzz;
+pub mod translation;
+
+use cpu_emulator::machine::{Machine, KBD};
+use std::fmt;
+use translation::{describe_command, Translation};
+use vm_emulator::vm::{ARG, LCL, SP, STACK_BASE, TEMP, TEMP_SIZE, THAT, THIS};
+use vm_emulator::{Program, Vm};
+
+pub use translation::translate;
+
+/// CPU cycles the code of a single command may take. Even a function with
+/// hundreds of local variables initializes them in fewer.
+pub const MAX_CYCLES_PER_COMMAND: u64 = 100_000;
+
+/// First address of the heap, past the stack.
+const HEAP_BASE: u16 = 2048;
+
+/// Differences listed for a region of memory before the rest are counted.
+const DIFFERENCES_SHOWN: usize = 8;
+
+/// The first point where the two runs disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Commands the VM executed, including the one the runs disagree after.
+    pub steps: u64,
+    /// What was executed last, or `None` before the first command.
+    pub command: Option<String>,
+    pub differences: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.command {
+            Some(command) => write!(f, "after step {}, {command}:", self.steps)?,
+            None => write!(f, "before the first command:")?,
+        }
+        for difference in &self.differences {
+            write!(f, "\n  {difference}")?;
+        }
+        Ok(())
+    }
+}
+
+/// How a comparison ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Both runs agreed until the VM halted, ended, or executed the maximum
+    /// number of steps.
+    Agreed {
+        steps: u64,
+    },
+    Diverged(Divergence),
+}
+
+/// Checks that the program runs without the built-in OS, which the
+/// translated code can't call.
+fn check_self_contained(program: &Program) -> Result<(), String> {
+    if program.function("Sys.init").is_none() && program.function("Main.main").is_some() {
+        return Err(
+            "the program has no Sys.init: the translated code can't use the built-in one".into(),
+        );
+    }
+    for inst in &program.insts {
+        if let vm_translator::Inst::Call(name, _) = inst {
+            if program.function(name).is_none() {
+                return Err(format!(
+                    "the program calls {name}, which it doesn't define: the translated code can't use the built-in OS"
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Describes a word holding different values.
+fn difference(what: &str, vm_value: i16, cpu_value: u16) -> Option<String> {
+    (vm_value != cpu_value as i16)
+        .then(|| format!("{what}: VM {vm_value}, assembly {}", cpu_value as i16))
+}
+
+/// Compares the registers and memory the VM uses, the stack being compared up
+/// to the VM's stack pointer and statics by their symbol.
+fn differences(vm: &Vm, cpu: &Machine, translation: &Translation) -> Vec<String> {
+    let mut differences = Vec::new();
+    let vm_ram = vm.ram();
+    let cpu_ram = cpu.ram();
+    let word =
+        |what: &str, addr: u16| difference(what, vm_ram[addr as usize], cpu_ram[addr as usize]);
+
+    for (name, addr) in [
+        ("SP", SP),
+        ("LCL", LCL),
+        ("ARG", ARG),
+        ("THIS", THIS),
+        ("THAT", THAT),
+    ] {
+        differences.extend(word(name, addr));
+    }
+    for addr in TEMP..TEMP + TEMP_SIZE {
+        differences.extend(word(&format!("temp {}", addr - TEMP), addr));
+    }
+    for s in &translation.statics {
+        let Some(asm_addr) = s.asm_addr else {
+            differences.push(format!(
+                "static {}: not a variable of the assembly",
+                s.symbol
+            ));
+            continue;
+        };
+        let what = format!(
+            "static {} (RAM[{}], assembly RAM[{asm_addr}])",
+            s.symbol, s.vm_addr
+        );
+        differences.extend(difference(
+            &what,
+            vm_ram[s.vm_addr as usize],
+            cpu_ram[asm_addr as usize],
+        ));
+    }
+
+    // the saved return addresses of the call frames, found by following the
+    // saved LCL of each frame
+    let mut return_slots = Vec::new();
+    let mut lcl = vm_ram[LCL as usize] as u16;
+    while (STACK_BASE + 5..=HEAP_BASE).contains(&lcl) {
+        return_slots.push(lcl - 5);
+        lcl = vm_ram[lcl as usize - 4] as u16;
+    }
+
+    let sp = (vm_ram[SP as usize] as u16).clamp(STACK_BASE, HEAP_BASE);
+    let mut stack = Vec::new();
+    for addr in STACK_BASE..sp {
+        let (vm_value, cpu_value) = (vm_ram[addr as usize], cpu_ram[addr as usize]);
+        if !return_slots.contains(&addr) {
+            stack.extend(word(&format!("RAM[{addr}]"), addr));
+            continue;
+        }
+        let vm_target = vm_value as u16 as usize;
+        if translation.rom_address(vm_target) != Some(cpu_value) {
+            stack.push(format!(
+                "return address at RAM[{addr}]: VM {}, assembly {}",
+                describe_command(vm.program(), vm_target),
+                translation.describe(vm.program(), cpu_value)
+            ));
+        }
+    }
+    summarize(&mut differences, stack);
+
+    let heap = (HEAP_BASE..KBD)
+        .filter_map(|addr| word(&format!("RAM[{addr}]"), addr))
+        .collect();
+    summarize(&mut differences, heap);
+
+    differences
+}
+
+/// Adds the first differences of a region, counting the others.
+fn summarize(differences: &mut Vec<String>, mut region: Vec<String>) {
+    if region.len() > DIFFERENCES_SHOWN {
+        let more = region.len() - DIFFERENCES_SHOWN;
+        region.truncate(DIFFERENCES_SHOWN);
+        region.push(format!("... and {more} more"));
+    }
+    differences.extend(region);
+}
+
+/// Runs the CPU up to the start of the next command's code.
+fn run_to_boundary(cpu: &mut Machine, translation: &Translation) -> Result<(), String> {
+    for _ in 0..MAX_CYCLES_PER_COMMAND {
+        cpu.step().map_err(|fault| fault.to_string())?;
+        if translation.is_boundary(cpu.pc) {
+            return Ok(());
+        }
+    }
+    Err(format!(
+        "the code didn't reach another command within {MAX_CYCLES_PER_COMMAND} cycles, ROM[{}] being next",
+        cpu.pc
+    ))
+}
+
+/// Runs the program on both emulators until the VM halts, ends or executed
+/// `max_steps` commands, or the runs disagree. Programs calling functions
+/// they don't define can't be compared, as can't programs whose translation
+/// doesn't assemble.
+pub fn compare(program: Program, max_steps: u64) -> Result<Outcome, String> {
+    check_self_contained(&program)?;
+    let asm = translate(&program)?;
+    let translation = Translation::assemble(&program, asm)
+        .map_err(|errors| format!("the translated code doesn't assemble:\n{errors}"))?;
+    compare_with(program, &translation, max_steps)
+}
+
+/// Like [`compare`], with the program already translated and assembled.
+pub fn compare_with(
+    program: Program,
+    translation: &Translation,
+    max_steps: u64,
+) -> Result<Outcome, String> {
+    let mut cpu = Machine::new();
+    cpu.load(&translation.rom);
+
+    let bootstrap = program.function("Sys.init").is_some();
+    let mut vm = Vm::new(program);
+    if bootstrap {
+        vm.bootstrap().map_err(|trap| trap.to_string())?;
+    }
+
+    let mut command = None;
+    loop {
+        let reached = run_to_boundary(&mut cpu, translation);
+        let mut differences = match reached {
+            Ok(()) => differences(&vm, &cpu, translation),
+            Err(ref err) => vec![err.clone()],
+        };
+        if reached.is_ok() && translation.rom_address(vm.pc) != Some(cpu.pc) {
+            differences.insert(
+                0,
+                format!(
+                    "the VM continues with {}, the assembly with {}",
+                    describe_command(vm.program(), vm.pc),
+                    translation.describe(vm.program(), cpu.pc)
+                ),
+            );
+        }
+        if !differences.is_empty() {
+            return Ok(Outcome::Diverged(Divergence {
+                steps: vm.steps(),
+                command,
+                differences,
+            }));
+        }
+
+        if vm.is_halted() || vm.pc == vm.program().len() || vm.steps() >= max_steps {
+            return Ok(Outcome::Agreed { steps: vm.steps() });
+        }
+        command = Some(describe_command(vm.program(), vm.pc));
+        vm.step()
+            .map_err(|trap| format!("the VM stopped at {}: {trap}", command.as_ref().unwrap()))?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compare_sources(sources: &[(&str, &str)]) -> Result<Outcome, String> {
+        compare(Program::from_sources(sources.iter().copied())?, 1000)
+    }
+
+    #[test]
+    fn agrees_on_arithmetic() {
+        let outcome = compare_sources(&[(
+            "Main",
+            "push constant 7\npush constant 8\nadd\npush constant 3\nlt\n\
+             push constant 5\npop static 2\npush constant 3000\npop pointer 1\n\
+             push constant 9\npop that 4\npush static 2\npop temp 3\n",
+        )]);
+        assert_eq!(outcome, Ok(Outcome::Agreed { steps: 13 }));
+    }
+
+    #[test]
+    fn reports_the_first_divergent_command() {
+        // a translation that subtracts where it should add
+        let program = Program::from_sources([(
+            "Main",
+            "push constant 1\npush constant 2\nadd\npush constant 2\n",
+        )])
+        .unwrap();
+        let asm = translate(&program).unwrap().replace("M=M+D", "M=M-D");
+        let translation = Translation::assemble(&program, asm).unwrap();
+        let outcome = compare_with(program, &translation, 1000);
+        let Ok(Outcome::Diverged(divergence)) = outcome else {
+            panic!("no divergence: {outcome:?}");
+        };
+        assert_eq!(divergence.steps, 3);
+        assert_eq!(
+            divergence.command.as_deref(),
+            Some("command 2 of Main.vm (`add`)")
+        );
+        assert_eq!(divergence.differences, ["RAM[256]: VM 3, assembly -1"]);
+    }
+
+    #[test]
+    fn agrees_on_calls_and_the_heap() {
+        // only words that differ are reported, so a correct translation of
+        // a program using frames, locals and the heap agrees throughout
+        let outcome = compare_sources(&[(
+            "Main",
+            "function Sys.init 0\npush constant 4\ncall Main.f 1\npop temp 0\n\
+             label END\ngoto END\n\
+             function Main.f 2\npush argument 0\npop local 1\npush constant 3000\n\
+             pop pointer 1\npush local 1\npop that 0\npush that 0\nreturn\n",
+        )]);
+        assert_eq!(outcome, Ok(Outcome::Agreed { steps: 13 }));
+    }
+
+    #[test]
+    fn agrees_on_comparisons_that_overflow() {
+        // -32768 compared with 1: x - y overflows to 32767
+        let outcome = compare_sources(&[(
+            "Main",
+            "push constant 32767\nneg\npush constant 1\nsub\npop static 0\n\
+             push static 0\npush constant 1\ngt\n\
+             push static 0\npush constant 1\nlt\n\
+             push constant 1\npush static 0\ngt\n",
+        )]);
+        assert_eq!(outcome, Ok(Outcome::Agreed { steps: 14 }));
+    }
+
+    #[test]
+    fn agrees_on_temp() {
+        // with THIS set, an address computed from RAM[3] rather than 3 is off
+        let outcome = compare_sources(&[(
+            "Main",
+            "push constant 3000\npop pointer 0\npush constant 7\npop temp 3\n\
+             push temp 3\npush constant 1\nadd\n",
+        )]);
+        assert_eq!(outcome, Ok(Outcome::Agreed { steps: 7 }));
+    }
+
+    #[test]
+    fn agrees_on_function_locals() {
+        // every local is pushed and set to 0
+        let outcome = compare_sources(&[(
+            "Main",
+            "function Sys.init 3\npush local 0\npush local 1\nadd\npush local 2\nadd\n\
+             label END\ngoto END\n",
+        )]);
+        assert_eq!(outcome, Ok(Outcome::Agreed { steps: 6 }));
+    }
+
+    #[test]
+    fn agrees_on_returns() {
+        // the callee's return jumps back to the caller, not to the code
+        // after its own
+        let outcome = compare_sources(&[(
+            "Main",
+            "function Sys.init 0\npush constant 5\ncall Main.inc 1\npop static 0\n\
+             label END\ngoto END\n\
+             function Main.inc 0\npush argument 0\npush constant 1\nadd\nreturn\n\
+             function Main.other 0\npush constant 9\nreturn\n",
+        )]);
+        assert_eq!(outcome, Ok(Outcome::Agreed { steps: 9 }));
+    }
+
+    #[test]
+    fn agrees_on_calls() {
+        // each call returns to a label of its own, after the call
+        let outcome = compare_sources(&[(
+            "Main",
+            "function Sys.init 0\ncall Main.one 0\ncall Main.one 0\nadd\npop static 0\n\
+             label END\ngoto END\n\
+             function Main.one 0\npush constant 1\nreturn\n",
+        )]);
+        assert_eq!(outcome, Ok(Outcome::Agreed { steps: 11 }));
+    }
+
+    #[test]
+    fn requires_a_self_contained_program() {
+        let err = compare_sources(&[("Main", "call Math.multiply 0\n")]).unwrap_err();
+        assert!(err.contains("Math.multiply"), "{err}");
+
+        // rejected when loaded, as the translator would
+        let err = compare_sources(&[("Main", "push constant 1\npop pointer 2\n")]).unwrap_err();
+        assert_eq!(
+            err,
+            "Main.vm:2:1: pointer 2 is out of range, pointer only has entries 0 and 1"
+        );
+    }
+}
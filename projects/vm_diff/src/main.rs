@@ -0,0 +1,96 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+use vm_diff::{compare, translate, Outcome};
+use vm_emulator::Program;
+
+const DEFAULT_STEPS: u64 = 1_000_000;
+
+const USAGE: &str = "\
+Usage: vm_diff [--asm FILE] <FILE.vm|DIRECTORY> [MAX_STEPS]
+
+Runs a VM program on the VM emulator and, translated by the VM translator, on
+the CPU emulator, comparing the stack, the segments and the heap each time
+both reach the next command. Stops at the first command after which they
+differ, or once the VM halts or executed MAX_STEPS commands (default 1000000).
+
+Options:
+  --asm FILE  also write the translated code, with a (__vm.N) label before the
+              code of command N
+
+Exit codes:
+  0  both runs agreed
+  1  the runs diverged
+  2  invalid command line
+  3  the program can't be compared";
+
+fn main() -> ExitCode {
+    let mut args: Vec<_> = env::args().skip(1).collect();
+    let asm_file = match args.iter().position(|arg| arg == "--asm") {
+        Some(i) if i + 1 < args.len() => {
+            let file = args.remove(i + 1);
+            args.remove(i);
+            Some(file)
+        }
+        Some(_) => {
+            eprintln!("--asm needs a file\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+        None => None,
+    };
+    let (file, max_steps) = match args.as_slice() {
+        [file] => (file, Ok(DEFAULT_STEPS)),
+        [file, steps] => (
+            file,
+            steps.parse().map_err(|_| "MAX_STEPS must be a number"),
+        ),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let max_steps = match max_steps {
+        Ok(steps) => steps,
+        Err(msg) => {
+            eprintln!("{msg}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let program = match Program::load(Path::new(file)) {
+        Ok(program) => program,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return ExitCode::from(3);
+        }
+    };
+    if let Some(asm_file) = asm_file {
+        let asm = match translate(&program) {
+            Ok(asm) => asm,
+            Err(msg) => {
+                eprintln!("{msg}");
+                return ExitCode::from(3);
+            }
+        };
+        if let Err(err) = fs::write(&asm_file, asm) {
+            eprintln!("Could not write {asm_file}: {err}");
+            return ExitCode::from(3);
+        }
+    }
+
+    match compare(program, max_steps) {
+        Ok(Outcome::Agreed { steps }) => {
+            println!("the runs agreed for {steps} steps");
+            ExitCode::SUCCESS
+        }
+        Ok(Outcome::Diverged(divergence)) => {
+            println!("the runs diverged {divergence}");
+            ExitCode::from(1)
+        }
+        Err(msg) => {
+            eprintln!("{msg}");
+            ExitCode::from(3)
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! A VM program translated by `vm_translator` and assembled, with the ROM
+//! address where each command's code starts.
+
+use assembler::{parser, preprocessor, Assembler, Diagnostic, SymbolKind};
+use std::collections::HashMap;
+use vm_emulator::Program;
+use vm_translator::{generate_bootstrap_code, generate_inst_code, Inst, Labels, SegmentAddr};
+
+/// Prefix of the labels marking where each command's code starts.
+const MARKER: &str = "__vm";
+
+/// Where returning from `Sys.init` lands.
+const HALT_LABEL: &str = "__vm.halt";
+
+/// Sets the stack pointer like the VM does for programs without `Sys.init`.
+const SET_SP: &str = "@256\nD=A\n@SP\nM=D\n";
+
+/// A static variable: its symbol and its RAM address on both sides.
+pub struct Static {
+    pub symbol: String,
+    pub vm_addr: u16,
+    pub asm_addr: Option<u16>,
+}
+
+pub struct Translation {
+    pub asm: String,
+    pub rom: Vec<u16>,
+    /// ROM address of each command's code, then of the end of the program.
+    starts: Vec<u16>,
+    /// The last command starting at each of those addresses: labels have no
+    /// code, so they share their address with the command after them.
+    commands: HashMap<u16, usize>,
+    /// Where the bootstrap code continues once `Sys.init` returns.
+    halt: Option<u16>,
+    pub statics: Vec<Static>,
+}
+
+/// Translates the commands like `vm_translator` does, with a label before
+/// each command's code. Programs without `Sys.init` get no
+/// bootstrap code: they start by setting the stack pointer to 256, as the VM
+/// does. Fails on the first command the translator can't translate.
+pub fn translate(program: &Program) -> Result<String, String> {
+    let mut asm = if program.function("Sys.init").is_some() {
+        format!(
+            "{}({HALT_LABEL})\n@{HALT_LABEL}\n0;JMP\n",
+            generate_bootstrap_code()
+        )
+    } else {
+        SET_SP.to_string()
+    };
+
+    let (mut file, mut labels) = (None, Labels::default());
+    for (at, inst) in program.insts.iter().enumerate() {
+        let name = program.file_at(at).unwrap_or_default();
+        if file != Some(name) {
+            file = Some(name);
+            labels.enter_file(name);
+        }
+        asm += &format!("({MARKER}.{at})\n");
+        asm += &generate_inst_code(inst, name, &mut labels)
+            .map_err(|err| format!("{name}.vm: `{inst}`: {err}"))?;
+        asm += "\n";
+    }
+    Ok(asm + &format!("({MARKER}.{})\n", program.len()))
+}
+
+impl Translation {
+    /// Translates and assembles a program. Fails with the assembler's errors
+    /// if the translation is not valid assembly.
+    pub fn new(program: &Program) -> Result<Self, String> {
+        Self::assemble(program, translate(program)?)
+    }
+
+    /// Assembles code translated from a program, which must have the labels
+    /// [`translate`] puts before each command's code.
+    pub fn assemble(program: &Program, asm: String) -> Result<Self, String> {
+        let render = |diagnostics: Vec<Diagnostic>| {
+            diagnostics
+                .iter()
+                .filter(|diag| diag.is_error())
+                .map(|diag| diag.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let expansion = preprocessor::preprocess(&asm, None).map_err(render)?;
+        let tokens = parser::parse_located(&expansion.source)
+            .map_err(|diagnostics| render(expansion.remap_all(diagnostics)))?;
+        let mut assembler = Assembler::with_locations(tokens);
+        let diagnostics = expansion.remap_all(assembler.resolve_symbols());
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            return Err(render(diagnostics));
+        }
+
+        let symbols: HashMap<_, _> = assembler
+            .symbol_table()
+            .into_iter()
+            .map(|(kind, name, addr)| (name.to_string(), (kind, addr)))
+            .collect();
+        let label = |name: &str| match symbols.get(name) {
+            Some(&(SymbolKind::Label, addr)) => Some(addr),
+            _ => None,
+        };
+
+        let starts = (0..=program.len())
+            .map(|at| {
+                let marker = format!("{MARKER}.{at}");
+                label(&marker).ok_or(format!("the code has no ({marker}) label"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let commands = starts
+            .iter()
+            .enumerate()
+            .map(|(at, &addr)| (addr, at))
+            .collect();
+
+        let mut statics: Vec<Static> = Vec::new();
+        for (at, inst) in program.insts.iter().enumerate() {
+            let (Inst::Push(SegmentAddr::Static(index)) | Inst::Pop(SegmentAddr::Static(index))) =
+                inst
+            else {
+                continue;
+            };
+            let symbol = format!("{}.{index}", program.file_at(at).unwrap_or_default());
+            if statics.iter().any(|s| s.symbol == symbol) {
+                continue;
+            }
+            statics.push(Static {
+                vm_addr: program.static_address(at, *index).unwrap(),
+                asm_addr: match symbols.get(&symbol) {
+                    Some(&(SymbolKind::Variable, addr)) => Some(addr),
+                    _ => None,
+                },
+                symbol,
+            });
+        }
+
+        Ok(Self {
+            rom: assembler.assemble().instructions,
+            halt: label(HALT_LABEL),
+            asm,
+            starts,
+            commands,
+            statics,
+        })
+    }
+
+    /// ROM address of the code the VM runs next, given its program counter.
+    pub fn rom_address(&self, pc: usize) -> Option<u16> {
+        match pc {
+            vm_emulator::vm::HALT => self.halt,
+            pc => self.starts.get(pc).copied(),
+        }
+    }
+
+    /// Whether the CPU is at the start of a command's code, or done.
+    pub fn is_boundary(&self, addr: u16) -> bool {
+        self.commands.contains_key(&addr) || self.halt == Some(addr)
+    }
+
+    /// Describes a ROM address in terms of the VM program.
+    pub fn describe(&self, program: &Program, addr: u16) -> String {
+        if self.halt == Some(addr) {
+            return "the end of the bootstrap code".to_string();
+        }
+        match self.commands.get(&addr) {
+            Some(&at) => describe_command(program, at),
+            None => format!("ROM[{addr}]"),
+        }
+    }
+}
+
+/// Describes a command by its index, its file and the command itself.
+pub fn describe_command(program: &Program, at: usize) -> String {
+    match program.insts.get(at) {
+        Some(inst) => format!(
+            "command {at} of {}.vm (`{inst}`)",
+            program.file_at(at).unwrap_or_default()
+        ),
+        None if at == vm_emulator::vm::HALT => "the end of the bootstrap code".to_string(),
+        None => "the end of the program".to_string(),
+    }
+}
@@ -0,0 +1,6 @@
+pub mod os;
+pub mod program;
+pub mod vm;
+
+pub use program::Program;
+pub use vm::{Stop, Trap, Vm, RAM_SIZE};
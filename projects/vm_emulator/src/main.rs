@@ -0,0 +1,83 @@
+use std::env;
+use std::path::Path;
+use std::process::ExitCode;
+use vm_emulator::vm::{ARG, LCL, SP, STACK_BASE, THAT, THIS};
+use vm_emulator::{Program, Stop, Vm};
+
+const DEFAULT_STEPS: u64 = 1_000_000;
+
+/// Words of the stack printed when done.
+const STACK_SHOWN: usize = 16;
+
+const USAGE: &str = "\
+Usage: vm_emulator <FILE.vm|DIRECTORY> [MAX_STEPS]
+
+Runs a VM program until it halts or MAX_STEPS commands were executed
+(default 1000000). A directory runs all of its .vm files, starting with a call
+to Sys.init. OS functions the program doesn't define are built in.";
+
+fn main() -> ExitCode {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let (file, max_steps) = match args.as_slice() {
+        [file] => (file, Ok(DEFAULT_STEPS)),
+        [file, steps] => (
+            file,
+            steps.parse().map_err(|_| "MAX_STEPS must be a number"),
+        ),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let max_steps = match max_steps {
+        Ok(steps) => steps,
+        Err(msg) => {
+            eprintln!("{msg}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let program = match Program::load(Path::new(file)) {
+        Ok(program) => program,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let has_sys_init = program.function("Sys.init").is_some();
+    let mut vm = Vm::new(program);
+    if has_sys_init {
+        if let Err(trap) = vm.bootstrap() {
+            eprintln!("{trap}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    match vm.run(max_steps) {
+        Ok(Stop::Halted) => println!("halted after {} steps", vm.steps()),
+        Ok(Stop::EndOfProgram) => println!("ended after {} steps", vm.steps()),
+        Ok(Stop::StepLimit) => println!("stopped after {} steps", vm.steps()),
+        Err(trap) => {
+            eprintln!("{trap} after {} steps", vm.steps());
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let ram = vm.ram();
+    for (name, pointer) in [
+        ("SP", SP),
+        ("LCL", LCL),
+        ("ARG", ARG),
+        ("THIS", THIS),
+        ("THAT", THAT),
+    ] {
+        println!("{name:<4} = {}", ram[pointer as usize]);
+    }
+    let sp = (ram[SP as usize].max(0) as usize).min(ram.len());
+    let start = (STACK_BASE as usize).max(sp.saturating_sub(STACK_SHOWN));
+    for (addr, value) in ram.iter().enumerate().take(sp).skip(start) {
+        println!("RAM[{addr}] = {value}");
+    }
+
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,95 @@
+//! The built-in `Keyboard` class. Reading waits for the keyboard: the call
+//! is executed again on every step until the input is complete.
+
+use super::string::{BACKSPACE, NEW_LINE};
+use super::{Outcome, KBD};
+use crate::vm::{Trap, Vm};
+
+/// The longest line `readLine` reads.
+const LINE_LENGTH: i16 = 80;
+
+/// Input being read.
+#[derive(Debug, Default)]
+pub struct Input {
+    /// A key that was pressed and not released yet.
+    pressed: Option<i16>,
+    /// The string `readLine` reads into.
+    line: Option<i16>,
+}
+
+/// A character once its key was pressed and released.
+fn read_key(vm: &mut Vm) -> Option<i16> {
+    let key = vm.ram()[KBD as usize];
+    match vm.os.keyboard.pressed {
+        None if key != 0 => vm.os.keyboard.pressed = Some(key),
+        Some(c) if key == 0 => {
+            vm.os.keyboard.pressed = None;
+            return Some(c);
+        }
+        _ => {}
+    }
+    None
+}
+
+/// Reads a line, echoing it, until a new line is entered.
+fn read_line(vm: &mut Vm, message: i16) -> Result<Option<i16>, Trap> {
+    let line = match vm.os.keyboard.line {
+        Some(line) => line,
+        None => {
+            vm.invoke("Output.printString", &[message])?;
+            let line = vm.invoke("String.new", &[LINE_LENGTH])?;
+            vm.os.keyboard.line = Some(line);
+            line
+        }
+    };
+
+    let Some(c) = read_key(vm) else {
+        return Ok(None);
+    };
+    let length = vm.invoke("String.length", &[line])?;
+    match c {
+        NEW_LINE => {
+            vm.invoke("Output.println", &[])?;
+            vm.os.keyboard.line = None;
+            return Ok(Some(line));
+        }
+        BACKSPACE if length > 0 => {
+            vm.invoke("String.eraseLastChar", &[line])?;
+            vm.invoke("Output.backSpace", &[])?;
+        }
+        BACKSPACE => {}
+        _ if length < LINE_LENGTH => {
+            vm.invoke("String.appendChar", &[line, c])?;
+            vm.invoke("Output.printChar", &[c])?;
+        }
+        _ => {}
+    }
+    Ok(None)
+}
+
+pub(crate) fn call(vm: &mut Vm, name: &str, args: &[i16]) -> Result<Outcome, Trap> {
+    let value = match name {
+        "Keyboard.init" => 0,
+        "Keyboard.keyPressed" => vm.ram()[KBD as usize],
+        "Keyboard.readChar" => match read_key(vm) {
+            Some(c) => {
+                vm.invoke("Output.printChar", &[c])?;
+                c
+            }
+            None => return Ok(Outcome::Wait),
+        },
+        "Keyboard.readLine" => match read_line(vm, args[0])? {
+            Some(line) => line,
+            None => return Ok(Outcome::Wait),
+        },
+        _ => match read_line(vm, args[0])? {
+            Some(line) => {
+                let value = vm.invoke("String.intValue", &[line])?;
+                vm.invoke("String.dispose", &[line])?;
+                value
+            }
+            None => return Ok(Outcome::Wait),
+        },
+    };
+    Ok(Outcome::Return(value))
+}
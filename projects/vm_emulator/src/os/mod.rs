@@ -0,0 +1,412 @@
+//! The Jack OS, built into the VM like the VM emulator's built-in classes.
+//!
+//! A `call` to an OS function the program doesn't define runs the built-in
+//! one. Built-in functions use each other through the VM, so a program can
+//! bring its own version of some OS classes and rely on the built-in ones for
+//! the rest: the built-in `Output.printString` reads strings with the
+//! program's `String.charAt` if it has one.
+
+mod font;
+mod keyboard;
+mod output;
+mod screen;
+mod string;
+
+use crate::vm::{Trap, Vm};
+use std::collections::BTreeMap;
+
+pub const HEAP_BASE: u16 = 2048;
+pub const HEAP_END: u16 = 16384;
+pub const SCREEN: u16 = 16384;
+pub const KBD: u16 = 24576;
+
+/// What a built-in function did.
+pub(crate) enum Outcome {
+    Return(i16),
+    /// The function waits for the keyboard and must be called again.
+    Wait,
+    /// The function hands over to a function of the program, halting when it
+    /// returns: the built-in `Sys.init` runs `Main.main`.
+    Enter(&'static str),
+}
+
+/// The built-in functions and their number of arguments.
+const FUNCTIONS: [(&str, usize); 49] = [
+    ("Array.new", 1),
+    ("Array.dispose", 1),
+    ("Keyboard.init", 0),
+    ("Keyboard.keyPressed", 0),
+    ("Keyboard.readChar", 0),
+    ("Keyboard.readLine", 1),
+    ("Keyboard.readInt", 1),
+    ("Math.init", 0),
+    ("Math.abs", 1),
+    ("Math.multiply", 2),
+    ("Math.divide", 2),
+    ("Math.min", 2),
+    ("Math.max", 2),
+    ("Math.sqrt", 1),
+    ("Memory.init", 0),
+    ("Memory.peek", 1),
+    ("Memory.poke", 2),
+    ("Memory.alloc", 1),
+    ("Memory.deAlloc", 1),
+    ("Output.init", 0),
+    ("Output.moveCursor", 2),
+    ("Output.printChar", 1),
+    ("Output.printString", 1),
+    ("Output.printInt", 1),
+    ("Output.println", 0),
+    ("Output.backSpace", 0),
+    ("Screen.init", 0),
+    ("Screen.clearScreen", 0),
+    ("Screen.setColor", 1),
+    ("Screen.drawPixel", 2),
+    ("Screen.drawLine", 4),
+    ("Screen.drawRectangle", 4),
+    ("Screen.drawCircle", 3),
+    ("String.new", 1),
+    ("String.dispose", 1),
+    ("String.length", 1),
+    ("String.charAt", 2),
+    ("String.setCharAt", 3),
+    ("String.appendChar", 2),
+    ("String.eraseLastChar", 1),
+    ("String.intValue", 1),
+    ("String.setInt", 2),
+    ("String.newLine", 0),
+    ("String.backSpace", 0),
+    ("String.doubleQuote", 0),
+    ("Sys.init", 0),
+    ("Sys.halt", 0),
+    ("Sys.error", 1),
+    ("Sys.wait", 1),
+];
+
+pub fn is_built_in(name: &str) -> bool {
+    FUNCTIONS.iter().any(|&(function, _)| function == name)
+}
+
+/// An error of an OS function, with the code the Jack OS reports it with.
+pub(crate) fn error(function: &str, code: i16, message: &str) -> Trap {
+    Trap::Os(format!("{function}: {message} (error {code})"))
+}
+
+/// The state of the built-in classes.
+pub struct Os {
+    /// Free heap blocks by address, with their size.
+    free: BTreeMap<u16, u16>,
+    /// Allocated heap blocks by address, with their size.
+    allocated: BTreeMap<u16, u16>,
+    pub(crate) output: output::Cursor,
+    pub(crate) color: bool,
+    pub(crate) keyboard: keyboard::Input,
+}
+
+impl Default for Os {
+    fn default() -> Self {
+        Self {
+            free: BTreeMap::new(),
+            allocated: BTreeMap::new(),
+            output: output::Cursor::default(),
+            color: true,
+            keyboard: keyboard::Input::default(),
+        }
+        .with_empty_heap()
+    }
+}
+
+impl Os {
+    fn with_empty_heap(mut self) -> Self {
+        self.free = BTreeMap::from([(HEAP_BASE, HEAP_END - HEAP_BASE)]);
+        self.allocated.clear();
+        self
+    }
+
+    /// First fit allocation.
+    fn alloc(&mut self, size: u16) -> Option<u16> {
+        let (&addr, &free) = self.free.iter().find(|(_, &free)| free >= size)?;
+        self.free.remove(&addr);
+        if free > size {
+            self.free.insert(addr + size, free - size);
+        }
+        self.allocated.insert(addr, size);
+        Some(addr)
+    }
+
+    /// Frees a block, merging it with the free blocks around it. Addresses
+    /// that weren't allocated are ignored.
+    fn de_alloc(&mut self, addr: u16) {
+        let Some(size) = self.allocated.remove(&addr) else {
+            return;
+        };
+        let (mut start, mut end) = (addr, addr + size);
+        if let Some((&before, &free)) = self.free.range(..addr).next_back() {
+            if before + free == addr {
+                self.free.remove(&before);
+                start = before;
+            }
+        }
+        if let Some(free) = self.free.remove(&end) {
+            end += free;
+        }
+        self.free.insert(start, end - start);
+    }
+}
+
+/// Runs a built-in function.
+pub(crate) fn call(vm: &mut Vm, name: &str, args: &[i16]) -> Result<Outcome, Trap> {
+    let Some(&(_, arity)) = FUNCTIONS.iter().find(|&&(function, _)| function == name) else {
+        return Err(Trap::UndefinedFunction(name.to_string()));
+    };
+    if args.len() != arity {
+        return Err(Trap::Os(format!(
+            "{name} expects {arity} argument{}, found {}",
+            if arity == 1 { "" } else { "s" },
+            args.len()
+        )));
+    }
+
+    let value = match name {
+        "Array.new" => {
+            if args[0] <= 0 {
+                return Err(error(name, 2, "array size must be positive"));
+            }
+            vm.invoke("Memory.alloc", args)?
+        }
+        "Array.dispose" => vm.invoke("Memory.deAlloc", args)?,
+
+        "Math.init" => 0,
+        "Math.abs" => args[0].wrapping_abs(),
+        "Math.multiply" => args[0].wrapping_mul(args[1]),
+        "Math.divide" => {
+            if args[1] == 0 {
+                return Err(error(name, 3, "division by zero"));
+            }
+            args[0].wrapping_div(args[1])
+        }
+        "Math.min" => args[0].min(args[1]),
+        "Math.max" => args[0].max(args[1]),
+        "Math.sqrt" => {
+            if args[0] < 0 {
+                return Err(error(
+                    name,
+                    4,
+                    "cannot compute square root of a negative number",
+                ));
+            }
+            (args[0] as f64).sqrt() as i16
+        }
+
+        "Memory.init" => {
+            let os = std::mem::take(&mut vm.os);
+            vm.os = os.with_empty_heap();
+            0
+        }
+        "Memory.peek" => vm.read(args[0] as u16)?,
+        "Memory.poke" => {
+            vm.write(args[0] as u16, args[1])?;
+            0
+        }
+        "Memory.alloc" => {
+            if args[0] <= 0 {
+                return Err(error(name, 5, "allocated memory size must be positive"));
+            }
+            vm.os
+                .alloc(args[0] as u16)
+                .ok_or_else(|| error(name, 6, "heap overflow"))? as i16
+        }
+        "Memory.deAlloc" => {
+            vm.os.de_alloc(args[0] as u16);
+            0
+        }
+
+        "Sys.init" => {
+            for init in [
+                "Memory.init",
+                "Math.init",
+                "Screen.init",
+                "Output.init",
+                "Keyboard.init",
+            ] {
+                vm.invoke(init, &[])?;
+            }
+            return Ok(Outcome::Enter("Main.main"));
+        }
+        "Sys.halt" => {
+            vm.halt();
+            0
+        }
+        "Sys.error" => return Err(Trap::Os(format!("Sys.error: error {}", args[0]))),
+        // the VM doesn't keep track of time
+        "Sys.wait" => {
+            if args[0] < 0 {
+                return Err(error(name, 1, "duration must be positive"));
+            }
+            0
+        }
+
+        _ if name.starts_with("String.") => string::call(vm, name, args)?,
+        _ if name.starts_with("Output.") => output::call(vm, name, args)?,
+        _ if name.starts_with("Screen.") => screen::call(vm, name, args)?,
+        _ => return keyboard::call(vm, name, args),
+    };
+    Ok(Outcome::Return(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::Stop;
+    use crate::Program;
+
+    fn run(sources: &[(&str, &str)]) -> Vm {
+        let program = Program::from_sources(sources.iter().copied()).unwrap();
+        let mut vm = Vm::new(program);
+        assert_eq!(vm.run(100_000), Ok(Stop::Halted));
+        vm
+    }
+
+    #[test]
+    fn built_in_sys_init_runs_main() {
+        let vm = run(&[(
+            "Main",
+            "function Main.main 0\n\
+             push constant 7\npush constant 6\ncall Math.multiply 2\npop temp 0\n\
+             push constant 50\nneg\npush constant 7\ncall Math.divide 2\npop temp 1\n\
+             push constant 1000\ncall Math.sqrt 1\npop temp 2\n\
+             push constant 0\nreturn\n",
+        )]);
+        assert_eq!(vm.ram()[5..8], [42, -7, 31]);
+        // the built-in Sys.init took one step
+        assert_eq!(vm.steps(), 1 + 15);
+        assert_eq!(vm.current_function(), None);
+    }
+
+    #[test]
+    fn allocates_and_merges_blocks() {
+        let mut os = Os::default();
+        let a = os.alloc(10).unwrap();
+        let b = os.alloc(20).unwrap();
+        let c = os.alloc(30).unwrap();
+        assert_eq!([a, b, c], [HEAP_BASE, HEAP_BASE + 10, HEAP_BASE + 30]);
+
+        os.de_alloc(a);
+        os.de_alloc(b);
+        assert_eq!(os.alloc(25), Some(HEAP_BASE));
+        os.de_alloc(c);
+        os.de_alloc(HEAP_BASE);
+        assert_eq!(os.free, BTreeMap::from([(HEAP_BASE, HEAP_END - HEAP_BASE)]));
+        assert_eq!(os.alloc(HEAP_END - HEAP_BASE + 1), None);
+    }
+
+    #[test]
+    fn uses_the_programs_os_classes() {
+        // Array.new is built in, Memory.alloc isn't
+        let vm = run(&[
+            (
+                "Main",
+                "function Main.main 0\npush constant 3\ncall Array.new 1\npop temp 0\n\
+                 push constant 0\nreturn\n",
+            ),
+            (
+                "Memory",
+                "function Memory.alloc 0\npush argument 0\npop static 0\n\
+                 push constant 5000\nreturn\n",
+            ),
+        ]);
+        assert_eq!(vm.ram()[5], 5000);
+        assert_eq!(vm.ram()[16], 3);
+    }
+
+    #[test]
+    fn prints_strings() {
+        let vm = run(&[(
+            "Main",
+            "function Main.main 1\n\
+             push constant 6\ncall String.new 1\npop local 0\n\
+             push local 0\npush constant 72\ncall String.appendChar 2\n\
+             push constant 105\ncall String.appendChar 2\ncall Output.printString 1\npop temp 0\n\
+             push local 0\npush constant 1234\nneg\ncall String.setInt 2\npop temp 0\n\
+             push local 0\ncall String.intValue 1\npop temp 1\n\
+             push local 0\ncall String.length 1\npop temp 2\n\
+             push constant 0\nreturn\n",
+        )]);
+        assert_eq!(vm.ram()[6..8], [-1234, 5]);
+        assert_eq!(vm.os.output, output::Cursor { row: 0, column: 2 });
+
+        // "H" then "i" in the first word's two bytes, below a blank line
+        let screen = SCREEN as usize;
+        assert_eq!(vm.ram()[screen], 0);
+        assert_eq!(vm.ram()[screen + 32], 51 | 12 << 8);
+        assert_eq!(vm.ram()[screen + 4 * 32], 51 | 14 << 8);
+    }
+
+    #[test]
+    fn draws_shapes() {
+        let vm = run(&[(
+            "Main",
+            "function Main.main 0\n\
+             push constant 0\npush constant 0\npush constant 17\npush constant 1\n\
+             call Screen.drawRectangle 4\npop temp 0\n\
+             push constant 0\ncall Screen.setColor 1\npop temp 0\n\
+             push constant 1\npush constant 1\npush constant 4\npush constant 1\n\
+             call Screen.drawLine 4\npop temp 0\n\
+             push constant 0\nreturn\n",
+        )]);
+        let screen = SCREEN as usize;
+        assert_eq!(vm.ram()[screen..screen + 3], [-1, 0b11, 0]);
+        assert_eq!(vm.ram()[screen + 32..screen + 35], [!0b11110, 0b11, 0]);
+
+        let program = Program::from_sources([(
+            "Main",
+            "function Main.main 0\n\
+             push constant 10\npush constant 10\npush constant 11\n\
+             call Screen.drawCircle 3\nreturn\n",
+        )])
+        .unwrap();
+        assert_eq!(
+            Vm::new(program).run(100),
+            Err(Trap::Os(
+                "Screen.drawCircle: illegal radius (error 13)".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn waits_for_the_keyboard() {
+        let program = Program::from_sources([(
+            "Main",
+            "function Main.main 0\npush constant 1\ncall String.new 1\n\
+             call Keyboard.readInt 1\npop temp 0\npush constant 0\nreturn\n",
+        )])
+        .unwrap();
+        let mut vm = Vm::new(program);
+        assert_eq!(vm.run(100), Ok(Stop::StepLimit));
+
+        for key in ['4', '2', '\u{80}'] {
+            vm.ram_mut()[KBD as usize] = key as i16;
+            vm.step().unwrap();
+            vm.ram_mut()[KBD as usize] = 0;
+            vm.step().unwrap();
+        }
+        assert_eq!(vm.run(100), Ok(Stop::Halted));
+        assert_eq!(vm.ram()[5], 42);
+        assert_eq!(vm.os.output, output::Cursor { row: 1, column: 0 });
+    }
+
+    #[test]
+    fn reports_os_errors() {
+        let program = Program::from_sources([(
+            "Main",
+            "function Main.main 0\npush constant 1\npush constant 0\ncall Math.divide 2\n\
+             return\n",
+        )])
+        .unwrap();
+        let mut vm = Vm::new(program);
+        assert_eq!(
+            vm.run(100),
+            Err(Trap::Os("Math.divide: division by zero (error 3)".into()))
+        );
+    }
+}
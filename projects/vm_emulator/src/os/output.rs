@@ -0,0 +1,103 @@
+//! The built-in `Output` class: 23 rows of 64 characters, each drawn in an
+//! 8x11 pixel frame.
+
+use super::font::{BLACK_SQUARE, GLYPHS};
+use super::string::{BACKSPACE, NEW_LINE};
+use super::{error, SCREEN};
+use crate::vm::{Trap, Vm};
+
+pub const ROWS: i16 = 23;
+pub const COLUMNS: i16 = 64;
+const CHAR_HEIGHT: i16 = 11;
+const WORDS_PER_ROW: i16 = 32;
+
+/// Where the next character goes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cursor {
+    pub row: i16,
+    pub column: i16,
+}
+
+/// Draws a character at the cursor, without moving it.
+fn draw(vm: &mut Vm, c: i16) -> Result<(), Trap> {
+    let glyph = match c {
+        32..=126 => &GLYPHS[c as usize - 32],
+        _ => &BLACK_SQUARE,
+    };
+    let Cursor { row, column } = vm.os.output;
+    // two characters share a word, the left one in the low byte
+    let shift = if column % 2 == 0 { 0 } else { 8 };
+
+    // like the reference OS, leave the top line of the frame blank: the font's
+    // last line is always blank
+    let lines = [0].iter().chain(&glyph[..CHAR_HEIGHT as usize - 1]);
+    for (line, &bits) in lines.enumerate() {
+        let y = row * CHAR_HEIGHT + line as i16;
+        let addr = SCREEN + (y * WORDS_PER_ROW + column / 2) as u16;
+        let word = vm.read(addr)? as u16 & !(0xFF << shift) | (bits as u16) << shift;
+        vm.write(addr, word as i16)?;
+    }
+    Ok(())
+}
+
+fn println(vm: &mut Vm) {
+    let cursor = &mut vm.os.output;
+    cursor.column = 0;
+    cursor.row = (cursor.row + 1) % ROWS;
+}
+
+fn back_space(vm: &mut Vm) -> Result<(), Trap> {
+    let cursor = &mut vm.os.output;
+    if cursor.column > 0 {
+        cursor.column -= 1;
+    } else if cursor.row > 0 {
+        cursor.row -= 1;
+        cursor.column = COLUMNS - 1;
+    }
+    draw(vm, b' ' as i16)
+}
+
+fn print_char(vm: &mut Vm, c: i16) -> Result<(), Trap> {
+    match c {
+        NEW_LINE => println(vm),
+        BACKSPACE => back_space(vm)?,
+        _ => {
+            draw(vm, c)?;
+            vm.os.output.column += 1;
+            if vm.os.output.column == COLUMNS {
+                println(vm);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn call(vm: &mut Vm, name: &str, args: &[i16]) -> Result<i16, Trap> {
+    match name {
+        "Output.init" => vm.os.output = Cursor::default(),
+        "Output.moveCursor" => {
+            let (row, column) = (args[0], args[1]);
+            if !(0..ROWS).contains(&row) || !(0..COLUMNS).contains(&column) {
+                return Err(error(name, 20, "illegal cursor location"));
+            }
+            vm.os.output = Cursor { row, column };
+            draw(vm, b' ' as i16)?;
+        }
+        "Output.printChar" => print_char(vm, args[0])?,
+        "Output.printString" => {
+            let string = args[0];
+            for index in 0..vm.invoke("String.length", &[string])? {
+                let c = vm.invoke("String.charAt", &[string, index])?;
+                print_char(vm, c)?;
+            }
+        }
+        "Output.printInt" => {
+            for c in args[0].to_string().bytes() {
+                print_char(vm, c as i16)?;
+            }
+        }
+        "Output.println" => println(vm),
+        _ => back_space(vm)?,
+    }
+    Ok(0)
+}
@@ -0,0 +1,129 @@
+//! The built-in `Screen` class.
+
+use super::{error, SCREEN};
+use crate::vm::{Trap, Vm};
+
+pub const WIDTH: i16 = 512;
+pub const HEIGHT: i16 = 256;
+const WORDS_PER_ROW: i16 = WIDTH / 16;
+
+fn on_screen(x: i16, y: i16) -> bool {
+    (0..WIDTH).contains(&x) && (0..HEIGHT).contains(&y)
+}
+
+/// Sets a pixel to the current color, the least significant bit of a word
+/// being its leftmost pixel.
+fn draw_pixel(vm: &mut Vm, x: i16, y: i16) -> Result<(), Trap> {
+    let addr = SCREEN + (y * WORDS_PER_ROW + x / 16) as u16;
+    let bit = 1 << (x % 16);
+    let word = vm.read(addr)? as u16;
+    let word = if vm.os.color { word | bit } else { word & !bit };
+    vm.write(addr, word as i16)
+}
+
+/// Draws the part of a horizontal line that is on the screen.
+fn draw_row(vm: &mut Vm, x1: i16, x2: i16, y: i16) -> Result<(), Trap> {
+    if !(0..HEIGHT).contains(&y) {
+        return Ok(());
+    }
+    for x in x1.max(0)..=x2.min(WIDTH - 1) {
+        draw_pixel(vm, x, y)?;
+    }
+    Ok(())
+}
+
+/// Bresenham's line algorithm, stepping along the longer axis and choosing
+/// the same pixels as the reference OS.
+fn draw_line(vm: &mut Vm, from: (i16, i16), to: (i16, i16)) -> Result<(), Trap> {
+    let steep = (to.0 - from.0).abs() < (to.1 - from.1).abs();
+    // work with (major, minor) coordinates, major growing along the line
+    let flip = |(x, y): (i16, i16)| if steep { (y, x) } else { (x, y) };
+    let (mut from, mut to) = (flip(from), flip(to));
+    if to.0 < from.0 {
+        (from, to) = (to, from);
+    }
+
+    let (d_major, d_minor) = (to.0 - from.0, (to.1 - from.1).abs());
+    let minor_step = if to.1 < from.1 { -1 } else { 1 };
+    let (mut major, mut minor) = from;
+    let mut err = 2 * d_minor - d_major;
+    loop {
+        let (x, y) = flip((major, minor));
+        draw_pixel(vm, x, y)?;
+        if major >= to.0 {
+            return Ok(());
+        }
+        if err < 0 {
+            err += 2 * d_minor;
+        } else {
+            err += 2 * (d_minor - d_major);
+            minor += minor_step;
+        }
+        major += 1;
+    }
+}
+
+/// The midpoint circle algorithm, filling the circle with horizontal lines
+/// like the reference OS does.
+fn draw_circle(vm: &mut Vm, x: i16, y: i16, r: i16) -> Result<(), Trap> {
+    let (mut a, mut b, mut d) = (0, r, 1 - r);
+    loop {
+        draw_row(vm, x - a, x + a, y - b)?;
+        draw_row(vm, x - a, x + a, y + b)?;
+        draw_row(vm, x - b, x + b, y - a)?;
+        draw_row(vm, x - b, x + b, y + a)?;
+        if b <= a {
+            return Ok(());
+        }
+        if d < 0 {
+            d += 2 * a + 3;
+        } else {
+            d += 2 * (a - b) + 5;
+            b -= 1;
+        }
+        a += 1;
+    }
+}
+
+pub(crate) fn call(vm: &mut Vm, name: &str, args: &[i16]) -> Result<i16, Trap> {
+    match name {
+        "Screen.init" => vm.os.color = true,
+        "Screen.clearScreen" => {
+            let screen = SCREEN as usize;
+            vm.ram_mut()[screen..screen + (WORDS_PER_ROW * HEIGHT) as usize].fill(0);
+        }
+        "Screen.setColor" => vm.os.color = args[0] != 0,
+        "Screen.drawPixel" => {
+            if !on_screen(args[0], args[1]) {
+                return Err(error(name, 7, "illegal pixel coordinates"));
+            }
+            draw_pixel(vm, args[0], args[1])?;
+        }
+        "Screen.drawLine" => {
+            if !on_screen(args[0], args[1]) || !on_screen(args[2], args[3]) {
+                return Err(error(name, 8, "illegal line coordinates"));
+            }
+            draw_line(vm, (args[0], args[1]), (args[2], args[3]))?;
+        }
+        "Screen.drawRectangle" => {
+            let [x1, y1, x2, y2] = [args[0], args[1], args[2], args[3]];
+            if x1 > x2 || y1 > y2 || !on_screen(x1, y1) || !on_screen(x2, y2) {
+                return Err(error(name, 9, "illegal rectangle coordinates"));
+            }
+            for y in y1..=y2 {
+                draw_row(vm, x1, x2, y)?;
+            }
+        }
+        _ => {
+            let [x, y, r] = [args[0], args[1], args[2]];
+            if !on_screen(x, y) {
+                return Err(error(name, 12, "illegal center coordinates"));
+            }
+            if r < 0 || !on_screen(x - r, y - r) || !on_screen(x + r, y + r) {
+                return Err(error(name, 13, "illegal radius"));
+            }
+            draw_circle(vm, x, y, r)?;
+        }
+    }
+    Ok(0)
+}
@@ -0,0 +1,106 @@
+//! The built-in `String` class. A string is a heap block holding its maximum
+//! length, its length, then its characters.
+
+use super::error;
+use crate::vm::{Trap, Vm};
+
+const MAX_LENGTH: i16 = 0;
+const LENGTH: i16 = 1;
+const CHARS: i16 = 2;
+
+pub const NEW_LINE: i16 = 128;
+pub const BACKSPACE: i16 = 129;
+pub const DOUBLE_QUOTE: i16 = 34;
+
+fn field(vm: &Vm, this: i16, offset: i16) -> Result<i16, Trap> {
+    vm.read(this.wrapping_add(offset) as u16)
+}
+
+fn set_field(vm: &mut Vm, this: i16, offset: i16, value: i16) -> Result<(), Trap> {
+    vm.write(this.wrapping_add(offset) as u16, value)
+}
+
+pub(crate) fn call(vm: &mut Vm, name: &str, args: &[i16]) -> Result<i16, Trap> {
+    let this = args.first().copied().unwrap_or(0);
+    let in_bounds = |index: i16, length: i16| (0..length).contains(&index);
+
+    Ok(match name {
+        "String.new" => {
+            let max_length = args[0];
+            if max_length < 0 {
+                return Err(error(name, 14, "maximum length must be non-negative"));
+            }
+            let this = vm.invoke("Memory.alloc", &[max_length.saturating_add(CHARS)])?;
+            set_field(vm, this, MAX_LENGTH, max_length)?;
+            set_field(vm, this, LENGTH, 0)?;
+            this
+        }
+        "String.dispose" => vm.invoke("Memory.deAlloc", &[this])?,
+        "String.length" => field(vm, this, LENGTH)?,
+        "String.charAt" => {
+            if !in_bounds(args[1], field(vm, this, LENGTH)?) {
+                return Err(error(name, 15, "string index out of bounds"));
+            }
+            field(vm, this, CHARS + args[1])?
+        }
+        "String.setCharAt" => {
+            if !in_bounds(args[1], field(vm, this, LENGTH)?) {
+                return Err(error(name, 16, "string index out of bounds"));
+            }
+            set_field(vm, this, CHARS + args[1], args[2])?;
+            0
+        }
+        "String.appendChar" => {
+            let length = field(vm, this, LENGTH)?;
+            if length >= field(vm, this, MAX_LENGTH)? {
+                return Err(error(name, 17, "string is full"));
+            }
+            set_field(vm, this, CHARS + length, args[1])?;
+            set_field(vm, this, LENGTH, length + 1)?;
+            this
+        }
+        "String.eraseLastChar" => {
+            let length = field(vm, this, LENGTH)?;
+            if length == 0 {
+                return Err(error(name, 18, "string is empty"));
+            }
+            set_field(vm, this, LENGTH, length - 1)?;
+            0
+        }
+        "String.intValue" => {
+            let mut chars = Vec::new();
+            for index in 0..field(vm, this, LENGTH)? {
+                chars.push(field(vm, this, CHARS + index)?);
+            }
+            let (sign, digits) = match chars.split_first() {
+                Some((&c, rest)) if c == b'-' as i16 => (-1, rest),
+                _ => (1, &chars[..]),
+            };
+            digits
+                .iter()
+                .map_while(|&c| {
+                    (b'0' as i16..=b'9' as i16)
+                        .contains(&c)
+                        .then(|| c - b'0' as i16)
+                })
+                .fold(0i16, |value, digit| {
+                    value.wrapping_mul(10).wrapping_add(digit)
+                })
+                .wrapping_mul(sign)
+        }
+        "String.setInt" => {
+            let digits = args[1].to_string();
+            if digits.len() as i16 > field(vm, this, MAX_LENGTH)? {
+                return Err(error(name, 19, "insufficient string capacity"));
+            }
+            for (index, c) in digits.bytes().enumerate() {
+                set_field(vm, this, CHARS + index as i16, c as i16)?;
+            }
+            set_field(vm, this, LENGTH, digits.len() as i16)?;
+            0
+        }
+        "String.newLine" => NEW_LINE,
+        "String.backSpace" => BACKSPACE,
+        _ => DOUBLE_QUOTE,
+    })
+}
@@ -0,0 +1,292 @@
+//! A VM program: the commands of one or more `.vm` files, linked so that they
+//! can be run without translating them to assembly.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use vm_translator::{Inst, Parser, SegmentAddr};
+
+// the translator's, so that both run the same files of a directory
+pub use vm_translator::vm_files;
+
+/// First RAM address of the static segments.
+pub const STATIC_BASE: u16 = 16;
+/// RAM addresses available to the static segments of all files.
+pub const STATIC_SIZE: u16 = 240;
+
+pub struct Program {
+    /// The name of each file, without its `.vm` extension, which is also the
+    /// class its functions belong to.
+    pub files: Vec<String>,
+    pub insts: Vec<Inst>,
+    /// The file each command comes from.
+    file_of: Vec<usize>,
+    /// The function each command belongs to, if any.
+    function_of: Vec<Option<usize>>,
+    function_names: Vec<String>,
+    functions: HashMap<String, usize>,
+    /// Where each `goto` and `if-goto` jumps to.
+    jumps: Vec<Option<usize>>,
+    /// RAM address of each file's `static 0`.
+    static_bases: Vec<u16>,
+}
+
+impl Program {
+    /// Loads a `.vm` file, or every `.vm` file of a directory.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let files = if path.is_dir() {
+            vm_files(path)?
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        let mut sources = Vec::new();
+        for file in &files {
+            let source = fs::read_to_string(file)
+                .map_err(|err| format!("Could not read {}: {err}", file.display()))?;
+            let name = file
+                .file_stem()
+                .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+            sources.push((name, source));
+        }
+        Self::from_sources(
+            sources
+                .iter()
+                .map(|(name, source)| (name.as_str(), source.as_str())),
+        )
+    }
+
+    /// Parses, validates and links files given as `(name, source)` pairs.
+    pub fn from_sources<'a>(
+        sources: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<Self, String> {
+        let mut program = Program {
+            files: Vec::new(),
+            insts: Vec::new(),
+            file_of: Vec::new(),
+            function_of: Vec::new(),
+            function_names: Vec::new(),
+            functions: HashMap::new(),
+            jumps: Vec::new(),
+            static_bases: Vec::new(),
+        };
+        let mut next_static = STATIC_BASE;
+
+        for (name, source) in sources {
+            let file_name = format!("{name}.vm");
+            let mut parser = Parser::new(Path::new(&file_name));
+            // the translator rejects the same programs
+            let parsed = parser.parse_source(source).and_then(|()| parser.validate());
+            parsed.map_err(|errors| {
+                errors
+                    .iter()
+                    .map(|err| format!("{file_name}:{err}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })?;
+
+            let file = program.files.len();
+            program.files.push(name.to_string());
+            program.static_bases.push(next_static);
+            let statics = parser
+                .tokens
+                .iter()
+                .filter_map(|inst| match inst {
+                    Inst::Push(segment) | Inst::Pop(segment) => match segment {
+                        SegmentAddr::Static(index) => Some(index + 1),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(0);
+            next_static = next_static.saturating_add(statics);
+            if next_static > STATIC_BASE + STATIC_SIZE {
+                return Err(format!(
+                    "{file_name}: the static variables don't fit in RAM[{STATIC_BASE}..{}]",
+                    STATIC_BASE + STATIC_SIZE
+                ));
+            }
+
+            let mut function = None;
+            for inst in parser.tokens {
+                if let Inst::Function(name, _) = &inst {
+                    if program.functions.contains_key(name) {
+                        return Err(format!("{file_name}: function {name} is defined twice"));
+                    }
+                    program.functions.insert(name.clone(), program.insts.len());
+                    function = Some(program.function_names.len());
+                    program.function_names.push(name.clone());
+                }
+                program.insts.push(inst);
+                program.file_of.push(file);
+                program.function_of.push(function);
+            }
+        }
+
+        program.link()?;
+        Ok(program)
+    }
+
+    /// Resolves the labels of jumps, which are local to the function they're
+    /// in.
+    fn link(&mut self) -> Result<(), String> {
+        // return addresses are pushed on the stack, with a couple of values
+        // reserved for the VM
+        if self.insts.len() >= 0xFFFE {
+            return Err(format!(
+                "the program has {} commands, too many to call functions",
+                self.insts.len()
+            ));
+        }
+
+        let mut labels = HashMap::new();
+        for (at, inst) in self.insts.iter().enumerate() {
+            if let Inst::Label(label) = inst {
+                if labels.insert((self.function_of[at], label), at).is_some() {
+                    return Err(format!(
+                        "{}: label {label} is defined twice in {}",
+                        self.file_name(at),
+                        self.scope_name(at)
+                    ));
+                }
+            }
+        }
+
+        let mut jumps = Vec::with_capacity(self.insts.len());
+        for (at, inst) in self.insts.iter().enumerate() {
+            jumps.push(match inst {
+                Inst::Goto(label) | Inst::IfGoto(label) => {
+                    match labels.get(&(self.function_of[at], label)) {
+                        Some(&target) => Some(target),
+                        None => {
+                            return Err(format!(
+                                "{}: label {label} is not defined in {}",
+                                self.file_name(at),
+                                self.scope_name(at)
+                            ))
+                        }
+                    }
+                }
+                _ => None,
+            });
+        }
+        self.jumps = jumps;
+        Ok(())
+    }
+
+    fn file_name(&self, at: usize) -> String {
+        format!("{}.vm", self.files[self.file_of[at]])
+    }
+
+    fn scope_name(&self, at: usize) -> String {
+        match self.function_at(at) {
+            Some(function) => format!("function {function}"),
+            None => "the code outside of functions".to_string(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.insts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.insts.is_empty()
+    }
+
+    /// Index of the `function` command of a function.
+    pub fn function(&self, name: &str) -> Option<usize> {
+        self.functions.get(name).copied()
+    }
+
+    /// The function the command at `at` belongs to.
+    pub fn function_at(&self, at: usize) -> Option<&str> {
+        let function = (*self.function_of.get(at)?)?;
+        Some(&self.function_names[function])
+    }
+
+    /// The file the command at `at` comes from.
+    pub fn file_at(&self, at: usize) -> Option<&str> {
+        Some(&self.files[*self.file_of.get(at)?])
+    }
+
+    /// Where the `goto` or `if-goto` at `at` jumps to.
+    pub fn jump_target(&self, at: usize) -> Option<usize> {
+        *self.jumps.get(at)?
+    }
+
+    /// RAM address of `static index` in the file the command at `at` comes
+    /// from.
+    pub fn static_address(&self, at: usize, index: u16) -> Option<u16> {
+        let file = *self.file_of.get(at)?;
+        Some(self.static_bases[file] + index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_files() {
+        let program = Program::from_sources([
+            (
+                "Main",
+                "function Main.main 0\npush static 2\nlabel LOOP\ngoto LOOP\n\
+                 function Main.other 0\nlabel LOOP\nif-goto LOOP\n",
+            ),
+            ("Sys", "function Sys.init 0\npop static 0\n"),
+        ])
+        .unwrap();
+
+        assert_eq!(program.function("Sys.init"), Some(7));
+        assert_eq!(program.function_at(6), Some("Main.other"));
+        assert_eq!(program.file_at(8), Some("Sys"));
+        // labels are local to their function
+        assert_eq!(program.jump_target(3), Some(2));
+        assert_eq!(program.jump_target(6), Some(5));
+        // Main uses three statics, so Sys's come after them
+        assert_eq!(program.static_address(1, 2), Some(18));
+        assert_eq!(program.static_address(8, 0), Some(19));
+    }
+
+    #[test]
+    fn rejects_bad_labels() {
+        let undefined = Program::from_sources([(
+            "Main",
+            "function Main.a 0\nlabel L\nfunction Main.b 0\ngoto L\n",
+        )]);
+        assert_eq!(
+            undefined.err().unwrap(),
+            "Main.vm: label L is not defined in function Main.b"
+        );
+
+        let twice = Program::from_sources([("Main", "label L\nlabel L\n")]);
+        assert_eq!(
+            twice.err().unwrap(),
+            "Main.vm: label L is defined twice in the code outside of functions"
+        );
+
+        let unparsed = Program::from_sources([("Main", "push local\nlabel\n")]);
+        assert_eq!(
+            unparsed.err().unwrap(),
+            "Main.vm:1:1: `push` expects 2 arguments, found 1\n\
+             Main.vm:2:1: `label` expects 1 argument, found 0"
+        );
+    }
+
+    #[test]
+    fn validates_segments() {
+        let invalid = Program::from_sources([(
+            "Main",
+            "push constant 40000
+push pointer 2
+",
+        )]);
+        assert_eq!(
+            invalid.err().unwrap(),
+            "Main.vm:1:1: constant 40000 doesn't fit in 15 bits, the largest is 32767\n\
+             Main.vm:2:1: pointer 2 is out of range, pointer only has entries 0 and 1"
+        );
+    }
+}
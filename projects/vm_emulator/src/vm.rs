@@ -0,0 +1,516 @@
+use crate::os::{self, Os, Outcome};
+use crate::program::Program;
+use std::fmt;
+use vm_translator::{Inst, SegmentAddr};
+
+pub const RAM_SIZE: usize = 32768;
+
+pub const SP: u16 = 0;
+pub const LCL: u16 = 1;
+pub const ARG: u16 = 2;
+pub const THIS: u16 = 3;
+pub const THAT: u16 = 4;
+pub const TEMP: u16 = 5;
+pub const TEMP_SIZE: u16 = 8;
+pub const STACK_BASE: u16 = 256;
+
+/// Words a call saves on the stack: the return address, LCL, ARG, THIS and
+/// THAT.
+const FRAME_SIZE: i16 = 5;
+
+/// Return address of a function called by the built-in `Sys.init`: like
+/// `Sys.halt`, returning to it halts the VM.
+pub const HALT: usize = 0xFFFF;
+/// Return address of a function called by another built-in function.
+const RETURN_TO_BUILT_IN: usize = 0xFFFE;
+
+/// Reasons the VM can't execute a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trap {
+    /// The program ran past its last command.
+    EndOfProgram,
+    UndefinedFunction(String),
+    /// A segment index out of the segment's range, such as `pointer 2`.
+    InvalidSegment(String),
+    /// A segment access or stack operation outside of the RAM.
+    InvalidAddress(i32),
+    /// An error reported by a built-in OS function, or by `Sys.error`.
+    Os(String),
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trap::EndOfProgram => write!(f, "the program ran past its last command"),
+            Trap::UndefinedFunction(name) => write!(f, "function {name} is not defined"),
+            Trap::InvalidSegment(msg) => write!(f, "{msg}"),
+            Trap::InvalidAddress(addr) => write!(f, "RAM[{addr}] is out of range"),
+            Trap::Os(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+/// Why [`Vm::run`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The program reached a `goto` jumping to the label right before it, or
+    /// called `Sys.halt`.
+    Halted,
+    /// The program ran up to its last command, as programs without functions
+    /// do.
+    EndOfProgram,
+    StepLimit,
+}
+
+/// The VM: a program, the RAM holding the stack and the segments, and the
+/// index of the next command to execute.
+pub struct Vm {
+    program: Program,
+    ram: Vec<i16>,
+    pub pc: usize,
+    steps: u64,
+    pub(crate) os: Os,
+    halted: bool,
+    /// Whether the first step calls the built-in `Sys.init`.
+    boot: bool,
+    /// Functions of the program currently running for a built-in function.
+    depth: usize,
+}
+
+impl Vm {
+    /// Starts at `Sys.init` if the program defines it, like the VM emulator
+    /// does, without calling it: the stack pointer starts at 256 and the
+    /// other segments are left to the caller. A Jack program relying on the
+    /// built-in `Sys.init` starts with a call to it instead.
+    pub fn new(program: Program) -> Self {
+        let sys_init = program.function("Sys.init");
+        let mut vm = Self {
+            pc: sys_init.unwrap_or(0),
+            boot: sys_init.is_none() && program.function("Main.main").is_some(),
+            program,
+            ram: vec![0; RAM_SIZE],
+            steps: 0,
+            os: Os::default(),
+            halted: false,
+            depth: 0,
+        };
+        vm.ram[SP as usize] = STACK_BASE as i16;
+        vm.pc = vm.past_labels(vm.pc);
+        vm
+    }
+
+    /// Sets the stack pointer to 256 and calls `Sys.init` the way the VM
+    /// translator's bootstrap code does.
+    pub fn bootstrap(&mut self) -> Result<(), Trap> {
+        self.write(SP, STACK_BASE as i16)?;
+        self.boot = false;
+        self.pc = self.call_function("Sys.init", 0, HALT)?;
+        Ok(())
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn ram(&self) -> &[i16] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [i16] {
+        &mut self.ram
+    }
+
+    /// Number of commands executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// The function of the next command.
+    pub fn current_function(&self) -> Option<&str> {
+        self.program.function_at(self.pc)
+    }
+
+    fn address(addr: i32) -> Result<usize, Trap> {
+        if (0..RAM_SIZE as i32).contains(&addr) {
+            Ok(addr as usize)
+        } else {
+            Err(Trap::InvalidAddress(addr))
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> Result<i16, Trap> {
+        Ok(self.ram[Self::address(addr as i32)?])
+    }
+
+    pub fn write(&mut self, addr: u16, value: i16) -> Result<(), Trap> {
+        self.ram[Self::address(addr as i32)?] = value;
+        Ok(())
+    }
+
+    fn push(&mut self, value: i16) -> Result<(), Trap> {
+        let sp = self.ram[SP as usize];
+        self.ram[Self::address(sp as i32)?] = value;
+        self.ram[SP as usize] = sp.wrapping_add(1);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<i16, Trap> {
+        let sp = self.ram[SP as usize].wrapping_sub(1);
+        let value = self.ram[Self::address(sp as i32)?];
+        self.ram[SP as usize] = sp;
+        Ok(value)
+    }
+
+    /// The RAM address of a segment entry. Constants have none.
+    fn segment_address(&self, segment: &SegmentAddr) -> Result<usize, Trap> {
+        let base = |pointer: u16| self.ram[pointer as usize] as i32;
+        let addr = match *segment {
+            SegmentAddr::Constant(_) => {
+                return Err(Trap::InvalidSegment(
+                    "the constant segment can't be written".into(),
+                ))
+            }
+            SegmentAddr::Static(index) => self
+                .program
+                .static_address(self.pc, index)
+                .ok_or(Trap::EndOfProgram)? as i32,
+            SegmentAddr::Temp(index) if index < TEMP_SIZE => (TEMP + index) as i32,
+            SegmentAddr::Temp(index) => {
+                return Err(Trap::InvalidSegment(format!(
+                    "temp {index} is out of range"
+                )))
+            }
+            SegmentAddr::Pointer(index) if index < 2 => (THIS + index) as i32,
+            SegmentAddr::Pointer(index) => {
+                return Err(Trap::InvalidSegment(format!(
+                    "pointer {index} is out of range"
+                )))
+            }
+            SegmentAddr::Local(index) => base(LCL) + index as i32,
+            SegmentAddr::Arg(index) => base(ARG) + index as i32,
+            SegmentAddr::This(index) => base(THIS) + index as i32,
+            SegmentAddr::That(index) => base(THAT) + index as i32,
+        };
+        Self::address(addr)
+    }
+
+    /// Pushes a frame and jumps to a function, which returns to `ret`.
+    fn call(&mut self, name: &str, args: u16, ret: usize) -> Result<(), Trap> {
+        let entry = self
+            .program
+            .function(name)
+            .ok_or_else(|| Trap::UndefinedFunction(name.to_string()))?;
+
+        self.push(ret as i16)?;
+        for pointer in [LCL, ARG, THIS, THAT] {
+            self.push(self.ram[pointer as usize])?;
+        }
+        let sp = self.ram[SP as usize];
+        self.ram[ARG as usize] = sp.wrapping_sub(FRAME_SIZE).wrapping_sub(args as i16);
+        self.ram[LCL as usize] = sp;
+        self.pc = entry;
+        Ok(())
+    }
+
+    fn ret(&mut self) -> Result<(), Trap> {
+        let frame = self.ram[LCL as usize] as i32;
+        let saved = |vm: &Self, offset: i32| Ok(vm.ram[Self::address(frame - offset)?]);
+        let ret = saved(self, FRAME_SIZE as i32)?;
+
+        let value = self.pop()?;
+        let arg = self.ram[ARG as usize];
+        self.ram[Self::address(arg as i32)?] = value;
+        self.ram[SP as usize] = arg.wrapping_add(1);
+        for (offset, pointer) in [THAT, THIS, ARG, LCL].into_iter().enumerate() {
+            self.ram[pointer as usize] = saved(self, offset as i32 + 1)?;
+        }
+        self.pc = ret as u16 as usize;
+        self.halted |= self.pc == HALT;
+        Ok(())
+    }
+
+    /// Calls a function of the program, or else the built-in OS function of
+    /// that name, returning where execution goes on.
+    fn call_function(&mut self, name: &str, args: u16, ret: usize) -> Result<usize, Trap> {
+        if self.program.function(name).is_some() {
+            self.call(name, args, ret)?;
+            return Ok(self.pc);
+        }
+
+        let sp = self.ram[SP as usize] as i32;
+        let first = Self::address(sp - args as i32)?;
+        let values = self.ram[first..first + args as usize].to_vec();
+        match os::call(self, name, &values)? {
+            Outcome::Return(value) => {
+                self.ram[SP as usize] = first as i16;
+                self.push(value)?;
+                Ok(ret)
+            }
+            // try again on the next step, until the keyboard provides the input
+            Outcome::Wait if self.depth == 0 => Ok(self.pc),
+            Outcome::Wait => Err(Trap::Os(format!(
+                "{name} can't wait for the keyboard when called by a built-in function"
+            ))),
+            Outcome::Enter(function) => {
+                self.ram[SP as usize] = first as i16;
+                self.call(function, 0, HALT)?;
+                Ok(self.pc)
+            }
+        }
+    }
+
+    /// Calls a function for a built-in function, running it to completion if
+    /// the program defines it.
+    pub(crate) fn invoke(&mut self, name: &str, args: &[i16]) -> Result<i16, Trap> {
+        if self.program.function(name).is_none() {
+            return match os::call(self, name, args)? {
+                Outcome::Return(value) => Ok(value),
+                _ => Err(Trap::Os(format!(
+                    "{name} can't be called by a built-in function"
+                ))),
+            };
+        }
+
+        let pc = self.pc;
+        for &arg in args {
+            self.push(arg)?;
+        }
+        self.call(name, args.len() as u16, RETURN_TO_BUILT_IN)?;
+        self.depth += 1;
+        let result = self.run_invoked();
+        self.depth -= 1;
+        self.pc = pc;
+        result
+    }
+
+    fn run_invoked(&mut self) -> Result<i16, Trap> {
+        while self.pc != RETURN_TO_BUILT_IN {
+            if self.halted {
+                return Ok(0);
+            }
+            self.step()?;
+        }
+        self.pop()
+    }
+
+    /// Stops the VM for good, as `Sys.halt` does.
+    pub(crate) fn halt(&mut self) {
+        self.halted = true;
+    }
+
+    fn binary(&mut self, op: impl Fn(i16, i16) -> i16) -> Result<(), Trap> {
+        let y = self.pop()?;
+        let x = self.pop()?;
+        self.push(op(x, y))
+    }
+
+    fn unary(&mut self, op: impl Fn(i16) -> i16) -> Result<(), Trap> {
+        let x = self.pop()?;
+        self.push(op(x))
+    }
+
+    /// The first command from `at` on that isn't a label. Labels only mark
+    /// places to jump to, so like the VM emulator, the VM steps over them.
+    fn past_labels(&self, mut at: usize) -> usize {
+        while let Some(Inst::Label(_)) = self.program.insts.get(at) {
+            at += 1;
+        }
+        at
+    }
+
+    /// Executes one command. Once halted, the VM stays put.
+    pub fn step(&mut self) -> Result<(), Trap> {
+        if self.halted {
+            return Ok(());
+        }
+        if self.boot {
+            self.boot = false;
+            self.pc = self.call_function("Sys.init", 0, HALT)?;
+            self.steps += 1;
+            return Ok(());
+        }
+
+        let Some(inst) = self.program.insts.get(self.pc) else {
+            return Err(Trap::EndOfProgram);
+        };
+        let mut next = self.pc + 1;
+        let truth = |holds: bool| -(holds as i16);
+
+        match inst.clone() {
+            Inst::Push(SegmentAddr::Constant(value)) => self.push(value as i16)?,
+            Inst::Push(segment) => {
+                let value = self.ram[self.segment_address(&segment)?];
+                self.push(value)?;
+            }
+            Inst::Pop(segment) => {
+                let addr = self.segment_address(&segment)?;
+                self.ram[addr] = self.pop()?;
+            }
+
+            Inst::Add => self.binary(i16::wrapping_add)?,
+            Inst::Sub => self.binary(i16::wrapping_sub)?,
+            Inst::Neg => self.unary(i16::wrapping_neg)?,
+            Inst::Eq => self.binary(|x, y| truth(x == y))?,
+            Inst::Gt => self.binary(|x, y| truth(x > y))?,
+            Inst::Lt => self.binary(|x, y| truth(x < y))?,
+            Inst::And => self.binary(|x, y| x & y)?,
+            Inst::Or => self.binary(|x, y| x | y)?,
+            Inst::Not => self.unary(|x| !x)?,
+
+            Inst::Label(_) => {}
+            Inst::Goto(_) => next = self.program.jump_target(self.pc).unwrap(),
+            Inst::IfGoto(_) => {
+                if self.pop()? != 0 {
+                    next = self.program.jump_target(self.pc).unwrap();
+                }
+            }
+
+            Inst::Function(_, locals) => {
+                for _ in 0..locals {
+                    self.push(0)?;
+                }
+            }
+            Inst::Call(name, args) => next = self.call_function(&name, args, next)?,
+            Inst::Return => {
+                self.ret()?;
+                next = self.pc;
+            }
+        }
+
+        self.pc = self.past_labels(next);
+        self.steps += 1;
+        Ok(())
+    }
+
+    /// Whether the next command is a `goto` to the label right before it, the
+    /// way VM programs end, or `Sys.halt` was called.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+            || matches!(self.program.insts.get(self.pc), Some(Inst::Goto(_)))
+                && self
+                    .program
+                    .jump_target(self.pc)
+                    .is_some_and(|target| self.past_labels(target) == self.pc)
+    }
+
+    fn stopped(&self) -> Option<Stop> {
+        if self.is_halted() {
+            Some(Stop::Halted)
+        } else if self.pc == self.program.len() {
+            Some(Stop::EndOfProgram)
+        } else {
+            None
+        }
+    }
+
+    /// Runs until the program halts or ends, or `max_steps` commands were
+    /// executed.
+    pub fn run(&mut self, max_steps: u64) -> Result<Stop, Trap> {
+        for _ in 0..max_steps {
+            if let Some(stop) = self.stopped() {
+                return Ok(stop);
+            }
+            self.step()?;
+        }
+        Ok(self.stopped().unwrap_or(Stop::StepLimit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vm(sources: &[(&str, &str)]) -> Vm {
+        Vm::new(Program::from_sources(sources.iter().copied()).unwrap())
+    }
+
+    #[test]
+    fn runs_stack_test() {
+        let mut vm = vm(&[(
+            "StackTest",
+            include_str!("../../7/StackArithmetic/StackTest/StackTest.vm"),
+        )]);
+        assert_eq!(vm.run(1000), Ok(Stop::EndOfProgram));
+        assert_eq!(vm.steps(), 38);
+        assert_eq!(vm.step(), Err(Trap::EndOfProgram));
+
+        assert_eq!(vm.ram()[..1], [266]);
+        assert_eq!(vm.ram()[256..266], [-1, 0, 0, 0, -1, 0, -1, 0, 0, -91]);
+    }
+
+    #[test]
+    fn calls_functions() {
+        let mut vm = vm(&[
+            (
+                "Main",
+                include_str!("../../8/FunctionCalls/FibonacciElement/Main.vm"),
+            ),
+            (
+                "Sys",
+                include_str!("../../8/FunctionCalls/FibonacciElement/Sys.vm"),
+            ),
+        ]);
+        vm.bootstrap().unwrap();
+        assert_eq!(vm.current_function(), Some("Sys.init"));
+        assert_eq!(vm.run(10_000), Ok(Stop::Halted));
+        // fibonacci(4) is left on Sys.init's stack
+        assert_eq!(vm.ram()[0], 262);
+        assert_eq!(vm.ram()[261], 3);
+    }
+
+    #[test]
+    fn keeps_statics_per_file() {
+        let mut vm = vm(&[
+            (
+                "Class1",
+                include_str!("../../8/FunctionCalls/StaticsTest/Class1.vm"),
+            ),
+            (
+                "Class2",
+                include_str!("../../8/FunctionCalls/StaticsTest/Class2.vm"),
+            ),
+            (
+                "Sys",
+                include_str!("../../8/FunctionCalls/StaticsTest/Sys.vm"),
+            ),
+        ]);
+        vm.ram_mut()[0] = 261;
+        assert_eq!(vm.run(100), Ok(Stop::Halted));
+        assert_eq!(vm.ram()[..1], [263]);
+        assert_eq!(vm.ram()[261..263], [-2, 8]);
+        // Class1's two statics, then Class2's
+        assert_eq!(vm.ram()[16..20], [6, 8, 23, 15]);
+    }
+
+    #[test]
+    fn steps_over_labels() {
+        let mut vm = vm(&[(
+            "Main",
+            "label START\npush constant 2\npop temp 0\nlabel LOOP\npush temp 0\n\
+             push constant 1\nsub\npop temp 0\npush temp 0\nif-goto LOOP\n\
+             label END\ngoto END\n",
+        )]);
+        assert_eq!(vm.pc, 1);
+        assert_eq!(vm.run(100), Ok(Stop::Halted));
+        // two commands, then two rounds of six
+        assert_eq!(vm.steps(), 14);
+        assert_eq!(vm.ram()[..1], [256]);
+    }
+
+    #[test]
+    fn traps_invalid_accesses() {
+        // programs are validated when loaded, but their commands are public
+        let mut program =
+            Program::from_sources([("Main", "push constant 1\npop pointer 1\ncall Foo.bar 0\n")])
+                .unwrap();
+        program.insts[1] = Inst::Pop(SegmentAddr::Pointer(2));
+        let mut vm = Vm::new(program);
+        vm.step().unwrap();
+        assert_eq!(
+            vm.step(),
+            Err(Trap::InvalidSegment("pointer 2 is out of range".into()))
+        );
+        vm.pc = 2;
+        assert_eq!(vm.step(), Err(Trap::UndefinedFunction("Foo.bar".into())));
+    }
+}